@@ -4,45 +4,84 @@
 //! by flattening every `storage.*.command` and
 //! `hooks.*.*.{command,notify}` into a direct field on this type.
 //! Commands consume `Account` and call the driver methods
-//! (`resolve_token`, `write_to_storage`,
+//! (`resolve_token`, `write_to_storage`, `execute_on_authorize_hook`,
 //! `execute_on_{issue,refresh}_{success,error}_hook`,
+//! `execute_on_invalid_grant_hook`, `check_refresh_token_expiry`,
 //! `redirection`) instead of walking the original config tree.
 
-#[cfg(feature = "notify")]
-use std::time::Duration;
 use std::{
     borrow::Cow,
     collections::HashMap,
+    fs,
     io::Write,
     net::TcpListener,
     path::PathBuf,
     process::{Command, Stdio},
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{Context, Result, anyhow, bail};
 #[cfg(feature = "notify")]
-use humantime::format_duration;
-use log::trace;
+use humantime::{format_duration, format_rfc3339_seconds};
+use log::{debug, trace};
 #[cfg(feature = "notify")]
 use notify_rust::Notification;
-use pimalaya_config::secret::Secret;
+use pimalaya_config::{command, secret::Secret};
 use pimalaya_stream::tls::Tls;
 use secrecy::ExposeSecret;
 use url::Url;
 
-use io_oauth::rfc6749::issue_access_token::{
-    Oauth20AccessTokenErrorParams, Oauth20AccessTokenSuccessParams,
+use io_oauth::{
+    rfc6749::issue_access_token::Oauth20AccessTokenErrorParams,
+    rfc8628::auth::Oauth20DeviceAuthSuccessParams,
 };
 
-use crate::config::{
-    AccountConfig, EndpointsConfig, GrantConfig, HookConfig, HookStatusConfig, HooksConfig,
-    NotifyConfig, PkceConfig, StorageConfig, StoragesConfig,
+use crate::{
+    config::{
+        AccountConfig, AuthorizationDetailsConfig, CibaConfig, ClientAuthConfig, DriftConfig,
+        EndpointsConfig, GrantConfig, HookConfig, HookStatusConfig, HooksConfig,
+        InvalidGrantConfig, NotifyConfig, Oauth1SignatureMethodConfig, PageConfig, PkceConfig,
+        ProfileConfig, RedirectionConfig, RequestObjectConfig, StorageConfig, StoragesConfig,
+    },
+    token::{lock::RefreshLock, provenance::Provenance, stored::StoredToken},
 };
 
+/// Slack before the real expiry at which a token is treated as
+/// expired, unless `refresh-before` says otherwise: a token about to
+/// lapse is refreshed rather than handed out and rejected
+/// mid-request.
+const DEFAULT_REFRESH_BEFORE: Duration = Duration::from_secs(60);
+
+/// Lifetime assumed for a token issued without `expires_in`, unless
+/// `default-expires-in` says otherwise, so a session refreshes
+/// roughly hourly instead of never.
+const DEFAULT_EXPIRES_IN: Duration = Duration::from_secs(3600);
+
+/// How long a failed refresh is not retried while the stored token is
+/// served stale, unless `refresh-cooldown` says otherwise.
+const DEFAULT_REFRESH_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// How long before the refresh token expires the
+/// on-refresh-token-expiring hook fires, unless
+/// `refresh-token-expiring-before` says otherwise.
+const DEFAULT_REFRESH_TOKEN_EXPIRING_BEFORE: Duration = Duration::from_secs(24 * 3600);
+
+/// Attempts of a token request in all, unless `retry.attempts` says
+/// otherwise.
+const DEFAULT_RETRY_ATTEMPTS: usize = 3;
+
+/// Delay before the first retry, unless `retry.delay` says otherwise.
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay between two attempts, unless `retry.max-delay` says
+/// otherwise.
+const DEFAULT_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
 /// Flat, command-ready view of one OAuth 2.0 account.
 #[derive(Debug)]
 pub struct Account {
+    /// Name of the account, the key of its `[accounts.<name>]` block.
+    pub name: String,
     /// OAuth 2.0 client identifier.
     pub client_id: String,
     /// Optional OAuth 2.0 client secret.
@@ -53,28 +92,103 @@ pub struct Account {
     /// Path to the client certificate deriving the assertion `x5t`
     /// thumbprint, recomputed at every mint.
     pub client_certificate: Option<PathBuf>,
+    /// How the client authenticates to the authorization server.
+    pub client_auth: ClientAuthConfig,
+    /// Resource owner username of the password grant.
+    pub username: Option<String>,
+    /// Resource owner password of the password grant.
+    pub password: Option<Secret>,
     /// OAuth 2.0 grant flow run by the auth commands.
     pub grant: GrantConfig,
+    /// Security profile the account must comply with.
+    pub profile: Option<ProfileConfig>,
     /// TLS provider used for the HTTPS connections.
     pub tls: Tls,
     /// OAuth 2.0 scopes requested for the access token.
     pub scopes: Vec<String>,
+    /// Whether a token granted fewer scopes than requested fails
+    /// the command.
+    pub require_all_scopes: bool,
     /// PKCE posture of the authorization code grant.
     pub pkce: PkceConfig,
+    /// How the authorization request parameters travel (RFC 9101).
+    pub request_object: RequestObjectConfig,
     /// Extra parameters forwarded verbatim to the authorization
     /// request query.
     pub extras: HashMap<String, String>,
+    /// Authentication context classes requested by default (RFC 9470).
+    pub acr_values: Vec<String>,
+    /// Rich authorization request details (RFC 9396).
+    pub authorization_details: Option<AuthorizationDetailsConfig>,
     /// Whether `token show` refreshes an expired token by itself.
     pub auto_refresh: bool,
-
+    /// How long before its expiry a token is treated as expired.
+    pub refresh_before: Duration,
+    /// Lifetime assumed for a token issued without `expires_in`.
+    pub default_expires_in: Duration,
+    /// Whether `token show` prints a token not yet expired when its
+    /// refresh fails.
+    pub stale_on_error: bool,
+    /// How long a failed refresh is not retried while stale tokens
+    /// are served.
+    pub refresh_cooldown: Duration,
+    /// What a refresh token rejected with `invalid_grant` leads to.
+    pub on_invalid_grant: InvalidGrantConfig,
+    /// What a token authorized under another configuration leads to.
+    pub on_drift: DriftConfig,
+    /// Lifetime of a refresh token issued without
+    /// `refresh_token_expires_in`, when known.
+    pub refresh_token_lifetime: Option<Duration>,
+    /// How long before the refresh token expires the
+    /// on-refresh-token-expiring hook fires.
+    pub refresh_token_expiring_before: Duration,
+
+    /// Issuer identifier of the authorization server (RFC 8414).
+    pub issuer: Option<String>,
+    /// Whether authorization responses must carry `iss` (RFC 9207).
+    pub authorization_response_iss: bool,
     /// Authorization endpoint of the authorization code grant.
     pub authorization_endpoint: Option<Url>,
     /// Device authorization endpoint of the device grant (RFC 8628).
     pub device_authorization_endpoint: Option<Url>,
+    /// Backchannel authentication endpoint of the CIBA grant.
+    pub backchannel_authentication_endpoint: Option<Url>,
+    /// Pushed authorization request endpoint (RFC 9126) of the
+    /// authorization code grant.
+    pub pushed_authorization_request_endpoint: Option<Url>,
     /// Token endpoint shared by grants and refreshes.
     pub token_endpoint: Option<Url>,
     /// Redirection endpoint registered with the provider.
     pub redirection_endpoint: Option<Url>,
+    /// Temporary credential request endpoint of the OAuth 1.0a grant.
+    pub request_token_endpoint: Option<Url>,
+    /// Token request endpoint of the OAuth 1.0a grant.
+    pub access_token_endpoint: Option<Url>,
+    /// Signature method of the OAuth 1.0a requests.
+    pub oauth1_signature_method: Oauth1SignatureMethodConfig,
+
+    /// Attempts of a token request in all, the first one included.
+    pub retry_attempts: usize,
+    /// Delay before the first retry of a token request.
+    pub retry_delay: Duration,
+    /// Longest delay between two attempts of a token request.
+    pub retry_max_delay: Duration,
+
+    /// Page the redirection listener answers once a token is issued.
+    pub redirection_success_page: Option<PageConfig>,
+    /// Page the redirection listener answers once the flow failed.
+    pub redirection_error_page: Option<PageConfig>,
+    /// Whether the redirection pages ask the browser to close the tab.
+    pub redirection_auto_close: bool,
+
+    /// Login hint of the CIBA backchannel authentication request.
+    pub ciba_login_hint: Option<String>,
+    /// Binding message of the CIBA backchannel authentication request.
+    pub ciba_binding_message: Option<String>,
+
+    /// Command opening the authorization and verification URIs, the
+    /// system default browser when unset.
+    pub browser_command: Option<Command>,
 
     /// Command printing the stored token JSON on its stdout.
     pub read_storage_command: Command,
@@ -82,8 +196,13 @@ pub struct Account {
     pub write_storage_command: Command,
 
     /// Token resolved from storage, memoized for the session.
-    pub token: Option<Oauth20AccessTokenSuccessParams>,
+    pub token: Option<StoredToken>,
+    /// Provenance stored with the tokens issued, pinned to the account
+    /// configuration while `auth get` overlays its flags on it.
+    pub provenance: Option<Provenance>,
 
+    /// Command hook fired when a flow needs the user to authorize.
+    pub on_authorize_hook_command: Option<Command>,
     /// Command hook fired when a token is successfully issued.
     pub on_issue_success_hook_command: Option<Command>,
     /// Command hook fired when issuing a token fails.
@@ -92,7 +211,14 @@ pub struct Account {
     pub on_refresh_success_hook_command: Option<Command>,
     /// Command hook fired when refreshing the token fails.
     pub on_refresh_error_hook_command: Option<Command>,
+    /// Command hook fired when the server rejects the refresh token.
+    pub on_invalid_grant_hook_command: Option<Command>,
+    /// Command hook fired when the refresh token is about to expire.
+    pub on_refresh_token_expiring_hook_command: Option<Command>,
 
+    /// Notification fired when a flow needs the user to authorize.
+    #[cfg(feature = "notify")]
+    pub on_authorize_hook_notify: Option<NotifyConfig>,
     /// Notification fired when a token is successfully issued.
     #[cfg(feature = "notify")]
     pub on_issue_success_hook_notify: Option<NotifyConfig>,
@@ -105,22 +231,51 @@ pub struct Account {
     /// Notification fired when refreshing the token fails.
     #[cfg(feature = "notify")]
     pub on_refresh_error_hook_notify: Option<NotifyConfig>,
+    /// Notification fired when the server rejects the refresh token.
+    #[cfg(feature = "notify")]
+    pub on_invalid_grant_hook_notify: Option<NotifyConfig>,
+    /// Notification fired when the refresh token is about to expire.
+    #[cfg(feature = "notify")]
+    pub on_refresh_token_expiring_hook_notify: Option<NotifyConfig>,
 }
 
-impl From<AccountConfig> for Account {
-    fn from(cfg: AccountConfig) -> Self {
+impl From<(String, AccountConfig)> for Account {
+    fn from((name, cfg): (String, AccountConfig)) -> Self {
         let AccountConfig {
             client_id,
             client_secret,
             client_key,
             client_certificate,
+            client_auth,
+            username,
+            password,
             grant,
+            profile,
+            issuer,
+            authorization_response_iss,
             endpoints,
+            redirection,
+            ciba,
+            oauth1,
+            retry,
+            browser,
             tls,
             scopes,
+            require_all_scopes,
             pkce,
+            request_object,
             extras,
+            acr_values,
+            authorization_details,
             auto_refresh,
+            refresh_before,
+            default_expires_in,
+            stale_on_error,
+            refresh_cooldown,
+            on_invalid_grant,
+            on_drift,
+            refresh_token_lifetime,
+            refresh_token_expiring_before,
             storage,
             hooks,
             ..
@@ -129,18 +284,36 @@ impl From<AccountConfig> for Account {
         let EndpointsConfig {
             authorization,
             device_authorization,
+            backchannel_authentication,
+            pushed_authorization_request,
             token,
-            redirection,
+            redirection: redirection_endpoint,
+            request_token: request_token_endpoint,
+            access_token: access_token_endpoint,
         } = endpoints;
 
+        let RedirectionConfig {
+            success_page,
+            error_page,
+            auto_close,
+        } = redirection;
+
+        let CibaConfig {
+            login_hint: ciba_login_hint,
+            binding_message: ciba_binding_message,
+        } = ciba;
+
         let StoragesConfig {
             read: StorageConfig { command: read_cmd },
             write: StorageConfig { command: write_cmd },
         } = storage;
 
         let HooksConfig {
+            on_authorize,
             on_issue,
             on_refresh,
+            on_invalid_grant: invalid_grant,
+            on_refresh_token_expiring: refresh_token_expiring,
         } = hooks;
 
         let HookStatusConfig {
@@ -152,6 +325,11 @@ impl From<AccountConfig> for Account {
             error: refresh_error,
         } = on_refresh;
 
+        let HookConfig {
+            command: on_authorize_hook_command,
+            #[cfg(feature = "notify")]
+                notify: on_authorize_hook_notify,
+        } = on_authorize;
         let HookConfig {
             command: on_issue_success_hook_command,
             #[cfg(feature = "notify")]
@@ -172,29 +350,79 @@ impl From<AccountConfig> for Account {
             #[cfg(feature = "notify")]
                 notify: on_refresh_error_hook_notify,
         } = refresh_error;
+        let HookConfig {
+            command: on_invalid_grant_hook_command,
+            #[cfg(feature = "notify")]
+                notify: on_invalid_grant_hook_notify,
+        } = invalid_grant;
+        let HookConfig {
+            command: on_refresh_token_expiring_hook_command,
+            #[cfg(feature = "notify")]
+                notify: on_refresh_token_expiring_hook_notify,
+        } = refresh_token_expiring;
 
         Self {
+            name,
             client_id,
             client_secret,
             client_key,
             client_certificate,
+            client_auth,
+            username,
+            password,
             grant,
+            profile,
             tls,
             scopes,
+            require_all_scopes,
             pkce,
+            request_object,
             extras,
+            acr_values,
+            authorization_details,
             auto_refresh,
+            refresh_before: refresh_before.unwrap_or(DEFAULT_REFRESH_BEFORE),
+            default_expires_in: default_expires_in.unwrap_or(DEFAULT_EXPIRES_IN),
+            stale_on_error,
+            refresh_cooldown: refresh_cooldown.unwrap_or(DEFAULT_REFRESH_COOLDOWN),
+            on_invalid_grant,
+            on_drift,
+            refresh_token_lifetime,
+            refresh_token_expiring_before: refresh_token_expiring_before
+                .unwrap_or(DEFAULT_REFRESH_TOKEN_EXPIRING_BEFORE),
+            issuer,
+            authorization_response_iss,
             authorization_endpoint: authorization,
             device_authorization_endpoint: device_authorization,
+            backchannel_authentication_endpoint: backchannel_authentication,
+            pushed_authorization_request_endpoint: pushed_authorization_request,
             token_endpoint: token,
-            redirection_endpoint: redirection,
+            redirection_endpoint,
+            request_token_endpoint,
+            access_token_endpoint,
+            oauth1_signature_method: oauth1.signature_method,
+            retry_attempts: retry.attempts.unwrap_or(DEFAULT_RETRY_ATTEMPTS).max(1),
+            retry_delay: retry.delay.unwrap_or(DEFAULT_RETRY_DELAY),
+            retry_max_delay: retry.max_delay.unwrap_or(DEFAULT_RETRY_MAX_DELAY),
+            redirection_success_page: success_page,
+            redirection_error_page: error_page,
+            redirection_auto_close: auto_close,
+            ciba_login_hint,
+            ciba_binding_message,
+            browser_command: browser.map(|browser| browser.command),
             read_storage_command: read_cmd,
             write_storage_command: write_cmd,
             token: None,
+            provenance: None,
+            on_authorize_hook_command,
             on_issue_success_hook_command,
             on_issue_error_hook_command,
             on_refresh_success_hook_command,
             on_refresh_error_hook_command,
+            on_invalid_grant_hook_command,
+            on_refresh_token_expiring_hook_command,
+            #[cfg(feature = "notify")]
+            on_authorize_hook_notify,
             #[cfg(feature = "notify")]
             on_issue_success_hook_notify,
             #[cfg(feature = "notify")]
@@ -203,11 +431,37 @@ impl From<AccountConfig> for Account {
             on_refresh_success_hook_notify,
             #[cfg(feature = "notify")]
             on_refresh_error_hook_notify,
+            #[cfg(feature = "notify")]
+            on_invalid_grant_hook_notify,
+            #[cfg(feature = "notify")]
+            on_refresh_token_expiring_hook_notify,
         }
     }
 }
 
 impl Account {
+    /// Opens `url` with `browser`, the account browser command when
+    /// none is given, or the system default browser when the account
+    /// sets none either.
+    ///
+    /// The browser is spawned, not waited for: a browser already
+    /// running may hand the URI over and exit, a fresh one would
+    /// block the flow until its window closes.
+    pub fn open_browser(&self, url: &str, browser: Option<&Command>) -> Result<()> {
+        let Some(cmd) = browser.or(self.browser_command.as_ref()) else {
+            return Ok(open::that(url)?);
+        };
+
+        browser_command(cmd, url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Spawn browser command")?;
+
+        Ok(())
+    }
+
     /// Resolve the redirection URI: returns the configured one when
     /// set, otherwise binds to `127.0.0.1:0` and returns the
     /// resulting `http://127.0.0.1:<port>` URL.
@@ -223,9 +477,45 @@ impl Account {
         Ok(Cow::Owned(url))
     }
 
+    /// Whether the requests to the authorization server carry a signed
+    /// client assertion in their form (`client-auth =
+    /// "private-key-jwt"`). The client credentials JWT grant sends its
+    /// own in its params, so it never gets a second one.
+    pub fn private_key_jwt(&self) -> bool {
+        self.client_auth == ClientAuthConfig::PrivateKeyJwt
+            && self.grant != GrantConfig::ClientCredentialsJwt
+    }
+
+    /// Serializes the authorization details (RFC 9396) into the JSON
+    /// array the `authorization_details` parameter carries, reading
+    /// the file again when they live in one.
+    pub fn authorization_details(&self) -> Result<Option<String>> {
+        let details = match &self.authorization_details {
+            None => return Ok(None),
+            Some(AuthorizationDetailsConfig::Inline(details)) => details.clone(),
+            Some(AuthorizationDetailsConfig::Path(path)) => {
+                let json = fs::read(path).with_context(|| {
+                    format!("Read authorization details from {}", path.display())
+                })?;
+                serde_json::from_slice(&json).with_context(|| {
+                    format!("Parse authorization details array from {}", path.display())
+                })?
+            }
+        };
+
+        if let Some(i) = details
+            .iter()
+            .position(|detail| detail.get("type").is_none())
+        {
+            bail!("Authorization details object #{i} is missing its type");
+        }
+
+        Ok(Some(serde_json::to_string(&details)?))
+    }
+
     /// Resolves the account token, reading it from storage on first
     /// use and memoizing it so the read storage command runs once.
-    pub fn resolve_token(&mut self) -> Result<Oauth20AccessTokenSuccessParams> {
+    pub fn resolve_token(&mut self) -> Result<StoredToken> {
         if let Some(token) = &self.token {
             return Ok(token.clone());
         }
@@ -235,9 +525,16 @@ impl Account {
         Ok(token)
     }
 
+    /// Reads the token from storage again, bypassing the memoized one,
+    /// for a caller that waited on another process writing it.
+    pub fn reload_token(&mut self) -> Result<StoredToken> {
+        self.token = None;
+        self.resolve_token()
+    }
+
     /// Reads the persisted token by running the read storage command
     /// and parsing its stdout as the token response JSON.
-    fn read_storage(&mut self) -> Result<Oauth20AccessTokenSuccessParams> {
+    fn read_storage(&mut self) -> Result<StoredToken> {
         let cmd = &mut self.read_storage_command;
 
         let output = cmd
@@ -254,7 +551,7 @@ impl Account {
             return Err(err.context("Read access token via command error"));
         }
 
-        let res = Oauth20AccessTokenSuccessParams::try_from(output.stdout.as_slice())
+        let res = StoredToken::try_from(output.stdout.as_slice())
             .context("Parse access token from command error")?;
 
         Ok(res)
@@ -262,13 +559,108 @@ impl Account {
 
     /// Persists the token by running the write storage command and
     /// piping the token response JSON to its stdin. The local issuance
-    /// time is stamped first and the token is cached in memory after.
-    pub fn write_to_storage(
-        &mut self,
-        mut res: Oauth20AccessTokenSuccessParams,
-    ) -> Result<Oauth20AccessTokenSuccessParams> {
-        res.issued_at = Some(now_secs());
+    /// time is stamped first, along with the provenance of a new
+    /// authorization and the refresh token expiry from
+    /// `refresh-token-lifetime` when the response tells none, and the
+    /// token is cached in memory after.
+    pub fn write_to_storage(&mut self, mut res: StoredToken) -> Result<StoredToken> {
+        let now = now_secs();
+        res.params.issued_at = Some(now);
+
+        if res.provenance.is_none() {
+            let provenance = self.provenance.clone();
+            res.provenance = Some(provenance.unwrap_or_else(|| Provenance::of(self)));
+        }
+
+        if res.refresh_token_expires_at.is_none()
+            && res.params.refresh_token.is_some()
+            && let Some(lifetime) = self.refresh_token_lifetime
+        {
+            res.refresh_token_expires_at = Some(now + lifetime.as_secs());
+        }
+
+        self.write_storage(res)
+    }
+
+    /// Compares the scopes granted to `token` with the requested
+    /// ones: a mismatch fails under `require-all-scopes`, before the
+    /// token is stored, and warns on stderr otherwise.
+    pub fn check_scopes(&self, token: &StoredToken) -> Result<()> {
+        let missing = token.missing_scopes(&self.scopes);
 
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let missing = missing.join(", ");
+
+        if self.require_all_scopes {
+            bail!(
+                "Access token granted without the requested scopes {missing}, \
+                 run `ortie auth get --account {}` and grant them all",
+                self.name,
+            );
+        }
+
+        eprintln!(
+            "Warning: the access token was granted without the requested scopes {missing}, \
+             commands needing them will fail."
+        );
+
+        Ok(())
+    }
+
+    /// Marks the stored token as needing a new authorization, its
+    /// refresh token rejected, leaving the rest of it untouched.
+    pub fn mark_reauth_required(&mut self) -> Result<()> {
+        let mut token = self.resolve_token()?;
+        token.reauth_required = true;
+        self.write_storage(token)?;
+        Ok(())
+    }
+
+    /// Fires the on-refresh-token-expiring hook when the refresh token
+    /// expires within `refresh-token-expiring-before`, once per refresh
+    /// token: the stored token remembers it fired. Never fails, a
+    /// missed warning not being worth failing the command over.
+    pub fn check_refresh_token_expiry(&mut self) {
+        let expiring = |account: &Self, token: &StoredToken| {
+            token.params.refresh_token.is_some()
+                && !token.refresh_token_expiring_notified
+                && token
+                    .refresh_token_remaining()
+                    .is_some_and(|remaining| remaining <= account.refresh_token_expiring_before)
+        };
+
+        match self.resolve_token() {
+            Ok(token) if expiring(self, &token) => (),
+            _ => return,
+        }
+
+        // NOTE: the token is read again under the refresh lock, so
+        // concurrent callers fire the hook once between them.
+        let res = RefreshLock::acquire(&self.name).and_then(|_lock| {
+            let mut token = self.reload_token()?;
+
+            if expiring(self, &token) {
+                debug!("execute refresh token expiring hook");
+                self.execute_on_refresh_token_expiring_hook(&token);
+
+                token.refresh_token_expiring_notified = true;
+                self.write_storage(token)?;
+            }
+
+            Ok(())
+        });
+
+        if let Err(err) = res {
+            debug!("check refresh token expiry: {err:#}");
+        }
+    }
+
+    /// Runs the write storage command with the token response JSON on
+    /// its stdin, then caches the token in memory.
+    fn write_storage(&mut self, res: StoredToken) -> Result<StoredToken> {
         let cmd = &mut self.write_storage_command;
         let json = String::try_from(&res)?.into_bytes();
 
@@ -310,13 +702,29 @@ impl Account {
         Ok(res)
     }
 
+    /// Fires the on-authorize hook with what the user has to open or
+    /// enter.
+    pub fn execute_on_authorize_hook(&mut self, params: &AuthorizeHookParams<'_>) {
+        #[cfg(feature = "notify")]
+        let notify = self.on_authorize_hook_notify.as_ref();
+        #[cfg(not(feature = "notify"))]
+        let notify = None;
+        execute_authorize_hook(self.on_authorize_hook_command.as_mut(), notify, params);
+    }
+
     /// Fires the on-issue success hook with the issued token.
-    pub fn execute_on_issue_success_hook(&mut self, res: &Oauth20AccessTokenSuccessParams) {
+    pub fn execute_on_issue_success_hook(&mut self, res: &StoredToken) {
         #[cfg(feature = "notify")]
         let notify = self.on_issue_success_hook_notify.as_ref();
         #[cfg(not(feature = "notify"))]
         let notify = None;
-        execute_success_hook(self.on_issue_success_hook_command.as_mut(), notify, res);
+        let missing = res.missing_scopes(&self.scopes);
+        execute_success_hook(
+            self.on_issue_success_hook_command.as_mut(),
+            notify,
+            res,
+            &missing,
+        );
     }
 
     /// Fires the on-issue error hook with the server error.
@@ -329,12 +737,18 @@ impl Account {
     }
 
     /// Fires the on-refresh success hook with the refreshed token.
-    pub fn execute_on_refresh_success_hook(&mut self, res: &Oauth20AccessTokenSuccessParams) {
+    pub fn execute_on_refresh_success_hook(&mut self, res: &StoredToken) {
         #[cfg(feature = "notify")]
         let notify = self.on_refresh_success_hook_notify.as_ref();
         #[cfg(not(feature = "notify"))]
         let notify = None;
-        execute_success_hook(self.on_refresh_success_hook_command.as_mut(), notify, res);
+        let missing = res.missing_scopes(&self.scopes);
+        execute_success_hook(
+            self.on_refresh_success_hook_command.as_mut(),
+            notify,
+            res,
+            &missing,
+        );
     }
 
     /// Fires the on-refresh error hook with the server error.
@@ -345,6 +759,115 @@ impl Account {
         let notify = None;
         execute_error_hook(self.on_refresh_error_hook_command.as_mut(), notify, res);
     }
+
+    /// Fires the on-refresh-token-expiring hook with the refresh token
+    /// expiry.
+    pub fn execute_on_refresh_token_expiring_hook(&mut self, res: &StoredToken) {
+        #[cfg(feature = "notify")]
+        let notify = self.on_refresh_token_expiring_hook_notify.as_ref();
+        #[cfg(not(feature = "notify"))]
+        let notify = None;
+        execute_expiring_hook(
+            self.on_refresh_token_expiring_hook_command.as_mut(),
+            notify,
+            res,
+        );
+    }
+
+    /// Fires the on-invalid-grant hook with the server error.
+    pub fn execute_on_invalid_grant_hook(&mut self, res: &Oauth20AccessTokenErrorParams) {
+        #[cfg(feature = "notify")]
+        let notify = self.on_invalid_grant_hook_notify.as_ref();
+        #[cfg(not(feature = "notify"))]
+        let notify = None;
+        execute_error_hook(self.on_invalid_grant_hook_command.as_mut(), notify, res);
+    }
+}
+
+/// What a flow asks the user for, exposed to the on-authorize hook.
+#[derive(Debug)]
+pub enum AuthorizeHookParams<'a> {
+    /// The authorization URI of the authorization code grant.
+    AuthorizationUri(&'a Url),
+    /// The device authorization of the device grant.
+    DeviceCode(&'a Oauth20DeviceAuthSuccessParams),
+}
+
+impl AuthorizeHookParams<'_> {
+    /// The hook environment variables: `AUTHORIZATION_URI` for the
+    /// authorization code grant; `USER_CODE`, `VERIFICATION_URI`,
+    /// `VERIFICATION_URI_COMPLETE` and `EXPIRES_IN` for the device
+    /// grant.
+    fn vars(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Self::AuthorizationUri(uri) => vec![("AUTHORIZATION_URI", uri.to_string())],
+            Self::DeviceCode(device) => {
+                let mut vars = vec![
+                    ("USER_CODE", device.user_code.clone()),
+                    ("VERIFICATION_URI", device.verification_uri.clone()),
+                    ("EXPIRES_IN", device.expires_in.to_string()),
+                ];
+
+                if let Some(uri) = &device.verification_uri_complete {
+                    vars.push(("VERIFICATION_URI_COMPLETE", uri.clone()));
+                }
+
+                vars
+            }
+        }
+    }
+}
+
+/// Builds the browser command opening `url`, from the configured one.
+///
+/// The URL rides as the `URL` environment variable. The shell shape
+/// expands it by itself, and gets `"$URL"` appended when its line
+/// never mentions it; the exec shape has its `$URL` arguments
+/// substituted, or the URL appended as the last argument.
+fn browser_command(cmd: &Command, url: &str) -> Command {
+    let shell = command::shell("");
+    let args: Vec<String> = cmd
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+
+    let shell_form = cmd.get_program() == shell.get_program()
+        && args.len() == 2
+        && shell
+            .get_args()
+            .next()
+            .is_some_and(|flag| flag == args[0].as_str());
+
+    let mut browser = Command::new(cmd.get_program());
+    browser.env("URL", url);
+
+    if shell_form {
+        let line = &args[1];
+        let reference = if cfg!(windows) { "%URL%" } else { "$URL" };
+
+        browser.arg(&args[0]);
+        if line.contains(reference) || line.contains("${URL}") {
+            browser.arg(line);
+        } else {
+            browser.arg(format!("{line} \"{reference}\""));
+        }
+
+        return browser;
+    }
+
+    let mut substituted = false;
+    for arg in args {
+        if arg.contains("$URL") {
+            substituted = true;
+        }
+        browser.arg(arg.replace("$URL", url));
+    }
+
+    if !substituted {
+        browser.arg(url);
+    }
+
+    browser
 }
 
 /// Current Unix epoch seconds, stamped as the local token issuance time.
@@ -355,15 +878,62 @@ fn now_secs() -> u64 {
         .unwrap_or(0)
 }
 
+/// Runs the on-authorize hook: the command with the request exposed
+/// as environment variables, then the notification.
+fn execute_authorize_hook(
+    cmd: Option<&mut Command>,
+    #[cfg_attr(not(feature = "notify"), allow(unused))] notify: Option<&NotifyConfig>,
+    params: &AuthorizeHookParams<'_>,
+) {
+    trace!("execute authorize hook: {params:?}");
+
+    let vars = params.vars();
+
+    if let Some(cmd) = cmd {
+        cmd.envs(vars.iter().map(|(key, val)| (key, val)));
+
+        if let Err(err) = execute_command_hook(cmd) {
+            log::debug!("execute command hook error: {err}");
+        }
+    }
+
+    #[cfg(feature = "notify")]
+    if let Some(config) = notify {
+        let get_env = |key: &str| -> Result<Option<Cow<str>>, ()> {
+            if key == "EXPIRES_IN"
+                && let AuthorizeHookParams::DeviceCode(device) = params
+            {
+                let exp = Duration::from_secs(device.expires_in as u64);
+                return Ok(Some(format_duration(exp).to_string().into()));
+            }
+
+            if let Some((_, val)) = vars.iter().find(|(name, _)| *name == key) {
+                return Ok(Some(val.clone().into()));
+            }
+
+            match std::env::var(key) {
+                Ok(val) => Ok(Some(val.into())),
+                Err(_) => Ok(None),
+            }
+        };
+
+        notify_with(config, get_env);
+    }
+}
+
 /// Runs a success hook: the command with the token exposed as
 /// environment variables, then the notification.
 fn execute_success_hook(
     cmd: Option<&mut Command>,
     #[cfg_attr(not(feature = "notify"), allow(unused))] notify: Option<&NotifyConfig>,
-    res: &Oauth20AccessTokenSuccessParams,
+    res: &StoredToken,
+    missing: &[String],
 ) {
     trace!("execute success hook: {res:?}");
 
+    let expires_at = res.expires_at;
+    let res = &res.params;
+
     if let Some(cmd) = cmd {
         cmd.env("ACCESS_TOKEN", res.access_token.expose_secret());
         cmd.env("TOKEN_TYPE", &res.token_type);
@@ -372,6 +942,10 @@ fn execute_success_hook(
             cmd.env("EXPIRES_IN", exp.to_string());
         }
 
+        if let Some(exp) = expires_at {
+            cmd.env("EXPIRES_AT", exp.to_string());
+        }
+
         if let Some(token) = &res.refresh_token {
             cmd.env("REFRESH_TOKEN", token.expose_secret());
         }
@@ -380,6 +954,10 @@ fn execute_success_hook(
             cmd.env("SCOPE", scope);
         }
 
+        if !missing.is_empty() {
+            cmd.env("MISSING_SCOPES", missing.join(" "));
+        }
+
         if let Err(err) = execute_command_hook(cmd) {
             log::debug!("execute command hook error: {err}");
         }
@@ -398,11 +976,76 @@ fn execute_success_hook(
                 };
             }
 
+            if key == "EXPIRES_AT" {
+                return match expires_at {
+                    None => Ok(Some("unknown".into())),
+                    Some(exp) => {
+                        let exp = UNIX_EPOCH + Duration::from_secs(exp);
+                        Ok(Some(format_rfc3339_seconds(exp).to_string().into()))
+                    }
+                };
+            }
+
             if key == "TOKEN_TYPE" {
                 let t = (&res.token_type).into();
                 return Ok(Some(t));
             }
 
+            if key == "MISSING_SCOPES" {
+                return match missing {
+                    [] => Ok(Some("none".into())),
+                    missing => Ok(Some(missing.join(" ").into())),
+                };
+            }
+
+            match std::env::var(key) {
+                Ok(val) => Ok(Some(val.into())),
+                Err(_) => Ok(None),
+            }
+        };
+
+        notify_with(config, get_env);
+    }
+}
+
+/// Runs the refresh token expiring hook: the command with the refresh
+/// token expiry exposed as environment variables, then the
+/// notification.
+fn execute_expiring_hook(
+    cmd: Option<&mut Command>,
+    #[cfg_attr(not(feature = "notify"), allow(unused))] notify: Option<&NotifyConfig>,
+    res: &StoredToken,
+) {
+    let (Some(expires_at), Some(remaining)) =
+        (res.refresh_token_expires_at, res.refresh_token_remaining())
+    else {
+        return;
+    };
+
+    trace!("execute expiring hook: {expires_at}");
+
+    if let Some(cmd) = cmd {
+        cmd.env("REFRESH_TOKEN_EXPIRES_AT", expires_at.to_string());
+        cmd.env("REFRESH_TOKEN_EXPIRES_IN", remaining.as_secs().to_string());
+
+        if let Err(err) = execute_command_hook(cmd) {
+            log::debug!("execute command hook error: {err}");
+        }
+    }
+
+    #[cfg(feature = "notify")]
+    if let Some(config) = notify {
+        let get_env = |key: &str| -> Result<Option<Cow<str>>, ()> {
+            if key == "REFRESH_TOKEN_EXPIRES_AT" {
+                let exp = UNIX_EPOCH + Duration::from_secs(expires_at);
+                return Ok(Some(format_rfc3339_seconds(exp).to_string().into()));
+            }
+
+            if key == "REFRESH_TOKEN_EXPIRES_IN" {
+                let exp = Duration::from_secs(remaining.as_secs());
+                return Ok(Some(format_duration(exp).to_string().into()));
+            }
+
             match std::env::var(key) {
                 Ok(val) => Ok(Some(val.into())),
                 Err(_) => Ok(None),
@@ -510,3 +1153,40 @@ where
         log::debug!("execute notify hook error: {err}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/auth?a=1&b=2";
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn exec_browser_substitutes_or_appends_the_url() {
+        let cmd = browser_command(Command::new("firefox").args(["-P", "work", "$URL"]), URL);
+        assert_eq!(args(&cmd), ["-P", "work", URL]);
+
+        let cmd = browser_command(Command::new("firefox").arg("--private-window"), URL);
+        assert_eq!(args(&cmd), ["--private-window", URL]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_browser_expands_the_url_variable() {
+        let cmd = browser_command(&command::shell("firefox -P work \"$URL\""), URL);
+        assert_eq!(args(&cmd), ["-c", "firefox -P work \"$URL\""]);
+
+        let cmd = browser_command(&command::shell("firefox -P work"), URL);
+        assert_eq!(args(&cmd), ["-c", "firefox -P work \"$URL\""]);
+
+        let url = cmd
+            .get_envs()
+            .find_map(|(key, value)| (key == "URL").then_some(value));
+        assert_eq!(url.flatten().and_then(|url| url.to_str()), Some(URL));
+    }
+}
@@ -1,7 +1,17 @@
 //! `auth` subcommand tree: obtain OAuth 2.0 access tokens by running
 //! the grant configured on the account.
 
+mod ciba;
+mod events;
 pub mod get;
+#[cfg(unix)]
+mod handler;
+pub mod oauth1;
+mod par;
+mod password;
+mod qr;
+mod redirect;
+mod request_object;
 pub mod resume;
 
 use std::path::PathBuf;
@@ -17,11 +27,12 @@ use crate::{
 
 /// Get a fresh access token by running the account's OAuth grant.
 ///
-/// Start an authorization-code, device or client-credentials grant,
-/// or resume one with a redirected URI or device code.
+/// Start an authorization-code, device, CIBA, client-credentials or
+/// OAuth 1.0a grant, or resume one with a redirected URI, device code or CIBA
+/// auth request id.
 #[derive(Subcommand, Debug)]
 pub enum AuthCommand {
-    Get(AuthGetCommand),
+    Get(Box<AuthGetCommand>),
     #[command(visible_alias = "continue")]
     Resume(AuthResumeCommand),
 }
@@ -39,6 +50,9 @@ impl AuthCommand {
                 let mut account = take_account(printer, config_paths, account_name)?;
                 cmd.execute(printer, &mut account)
             }
+            // NOTE: the scheme handler only relays the URI to the waiting
+            // auth get, which owns the account: no config is needed.
+            Self::Resume(cmd) if cmd.from_handler => cmd.hand_over(),
             Self::Resume(cmd) => {
                 let mut account = take_account(printer, config_paths, account_name)?;
                 cmd.execute(printer, &mut account)
@@ -0,0 +1,132 @@
+//! Client Initiated Backchannel Authentication (OpenID CIBA), poll
+//! mode.
+//!
+//! The client asks the backchannel authentication endpoint to reach
+//! the user identified by a hint on their own device, then polls the
+//! token endpoint with the `auth_req_id` it got back, the way the
+//! device grant polls with its device code. io-oauth knows neither
+//! request, so both go through [`http::post_form`]. The token response
+//! is parsed like the other grants, keeping the members the io-oauth
+//! params drop.
+//!
+//! Refs: <https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html>
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use url::Url;
+
+use io_oauth::rfc6749::issue_access_token::Oauth20AccessTokenErrorParams;
+
+use crate::{account::Account, http, token::stored::StoredToken};
+
+/// The `grant_type` of the CIBA token request.
+const GRANT_TYPE: &str = "urn:openid:params:grant-type:ciba";
+
+/// Polling interval when the server sends none (CIBA section 7.3).
+pub const DEFAULT_INTERVAL: usize = 5;
+
+/// The successful backchannel authentication response.
+///
+/// Refs: <https://openid.net/specs/openid-client-initiated-backchannel-authentication-core-1_0.html#rfc.section.7.3>
+#[derive(Clone, Debug, Deserialize)]
+pub struct CibaAuthSuccessParams {
+    /// Identifier of the authentication request, polled for.
+    pub auth_req_id: String,
+    /// Lifetime of the request, in seconds.
+    pub expires_in: usize,
+    /// Minimum number of seconds between two polls.
+    #[serde(default = "default_interval")]
+    pub interval: usize,
+}
+
+fn default_interval() -> usize {
+    DEFAULT_INTERVAL
+}
+
+/// The backchannel authentication response, success or error.
+pub type CibaAuthResponse = Result<CibaAuthSuccessParams, Oauth20AccessTokenErrorParams>;
+
+/// Sends the backchannel authentication request: the account scopes,
+/// `ciba.login-hint`, `ciba.binding-message` and authorization
+/// details, then the extras, an extra of the same name (`auth get
+/// --login-hint` included) winning over the configured value.
+pub fn request_auth(account: &Account, endpoint: &Url) -> Result<CibaAuthResponse> {
+    let scope = account.scopes.join(" ");
+    let authorization_details = account.authorization_details()?;
+
+    let mut form = vec![("client_id", account.client_id.as_str())];
+
+    if !scope.is_empty() {
+        form.push(("scope", scope.as_str()));
+    }
+
+    if let Some(hint) = &account.ciba_login_hint {
+        form.push(("login_hint", hint.as_str()));
+    }
+
+    if let Some(message) = &account.ciba_binding_message {
+        form.push(("binding_message", message.as_str()));
+    }
+
+    if let Some(details) = &authorization_details {
+        form.push(("authorization_details", details.as_str()));
+    }
+
+    for (key, value) in &account.extras {
+        form.retain(|(name, _)| name != key);
+        form.push((key.as_str(), value.as_str()));
+    }
+
+    let res = http::post_form(account, endpoint, form)?;
+    let body = res.body.as_slice();
+
+    if res.status.is_success() {
+        let params = CibaAuthSuccessParams::try_from(body)
+            .context("Parse backchannel authentication response")?;
+        Ok(Ok(params))
+    } else {
+        let err = Oauth20AccessTokenErrorParams::try_from(body)
+            .context("Parse backchannel authentication error response")?;
+        Ok(Err(err))
+    }
+}
+
+/// Polls the token endpoint once for the token of `auth_req_id`.
+pub fn request_access_token(
+    account: &Account,
+    endpoint: &Url,
+    auth_req_id: &str,
+) -> Result<Result<StoredToken, Oauth20AccessTokenErrorParams>> {
+    let form = [
+        ("grant_type", GRANT_TYPE),
+        ("auth_req_id", auth_req_id),
+        ("client_id", account.client_id.as_str()),
+    ];
+
+    http::parse_token_response(http::post_form(account, endpoint, form)?)
+}
+
+/// Parses the success params from JSON bytes.
+impl TryFrom<&[u8]> for CibaAuthSuccessParams {
+    type Error = serde_json::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        serde_json::from_slice(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_interval_defaults_to_five_seconds() {
+        let params =
+            CibaAuthSuccessParams::try_from(&br#"{"auth_req_id":"r","expires_in":120}"#[..])
+                .unwrap();
+
+        assert_eq!(params.auth_req_id, "r");
+        assert_eq!(params.expires_in, 120);
+        assert_eq!(params.interval, DEFAULT_INTERVAL);
+    }
+}
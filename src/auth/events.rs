@@ -0,0 +1,134 @@
+//! Event protocol of `auth get --wait --json`.
+//!
+//! A script driving a whole login through one process reads stdout
+//! line by line: each line is one JSON object whose `event` field
+//! tells what happened, from the request to open down to the issued
+//! token or the error ending the flow. The events carry what the
+//! human output shows, never the tokens themselves.
+
+use std::io::{Write, stdout};
+
+use log::debug;
+use serde::Serialize;
+
+/// One event of the protocol, serialized as a line of JSON.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The authorization request to open in a browser, with what a
+    /// later `auth resume` needs.
+    AuthorizationRequest {
+        authorization_uri: &'a str,
+        state: &'a str,
+        pkce_code_verifier: Option<&'a str>,
+        redirect_uri: &'a str,
+    },
+    /// The OAuth 1.0a authorization request to open in a browser,
+    /// carrying the temporary credentials.
+    Oauth1AuthorizationRequest {
+        authorization_uri: &'a str,
+        redirect_uri: &'a str,
+    },
+    /// The device authorization response: the code to enter and
+    /// where.
+    DeviceCode {
+        user_code: &'a str,
+        verification_uri: &'a str,
+        verification_uri_complete: Option<&'a str>,
+        expires_in: usize,
+        interval: usize,
+    },
+    /// The backchannel authentication request was accepted: the user
+    /// approves it on their own device.
+    BackchannelAuthentication {
+        binding_message: Option<&'a str>,
+        expires_in: usize,
+        interval: usize,
+    },
+    /// A poll of the token endpoint is about to be sent.
+    Polling { attempt: usize, interval: u64 },
+    /// The server asked to poll less often.
+    SlowDown { interval: u64 },
+    /// The token was issued and stored.
+    Issued {
+        token_type: &'a str,
+        expires_in: Option<usize>,
+        scope: Option<&'a str>,
+    },
+    /// The flow failed; the process exits non-zero right after.
+    Error {
+        error: String,
+        error_description: Option<String>,
+    },
+}
+
+/// Where the events go: stdout when the protocol is on, nowhere
+/// otherwise, so the flows emit them unconditionally.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Events {
+    enabled: bool,
+}
+
+impl Events {
+    /// The sink of a flow, emitting only when `enabled`.
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    /// Whether the events replace the regular output.
+    pub fn is_enabled(self) -> bool {
+        self.enabled
+    }
+
+    /// Writes `event` as one line of JSON, flushed at once so the
+    /// reader sees it while the flow goes on.
+    pub fn emit(self, event: &Event<'_>) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut stdout = stdout().lock();
+
+        let result = serde_json::to_writer(&mut stdout, event)
+            .map_err(Into::into)
+            .and_then(|()| writeln!(stdout))
+            .and_then(|()| stdout.flush());
+
+        if let Err(err) = result {
+            debug!("emit auth event error: {err}");
+        }
+    }
+
+    /// Emits the error ending the flow: its message, and its causes as
+    /// the description.
+    pub fn emit_error(self, err: &anyhow::Error) {
+        let causes: Vec<String> = err.chain().skip(1).map(ToString::to_string).collect();
+
+        self.emit(&Event::Error {
+            error: err.to_string(),
+            error_description: (!causes.is_empty()).then(|| causes.join(": ")),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_tagged_objects() {
+        let json = serde_json::to_string(&Event::SlowDown { interval: 10 }).unwrap();
+        assert_eq!(json, r#"{"event":"slow_down","interval":10}"#);
+
+        let json = serde_json::to_string(&Event::Issued {
+            token_type: "Bearer",
+            expires_in: Some(3600),
+            scope: None,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            r#"{"event":"issued","token_type":"Bearer","expires_in":3600,"scope":null}"#
+        );
+    }
+}
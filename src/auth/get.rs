@@ -2,10 +2,12 @@
 
 use std::{
     borrow::Cow,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt, fs,
-    io::{IsTerminal, stdout},
-    time::Duration,
+    io::{IsTerminal, Write, stdout},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
 };
 
 use anyhow::{Context, Result, anyhow, bail};
@@ -16,25 +18,25 @@ use base64::{
 use clap::Parser;
 use humantime::format_duration;
 use log::debug;
-use pimalaya_cli::printer::{Message, Printer};
-use secrecy::ExposeSecret;
+use pimalaya_cli::{
+    clap::parsers::path_parser,
+    printer::{Message, Printer},
+};
+use secrecy::{ExposeSecret, SecretString};
 use serde::{
     Deserialize, Serialize, Serializer,
     de::value::{Error, StringDeserializer},
 };
 use url::{Host, Url};
 
-use pimalaya_config::secret::Secret;
+use pimalaya_config::{command, secret::Secret};
 
 use io_oauth::{
-    client::{Oauth20ClientStd, Oauth20ClientStdError, await_redirect},
+    client::{Oauth20ClientStd, Oauth20ClientStdError},
     rfc6749::{
         auth_request::Oauth20AuthRequestParams,
         client_credentials::Oauth20ClientCredentialsRequestParams,
-        issue_access_token::{
-            Oauth20AccessTokenErrorCode, Oauth20AccessTokenErrorParams,
-            Oauth20AccessTokenSuccessParams,
-        },
+        issue_access_token::{Oauth20AccessTokenErrorCode, Oauth20AccessTokenErrorParams},
         state::Oauth20State,
     },
     rfc7523::{
@@ -44,38 +46,247 @@ use io_oauth::{
     rfc7636::pkce::{
         Oauth20PkceCodeChallenge, Oauth20PkceCodeChallengeMethod, Oauth20PkceCodeVerifier,
     },
-    rfc8628::auth::{Oauth20DeviceAuthRequestParams, Oauth20DeviceAuthSuccessParams},
+    rfc8628::{
+        auth::{Oauth20DeviceAuthResponse, Oauth20DeviceAuthSuccessParams},
+        token::Oauth20DeviceAccessTokenRequestParams,
+    },
 };
 
 use crate::{
-    account::Account,
-    auth::resume::AuthResumeCommand,
-    config::{GrantConfig, PkceConfig},
+    account::{Account, AuthorizeHookParams},
+    auth::{
+        ciba::{self, CibaAuthSuccessParams},
+        events::{Event, Events},
+        oauth1, par, password, qr, redirect, request_object,
+        resume::{AuthResumeCommand, uri_parser},
+    },
+    config::{GrantConfig, PkceConfig, RequestObjectConfig},
+    http::{self, Credential},
+    profile,
+    token::{lock::RefreshLock, provenance::Provenance, stored::StoredToken},
 };
 
 /// Initiate a new OAuth 2.0 grant from scratch.
 ///
 /// Runs the grant configured on the account: `authorization-code`,
-/// `device`, `client-credentials` or `client-credentials-jwt`.
-/// Interactive shells complete the flow; non-interactive and `--json`
-/// hand off to `auth resume`. The client credentials kinds complete
-/// headlessly in one shot.
-#[derive(Debug, Parser)]
-pub struct AuthGetCommand;
+/// `device`, `ciba`, `password`, `client-credentials` or
+/// `client-credentials-jwt`. Interactive shells complete the flow;
+/// non-interactive and `--json` hand off to `auth resume`. The
+/// password and client credentials kinds complete headlessly in one
+/// shot.
+#[derive(Debug, Default, Parser)]
+pub struct AuthGetCommand {
+    /// Open the authorization URI with this shell command.
+    ///
+    /// Overrides the account `browser.command`. `$URL` stands for
+    /// the URI to open, appended when the command never mentions it.
+    #[arg(long, value_name = "COMMAND", conflicts_with = "no_browser")]
+    pub browser: Option<String>,
+
+    /// Print the authorization URI without opening any browser.
+    #[arg(long)]
+    pub no_browser: bool,
+
+    /// Render the URI to open as a QR code.
+    ///
+    /// The authorization URI, or the device verification URI. Rendered
+    /// by default when stdout is a terminal.
+    #[arg(long, overrides_with = "no_qr")]
+    pub qr: bool,
+
+    /// Never render the URI to open as a QR code.
+    #[arg(long, overrides_with = "qr")]
+    pub no_qr: bool,
+
+    /// Export the QR code of the URI to open as an image.
+    ///
+    /// PNG or SVG, after the file extension, for kiosk setups
+    /// displaying it away from the terminal.
+    #[arg(long, value_name = "PATH", value_parser = path_parser)]
+    pub qr_export: Option<PathBuf>,
+
+    /// Request this scope instead of the account ones.
+    ///
+    /// Repeatable; a value holding spaces counts as several scopes.
+    #[arg(long = "scope", value_name = "SCOPE")]
+    pub scopes: Vec<String>,
+
+    /// Hint the server about the identity to sign in with.
+    ///
+    /// Sets the `login_hint` authorization parameter, overriding the
+    /// account extras.
+    #[arg(long, value_name = "HINT")]
+    pub login_hint: Option<String>,
+
+    /// Ask the server how to prompt the user.
+    ///
+    /// Sets the `prompt` authorization parameter (`consent`,
+    /// `select_account`, `login`, `none`), overriding the account
+    /// extras.
+    #[arg(long, value_name = "PROMPT")]
+    pub prompt: Option<String>,
+
+    /// Ask for this authentication context class (RFC 9470).
+    ///
+    /// Repeatable, most preferred first; a value holding spaces counts
+    /// as several classes. Sets the `acr_values` authorization
+    /// parameter, overriding the account `acr-values` and extras.
+    #[arg(long = "acr-values", value_name = "ACR")]
+    pub acr_values: Vec<String>,
+
+    /// Ask the user to authenticate again past this age, in seconds.
+    ///
+    /// Sets the `max_age` authorization parameter (RFC 9470),
+    /// overriding the account extras; 0 forces a fresh login.
+    #[arg(long, value_name = "SECONDS")]
+    pub max_age: Option<u64>,
+
+    /// Forward this extra authorization parameter.
+    ///
+    /// Repeatable, overriding the account extras of the same key.
+    #[arg(long = "extra", value_name = "KEY=VALUE", value_parser = extra_parser)]
+    pub extras: Vec<(String, String)>,
+
+    /// Redirect to this URI instead of the account one.
+    ///
+    /// Overrides `endpoints.redirection`; it must be registered with
+    /// the provider as well.
+    #[arg(long, value_name = "URI", value_parser = uri_parser)]
+    pub redirect_uri: Option<Url>,
+
+    /// Keep waiting for the flow to complete outside of a terminal.
+    ///
+    /// Non-interactive and `--json` runs otherwise print the request
+    /// and hand off to `auth resume`. With `--json`, the progress
+    /// streams on stdout as newline-delimited JSON events.
+    #[arg(long)]
+    pub wait: bool,
+}
+
+/// The account settings `auth get` overlaid for one run, put back once
+/// it ended so a REPL session keeps its own.
+struct Overlaid {
+    scopes: Vec<String>,
+    extras: HashMap<String, String>,
+    redirection_endpoint: Option<Url>,
+    provenance: Option<Provenance>,
+}
+
+impl Overlaid {
+    fn restore(self, account: &mut Account) {
+        account.scopes = self.scopes;
+        account.extras = self.extras;
+        account.redirection_endpoint = self.redirection_endpoint;
+        account.provenance = self.provenance;
+    }
+}
 
 impl AuthGetCommand {
     /// Runs the grant configured on the account and completes it into
     /// a stored access token (interactive shells chain into resume).
     pub fn execute(self, printer: &mut impl Printer, account: &mut Account) -> Result<()> {
+        let events = Events::new(self.wait && printer.is_json());
+
+        // NOTE: the flags shape this one authorization, not the
+        // configuration the token answers for, so its provenance is
+        // taken before they overlay the account.
+        let provenance = Provenance::of(account);
+        let overlaid = self.overlay(account);
+        account.provenance = Some(provenance);
+
+        // NOTE: `--redirect-uri` may swap the checked redirection, so
+        // the profile is checked again once the flags are overlaid.
+        let result = profile::check(account).and_then(|()| self.run(printer, account, events));
+        overlaid.restore(account);
+
+        if let Err(err) = &result {
+            events.emit_error(err);
+        }
+
+        result
+    }
+
+    /// Overlays the authorization parameter flags onto the account,
+    /// returning the settings they replaced.
+    fn overlay(&self, account: &mut Account) -> Overlaid {
+        let overlaid = Overlaid {
+            scopes: account.scopes.clone(),
+            extras: account.extras.clone(),
+            redirection_endpoint: account.redirection_endpoint.clone(),
+            provenance: account.provenance.clone(),
+        };
+
+        if !self.scopes.is_empty() {
+            account.scopes = self
+                .scopes
+                .iter()
+                .flat_map(|scope| scope.split_whitespace())
+                .map(ToOwned::to_owned)
+                .collect();
+        }
+
+        // NOTE: the account default context classes are the least
+        // specific setting: any extra of the same key wins over them.
+        if !account.acr_values.is_empty() && !account.extras.contains_key("acr_values") {
+            let acr_values = account.acr_values.join(" ");
+            account.extras.insert("acr_values".into(), acr_values);
+        }
+
+        account.extras.extend(self.extras.iter().cloned());
+
+        // NOTE: the dedicated flags are the more specific ones, so they
+        // win over an `--extra` of the same key.
+        if let Some(hint) = &self.login_hint {
+            account.extras.insert("login_hint".into(), hint.clone());
+        }
+
+        if let Some(prompt) = &self.prompt {
+            account.extras.insert("prompt".into(), prompt.clone());
+        }
+
+        if !self.acr_values.is_empty() {
+            let acr_values = self
+                .acr_values
+                .iter()
+                .flat_map(|acr| acr.split_whitespace())
+                .collect::<Vec<_>>()
+                .join(" ");
+            account.extras.insert("acr_values".into(), acr_values);
+        }
+
+        if let Some(max_age) = self.max_age {
+            account.extras.insert("max_age".into(), max_age.to_string());
+        }
+
+        if let Some(uri) = &self.redirect_uri {
+            account.redirection_endpoint = Some(uri.clone());
+        }
+
+        overlaid
+    }
+
+    fn run(&self, printer: &mut impl Printer, account: &mut Account, events: Events) -> Result<()> {
         if account.grant == GrantConfig::Device {
-            return execute_device(printer, account);
+            return execute_device(printer, account, self, events);
+        }
+
+        if account.grant == GrantConfig::Ciba {
+            return execute_ciba(printer, account, self, events);
         }
 
         if account.grant.is_client_credentials() {
-            return execute_client_credentials(printer, account);
+            return execute_client_credentials(printer, account, events);
+        }
+
+        if account.grant == GrantConfig::Password {
+            return execute_password(printer, account, events);
         }
 
-        let Some(authorization_endpoint) = &account.authorization_endpoint else {
+        if account.grant == GrantConfig::Oauth1 {
+            return execute_oauth1(printer, account, self, events);
+        }
+
+        let Some(authorization_endpoint) = account.authorization_endpoint.clone() else {
             bail!("Missing endpoints.authorization in the account config");
         };
 
@@ -96,21 +307,44 @@ impl AuthGetCommand {
             PkceConfig::Off => None,
         };
 
-        let redirect_uri = account.redirection()?;
+        let redirect_uri = account.redirection()?.into_owned();
+        let authorization_details = account.authorization_details()?;
+
+        let mut extras: BTreeMap<Cow<'_, str>, Cow<'_, str>> = account
+            .extras
+            .iter()
+            .map(|(key, value)| (key.as_str().into(), value.as_str().into()))
+            .collect();
+
+        // NOTE: an extra of the same key wins, as it does over the
+        // other account settings.
+        if let Some(details) = &authorization_details {
+            extras
+                .entry("authorization_details".into())
+                .or_insert(details.as_str().into());
+        }
 
-        let auth_uri = Oauth20AuthRequestParams {
+        let params = Oauth20AuthRequestParams {
             client_id: account.client_id.as_str().into(),
             redirect_uri: Some(Cow::from(redirect_uri.as_str())),
             scope: BTreeSet::from_iter(account.scopes.iter().map(Into::into)),
             state: Some(Cow::Borrowed(&state)),
             pkce_code_challenge: pkce_code_challenge.as_ref().map(Cow::Borrowed),
-            extras: account
-                .extras
-                .iter()
-                .map(|(key, value)| (key.as_str().into(), value.as_str().into()))
-                .collect(),
-        }
-        .build_url(authorization_endpoint);
+            extras,
+        };
+
+        let auth_uri = match (
+            &account.pushed_authorization_request_endpoint,
+            account.request_object,
+        ) {
+            (Some(endpoint), _) => {
+                par::build_url(account, &params, &authorization_endpoint, endpoint)?
+            }
+            (None, RequestObjectConfig::Off) => params.build_url(&authorization_endpoint),
+            (None, RequestObjectConfig::Sign) => {
+                request_object::build_url(account, &params, &authorization_endpoint)?
+            }
+        };
 
         let authorization_uri = AuthorizationUri {
             authorization_uri: &auth_uri,
@@ -119,30 +353,54 @@ impl AuthGetCommand {
                 .as_ref()
                 .map(|challenge| &challenge.verifier),
             interactive,
+            qr: self.qr(auth_uri.as_str())?,
         };
 
+        debug!("execute authorize hook");
+        account.execute_on_authorize_hook(&AuthorizeHookParams::AuthorizationUri(&auth_uri));
+
         // NOTE: non-interactive or JSON: print (or serialize) the request
-        // and hand off to a manual `auth resume`. JSON stays a clean
-        // structured object carrying the state and verifier, so only
-        // the human output appends the ready-to-run command.
+        // and hand off to a manual `auth resume`, unless asked to wait.
+        // JSON stays a clean structured object carrying the state and
+        // verifier, so only the human output appends the ready-to-run
+        // command.
         if printer.is_json() || !interactive {
-            printer.out(authorization_uri)?;
-
-            if !printer.is_json() {
-                println!();
-                print_manual_resume(&state, pkce_code_challenge.as_ref().map(|c| &c.verifier));
+            if events.is_enabled() {
+                let verifier = pkce_code_challenge
+                    .as_ref()
+                    .map(|challenge| String::from_utf8_lossy(challenge.verifier.expose()));
+
+                events.emit(&Event::AuthorizationRequest {
+                    authorization_uri: auth_uri.as_str(),
+                    state: &String::from_utf8_lossy(state.expose()),
+                    pkce_code_verifier: verifier.as_deref(),
+                    redirect_uri: redirect_uri.as_str(),
+                });
+            } else {
+                printer.out(&authorization_uri)?;
             }
 
-            return Ok(());
-        }
+            if !self.wait {
+                if !printer.is_json() {
+                    println!();
+                    print_manual_resume(
+                        &state,
+                        pkce_code_challenge.as_ref().map(|c| &c.verifier),
+                        self.redirect_uri.as_ref(),
+                    );
+                }
 
-        println!("{authorization_uri}");
+                return Ok(());
+            }
+        } else {
+            println!("{authorization_uri}");
 
-        if interactive && let Err(err) = open::that(auth_uri.as_str()) {
-            println!("Cannot open your browser ({err})");
+            if let Err(err) = self.open_browser(account, auth_uri.as_str()) {
+                println!("Cannot open your browser ({err})");
 
-            let msg = "Click on the link to manually start the authorization process";
-            println!("{msg}: {auth_uri}");
+                let msg = "Click on the link to manually start the authorization process";
+                println!("{msg}: {auth_uri}");
+            }
         }
 
         // NOTE: a redirection the local listener cannot bind (a reverse-DNS
@@ -151,48 +409,156 @@ impl AuthGetCommand {
         // `auth resume` rather than binding a listener that would fail
         // on the unknown scheme (no host, no inferable port).
         if !is_loopback_redirect(&redirect_uri) {
-            println!();
-            println!(
-                "Ortie cannot capture the redirection {} automatically.",
-                redirect_uri.as_str(),
-            );
-            print_manual_resume(&state, pkce_code_challenge.as_ref().map(|c| &c.verifier));
+            if !events.is_enabled() {
+                println!();
+                println!(
+                    "Ortie cannot capture the redirection {} automatically.",
+                    redirect_uri.as_str(),
+                );
+                print_manual_resume(
+                    &state,
+                    pkce_code_challenge.as_ref().map(|c| &c.verifier),
+                    self.redirect_uri.as_ref(),
+                );
+            }
+
+            // NOTE: with a scheme handler installed, the browser hands
+            // the redirection to `auth resume --from-handler`, which
+            // passes it over to this process: the manual steps above
+            // only remain as the fallback.
+            #[cfg(unix)]
+            if let Some(waiter) = crate::auth::handler::listen(redirect_uri.scheme()) {
+                if !events.is_enabled() {
+                    println!();
+                    println!("Wait for the scheme handler to hand the redirection over…");
+                }
+
+                let cmd = AuthResumeCommand {
+                    input: waiter.accept()?,
+                    from_handler: false,
+                    state: Some(state),
+                    pkce: pkce_code_challenge.map(|pkce| pkce.verifier),
+                    redirect_uri: Some(redirect_uri),
+                    events,
+                };
+
+                return cmd.execute(printer, account);
+            }
+
+            if events.is_enabled() {
+                bail!(
+                    "Cannot wait for the redirection {}, install its handler with `ortie scheme install`",
+                    redirect_uri.as_str(),
+                );
+            }
 
             return Ok(());
         }
 
-        println!("Wait for redirection…");
+        if !events.is_enabled() {
+            println!("Wait for redirection…");
+        }
 
-        let redirected_uri = match await_redirect(&redirect_uri) {
-            Ok(redirected_uri) => redirected_uri,
+        let redirect = match redirect::accept(&redirect_uri) {
+            Ok(redirect) => redirect,
+            Err(err) if events.is_enabled() => {
+                return Err(err.context("Capture the redirection"));
+            }
             // NOTE: the listener could not bind or accept (a privileged or
             // taken port, a closed browser): fall back to the manual
             // flow instead of aborting the whole grant.
             Err(err) => {
                 println!();
                 println!("Ortie could not capture the redirection automatically ({err}).");
-                print_manual_resume(&state, pkce_code_challenge.as_ref().map(|c| &c.verifier));
+                print_manual_resume(
+                    &state,
+                    pkce_code_challenge.as_ref().map(|c| &c.verifier),
+                    self.redirect_uri.as_ref(),
+                );
 
                 return Ok(());
             }
         };
 
         let cmd = AuthResumeCommand {
-            input: redirected_uri.to_string(),
+            input: redirect.uri.to_string(),
+            from_handler: false,
             state: Some(state),
             pkce: pkce_code_challenge.map(|pkce| pkce.verifier),
-            redirect_uri: Some(redirect_uri.into_owned()),
+            redirect_uri: Some(redirect_uri),
+            events,
         };
 
-        cmd.execute(printer, account)
+        // NOTE: the browser waits on the listener until the code is
+        // exchanged, so its page can tell how the flow ended.
+        let result = cmd.execute(printer, account);
+        redirect.respond(account, &result);
+        result
+    }
+
+    /// Renders the QR code of `uri` for the terminal, unless disabled,
+    /// and exports it when asked to.
+    ///
+    /// Only an asked for QR code fails the flow: the one a terminal
+    /// gets by default is a convenience, skipped when it cannot be
+    /// rendered (a URI too long for the largest QR version).
+    fn qr(&self, uri: &str) -> Result<Option<String>> {
+        self.render_qr(uri, stdout().is_terminal())
+    }
+
+    /// [`Self::qr`], `terminal` telling whether stdout is one.
+    fn render_qr(&self, uri: &str, terminal: bool) -> Result<Option<String>> {
+        if let Some(path) = &self.qr_export {
+            qr::export(uri, path)?;
+        }
+
+        if self.no_qr || !(self.qr || terminal) {
+            return Ok(None);
+        }
+
+        match qr::render(uri) {
+            Ok(qr) => Ok(Some(qr)),
+            Err(err) if self.qr => Err(err),
+            Err(err) => {
+                debug!("skip QR code rendering: {err:#}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Opens `url` with the `--browser` override, or the account
+    /// launcher when none is given. Does nothing with `--no-browser`,
+    /// the URI being printed already.
+    fn open_browser(&self, account: &Account, url: &str) -> Result<()> {
+        if self.no_browser {
+            return Ok(());
+        }
+
+        let browser = self.browser.as_deref().map(command::shell);
+        account.open_browser(url, browser.as_ref())
     }
 }
 
 /// Prints the manual `auth resume` command that finishes the flow by
-/// hand, filled with the flow's state and (when PKCE is enabled) code
-/// verifier. Used whenever the local listener cannot capture the
+/// hand. Used whenever the local listener cannot capture the
 /// redirect: a non-interactive shell, a private-use redirection
 /// scheme, or a listener that failed to bind.
+fn print_manual_resume(
+    state: &Oauth20State,
+    pkce: Option<&Oauth20PkceCodeVerifier>,
+    redirect_uri: Option<&Url>,
+) {
+    println!(
+        "Once authorized, copy the URL your browser was redirected to, \
+	 then run the resume subcommand:"
+    );
+    println!();
+    println!("> {}", manual_resume_command(state, pkce, redirect_uri));
+}
+
+/// The `auth resume` command filled with the flow's state, its code
+/// verifier when PKCE is enabled, and the `--redirect-uri` override,
+/// which the code exchange must repeat.
 ///
 /// The values are attached to their flag with `=` and single quoted.
 /// Both halves matter: a PKCE verifier is drawn from the RFC 7636
@@ -200,24 +566,26 @@ impl AuthGetCommand {
 /// begin with `-` and be read as a flag rather than a value, which the
 /// `=` form settles before the parser sees it; the quotes keep the
 /// shell from expanding a leading `~`.
-fn print_manual_resume(state: &Oauth20State, pkce: Option<&Oauth20PkceCodeVerifier>) {
+fn manual_resume_command(
+    state: &Oauth20State,
+    pkce: Option<&Oauth20PkceCodeVerifier>,
+    redirect_uri: Option<&Url>,
+) -> String {
     let state = shell_single_quote(&String::from_utf8_lossy(state.expose()));
+    let mut cmd = format!("ortie auth resume --state={state}");
 
-    println!(
-        "Once authorized, copy the URL your browser was redirected to, \
-	 then run the resume subcommand:"
-    );
-    println!();
+    if let Some(verifier) = pkce {
+        let verifier = shell_single_quote(&String::from_utf8_lossy(verifier.expose()));
+        cmd.push_str(&format!(" --pkce={verifier}"));
+    }
 
-    match pkce {
-        Some(verifier) => {
-            let verifier = shell_single_quote(&String::from_utf8_lossy(verifier.expose()));
-            println!("> ortie auth resume --state={state} --pkce={verifier} <REDIRECTED_URI>");
-        }
-        None => {
-            println!("> ortie auth resume --state={state} <REDIRECTED_URI>");
-        }
+    if let Some(uri) = redirect_uri {
+        let uri = shell_single_quote(uri.as_str());
+        cmd.push_str(&format!(" --redirect-uri={uri}"));
     }
+
+    cmd.push_str(" <REDIRECTED_URI>");
+    cmd
 }
 
 /// Whether the redirection can be serviced by the local listener:
@@ -251,6 +619,9 @@ pub struct AuthorizationUri<'a> {
     pkce_code_verifier: Option<&'a Oauth20PkceCodeVerifier>,
     /// Whether the flow was initiated from an interactive shell.
     interactive: bool,
+    /// QR code of the authorization URI, rendered for the terminal.
+    #[serde(skip)]
+    qr: Option<String>,
 }
 
 /// Serializes the CSRF state as its UTF-8 string form.
@@ -291,11 +662,23 @@ impl fmt::Display for AuthorizationUri<'_> {
             writeln!(f, "Click on the link to start the authorization process:")
         }?;
 
-        writeln!(f, "{}", self.authorization_uri)
+        writeln!(f, "{}", self.authorization_uri)?;
+
+        if let Some(qr) = &self.qr {
+            writeln!(f)?;
+            write!(f, "{qr}")?;
+        }
+
+        Ok(())
     }
 }
 
-fn execute_device(printer: &mut impl Printer, account: &mut Account) -> Result<()> {
+fn execute_device(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    cmd: &AuthGetCommand,
+    events: Events,
+) -> Result<()> {
     let Some(device_endpoint) = account.device_authorization_endpoint.clone() else {
         bail!("Missing endpoints.device-authorization in the account config");
     };
@@ -304,20 +687,7 @@ fn execute_device(printer: &mut impl Printer, account: &mut Account) -> Result<(
     };
 
     let interactive = stdout().is_terminal();
-    let params = Oauth20DeviceAuthRequestParams {
-        client_id: account.client_id.as_str().into(),
-        scope: BTreeSet::from_iter(account.scopes.iter().map(|s| Cow::from(s.as_str()))),
-    };
-
-    let client_secret = account.client_secret.clone().map(Secret::get).transpose()?;
-    let mut device_client = Oauth20ClientStd::connect(
-        device_endpoint.clone(),
-        &account.tls,
-        account.client_id.clone(),
-    )?;
-    device_client.client_secret = client_secret;
-
-    let device = match device_client.request_device_auth(&device_endpoint, params)? {
+    let device = match request_device_auth(account, &device_endpoint)? {
         Ok(device) => device,
         Err(res) => {
             debug!("execute issue access token error hook");
@@ -340,34 +710,177 @@ fn execute_device(printer: &mut impl Printer, account: &mut Account) -> Result<(
         expires_in: device.expires_in,
         interval: device.interval,
         interactive,
+        qr: cmd.qr(device
+            .verification_uri_complete
+            .as_deref()
+            .unwrap_or(device.verification_uri.as_str()))?,
     };
 
-    // NOTE: D5, non-interactive or --json print and hand off to auth resume.
+    debug!("execute authorize hook");
+    account.execute_on_authorize_hook(&AuthorizeHookParams::DeviceCode(&device));
+
+    // NOTE: D5, non-interactive or --json print and hand off to auth
+    // resume, unless asked to wait.
     if printer.is_json() || !interactive {
-        printer.out(&view)?;
-        if !printer.is_json() {
-            println!();
-            println!("Once authorized, run:");
-            println!();
-            println!(
-                "> ortie auth resume {}",
-                shell_single_quote(&view.device_code)
-            );
+        if events.is_enabled() {
+            events.emit(&Event::DeviceCode {
+                user_code: &device.user_code,
+                verification_uri: &device.verification_uri,
+                verification_uri_complete: device.verification_uri_complete.as_deref(),
+                expires_in: device.expires_in,
+                interval: device.interval,
+            });
+        } else {
+            printer.out(&view)?;
         }
-        return Ok(());
+
+        if !cmd.wait {
+            if !printer.is_json() {
+                println!();
+                println!("Once authorized, run:");
+                println!();
+                println!(
+                    "> ortie auth resume {}",
+                    shell_single_quote(&view.device_code)
+                );
+            }
+            return Ok(());
+        }
+    } else {
+        println!("{view}");
+        let open_uri = device
+            .verification_uri_complete
+            .as_deref()
+            .unwrap_or(device.verification_uri.as_str());
+        if let Err(err) = cmd.open_browser(account, open_uri) {
+            println!("Cannot open your browser ({err})");
+            println!("Open {open_uri} and enter the code {}", device.user_code);
+        }
+    }
+
+    if !events.is_enabled() {
+        println!("Waiting for authorization…");
+    }
+
+    complete_device_token_poll(printer, account, &token_endpoint, &device, events)
+}
+
+/// Requests the device and user codes, forwarding the account extras
+/// and authorization details like the authorization code grant does,
+/// an extra of the same key winning over the details.
+///
+/// NOTE: io-oauth's device authorization params carry no extras, so
+/// the form goes through [`http::post_form`] instead.
+fn request_device_auth(account: &Account, endpoint: &Url) -> Result<Oauth20DeviceAuthResponse> {
+    let scope = account.scopes.join(" ");
+    let authorization_details = account.authorization_details()?;
+
+    let mut form = vec![("client_id", account.client_id.as_str())];
+    if !scope.is_empty() {
+        form.push(("scope", scope.as_str()));
+    }
+    if let Some(details) = &authorization_details
+        && !account.extras.contains_key("authorization_details")
+    {
+        form.push(("authorization_details", details.as_str()));
+    }
+    form.extend(
+        account
+            .extras
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str())),
+    );
+
+    let res = http::post_form(account, endpoint, form)?;
+    let body = res.body.as_slice();
+
+    if res.status.is_success() {
+        let device = Oauth20DeviceAuthSuccessParams::try_from(body)
+            .context("Parse device authorization response")?;
+        Ok(Ok(device))
+    } else {
+        let err = Oauth20AccessTokenErrorParams::try_from(body)
+            .context("Parse device authorization error response")?;
+        Ok(Err(err))
+    }
+}
+
+/// Sends the backchannel authentication request, then polls for the
+/// token, or hands the `auth_req_id` off to `auth resume` like the
+/// device grant hands off its device code.
+fn execute_ciba(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    cmd: &AuthGetCommand,
+    events: Events,
+) -> Result<()> {
+    let Some(ciba_endpoint) = account.backchannel_authentication_endpoint.clone() else {
+        bail!("Missing endpoints.backchannel-authentication in the account config");
+    };
+    let Some(token_endpoint) = account.token_endpoint.clone() else {
+        bail!("Missing endpoints.token in the account config");
+    };
+
+    let interactive = stdout().is_terminal();
+    let ciba = match ciba::request_auth(account, &ciba_endpoint)? {
+        Ok(ciba) => ciba,
+        Err(res) => {
+            debug!("execute issue access token error hook");
+            account.execute_on_issue_error_hook(&res);
+            let err = anyhow!("Backchannel authentication error (code {:?})", res.error);
+            return Err(match (res.error_description, res.error_uri) {
+                (None, None) => err,
+                (Some(desc), None) => anyhow!("{desc}").context(err),
+                (None, Some(uri)) => anyhow!("{uri}").context(err),
+                (Some(desc), Some(uri)) => anyhow!("{desc}: {uri}").context(err),
+            });
+        }
+    };
+
+    let view = BackchannelAuthentication {
+        auth_req_id: &ciba.auth_req_id,
+        binding_message: account
+            .extras
+            .get("binding_message")
+            .or(account.ciba_binding_message.as_ref())
+            .map(String::as_str),
+        expires_in: ciba.expires_in,
+        interval: ciba.interval,
+        interactive,
+    };
+
+    if printer.is_json() || !interactive {
+        if events.is_enabled() {
+            events.emit(&Event::BackchannelAuthentication {
+                binding_message: view.binding_message,
+                expires_in: ciba.expires_in,
+                interval: ciba.interval,
+            });
+        } else {
+            printer.out(&view)?;
+        }
+
+        if !cmd.wait {
+            if !printer.is_json() {
+                println!();
+                println!("Once approved, run:");
+                println!();
+                println!(
+                    "> ortie auth resume {}",
+                    shell_single_quote(&ciba.auth_req_id)
+                );
+            }
+            return Ok(());
+        }
+    } else {
+        println!("{view}");
     }
 
-    println!("{view}");
-    let open_uri = device
-        .verification_uri_complete
-        .as_deref()
-        .unwrap_or(device.verification_uri.as_str());
-    if let Err(err) = open::that(open_uri) {
-        println!("Cannot open your browser ({err})");
-        println!("Open {open_uri} and enter the code {}", device.user_code);
+    if !events.is_enabled() {
+        println!("Waiting for approval…");
     }
-    println!("Waiting for authorization…");
-    complete_device_token_poll(printer, account, &token_endpoint, &device)
+
+    complete_ciba_token_poll(printer, account, &token_endpoint, &ciba, events)
 }
 
 /// Polls the token endpoint until the device grant completes, then stores
@@ -377,19 +890,87 @@ pub(crate) fn complete_device_token_poll(
     account: &mut Account,
     token_endpoint: &Url,
     device: &Oauth20DeviceAuthSuccessParams,
+    events: Events,
 ) -> Result<()> {
     let client_secret = account.client_secret.clone().map(Secret::get).transpose()?;
-    let mut client = Oauth20ClientStd::connect(
-        token_endpoint.clone(),
-        &account.tls,
-        account.client_id.clone(),
-    )?;
-    client.client_secret = client_secret;
-
-    // NOTE: outer Result is transport / client-side; inner is the token body.
-    match client.await_device_access_token(&account.tls, device) {
-        Ok(Ok(res)) => report_token_issued(printer, account, &res),
-        Ok(Err(res)) => {
+
+    // NOTE: authorization servers rarely keep the socket alive
+    // between polls, so each one opens a fresh connection. Once sent,
+    // a device code may have been redeemed, hence single-use.
+    let res = poll_access_token(device.expires_in, device.interval, events, || {
+        http::request_token(account, token_endpoint, Credential::SingleUse, |client| {
+            let params = Oauth20DeviceAccessTokenRequestParams {
+                client_id: account.client_id.as_str().into(),
+                device_code: device.device_code.clone(),
+            };
+
+            // NOTE: io-oauth's device params carry no client
+            // assertion, the hand-made request appends it.
+            if account.private_key_jwt() {
+                let form = params.to_serializer().finish();
+                http::post_body(client, account, token_endpoint, form)?;
+            } else {
+                client.client_secret = client_secret.clone();
+                client.request_device_access_token(params).map(drop)?;
+            }
+
+            Ok(())
+        })
+    })?;
+
+    match res {
+        Some(res) => report_token_polled(printer, account, res, events),
+        None => {
+            let err = Oauth20ClientStdError::DeviceCodeExpired;
+            if let Some(params) = device_poll_client_error_hook_params(&err) {
+                debug!("execute issue access token error hook");
+                account.execute_on_issue_error_hook(&params);
+            }
+            Err(err.into())
+        }
+    }
+}
+
+/// Polls the token endpoint until the user approved the backchannel
+/// authentication request, then stores the token and fires the
+/// on-issue hooks like the device grant.
+pub(crate) fn complete_ciba_token_poll(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    token_endpoint: &Url,
+    ciba: &CibaAuthSuccessParams,
+    events: Events,
+) -> Result<()> {
+    let res = poll_access_token(ciba.expires_in, ciba.interval, events, || {
+        ciba::request_access_token(account, token_endpoint, &ciba.auth_req_id)
+    })?;
+
+    // NOTE: the local deadline is the client twin of the server
+    // `expired_token`, reported as such.
+    let res = res.unwrap_or_else(|| {
+        Err(Oauth20AccessTokenErrorParams {
+            error: Oauth20AccessTokenErrorCode::ExpiredToken,
+            error_description: Some(
+                "authentication request expired before the user approved it".into(),
+            ),
+            error_uri: None,
+        })
+    });
+
+    report_token_polled(printer, account, res, events)
+}
+
+/// Reports the final poll response: the issued token, or the server
+/// error after firing the on-issue error hook.
+fn report_token_polled(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    res: Result<StoredToken, Oauth20AccessTokenErrorParams>,
+    events: Events,
+) -> Result<()> {
+    match res {
+        Ok(res) => report_token_issued(printer, account, res, events),
+        Err(res) => {
             debug!("execute issue access token error hook");
             account.execute_on_issue_error_hook(&res);
             let err = anyhow!("Issue access token error (code {:?})", res.error);
@@ -400,12 +981,51 @@ pub(crate) fn complete_device_token_poll(
                 (Some(desc), Some(uri)) => anyhow!("{desc}: {uri}").context(err),
             })
         }
-        Err(err) => {
-            if let Some(params) = device_poll_client_error_hook_params(&err) {
-                debug!("execute issue access token error hook");
-                account.execute_on_issue_error_hook(&params);
-            }
-            Err(err.into())
+    }
+}
+
+/// Runs `poll` every interval until the user completed the
+/// authorization or the server rejected it, `slow_down` adding five
+/// seconds to the interval. Returns `None` once `expires_in` elapsed.
+///
+/// NOTE: io-oauth's `await_device_access_token` runs the same loop but
+/// keeps each poll to itself; this one reports them as events, and
+/// serves the CIBA grant too.
+fn poll_access_token<E>(
+    expires_in: usize,
+    interval: usize,
+    events: Events,
+    mut poll: impl FnMut() -> Result<Result<StoredToken, Oauth20AccessTokenErrorParams>, E>,
+) -> Result<Option<Result<StoredToken, Oauth20AccessTokenErrorParams>>, E> {
+    let deadline = Instant::now() + Duration::from_secs(expires_in as u64);
+    let mut interval = Duration::from_secs(interval as u64);
+    let mut attempt = 0;
+
+    loop {
+        thread::sleep(interval);
+
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+
+        attempt += 1;
+        events.emit(&Event::Polling {
+            attempt,
+            interval: interval.as_secs(),
+        });
+
+        match poll()? {
+            Ok(res) => return Ok(Some(Ok(res))),
+            Err(res) => match res.error {
+                Oauth20AccessTokenErrorCode::AuthorizationPending => continue,
+                Oauth20AccessTokenErrorCode::SlowDown => {
+                    interval += Duration::from_secs(5);
+                    events.emit(&Event::SlowDown {
+                        interval: interval.as_secs(),
+                    });
+                }
+                _ => return Ok(Some(Err(res))),
+            },
         }
     }
 }
@@ -434,9 +1054,13 @@ const JWT_ASSERTION_VALIDITY: Duration = Duration::from_secs(600);
 /// Runs the client credentials grant headlessly in one shot: no
 /// browser, no user code, no resume. Fires the on-issue hooks and
 /// persists the token like the interactive grants.
-fn execute_client_credentials(printer: &mut impl Printer, account: &mut Account) -> Result<()> {
+fn execute_client_credentials(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    events: Events,
+) -> Result<()> {
     match request_client_credentials_token(account)? {
-        Ok(res) => report_token_issued(printer, account, &res),
+        Ok(res) => report_token_issued(printer, account, res, events),
         Err(res) => {
             debug!("execute issue access token error hook");
             account.execute_on_issue_error_hook(&res);
@@ -449,86 +1073,313 @@ fn execute_client_credentials(printer: &mut impl Printer, account: &mut Account)
     }
 }
 
+/// Runs the password grant headlessly in one shot, like the client
+/// credentials grants, after warning the grant is on its way out.
+fn execute_password(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    events: Events,
+) -> Result<()> {
+    // NOTE: on stderr, so the JSON output stays parseable.
+    eprintln!("{}", password::DEPRECATION_WARNING);
+
+    match password::request_access_token(account)? {
+        Ok(res) => report_token_issued(printer, account, res, events),
+        Err(res) => {
+            debug!("execute issue access token error hook");
+            account.execute_on_issue_error_hook(&res);
+            let err = anyhow!("Issue access token error (code {:?})", res.error);
+            Err(match (res.error_description, res.error_uri) {
+                (None, None) => err,
+                (Some(desc), None) => anyhow!("{desc}").context(err),
+                (None, Some(uri)) => anyhow!("{uri}").context(err),
+                (Some(desc), Some(uri)) => anyhow!("{desc}: {uri}").context(err),
+            })
+        }
+    }
+}
+
+/// OAuth 1.0a: obtains temporary credentials, sends the user to
+/// authorize them, and exchanges them for token credentials once the
+/// loopback listener captured the verifier.
+///
+/// The temporary credentials live in this process only, so there is
+/// no hand-off to `auth resume`: every run waits on the listener,
+/// printing the authorization URI first when it cannot open it.
+fn execute_oauth1(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    cmd: &AuthGetCommand,
+    events: Events,
+) -> Result<()> {
+    let Some(request_token_endpoint) = account.request_token_endpoint.clone() else {
+        bail!("Missing endpoints.request-token in the account config");
+    };
+    let Some(authorization_endpoint) = account.authorization_endpoint.clone() else {
+        bail!("Missing endpoints.authorization in the account config");
+    };
+    let Some(access_token_endpoint) = account.access_token_endpoint.clone() else {
+        bail!("Missing endpoints.access-token in the account config");
+    };
+
+    let redirect_uri = account.redirection()?.into_owned();
+
+    if !is_loopback_redirect(&redirect_uri) {
+        bail!(
+            "The oauth1 grant captures its redirection on a loopback listener, {} cannot be",
+            redirect_uri.as_str(),
+        );
+    }
+
+    let temporary =
+        oauth1::request_temporary_credentials(account, &request_token_endpoint, &redirect_uri)?;
+    let auth_uri = oauth1::authorization_uri(&authorization_endpoint, &temporary);
+    let interactive = stdout().is_terminal();
+
+    debug!("execute authorize hook");
+    account.execute_on_authorize_hook(&AuthorizeHookParams::AuthorizationUri(&auth_uri));
+
+    let view = Oauth1AuthorizationUri {
+        authorization_uri: &auth_uri,
+        interactive,
+        qr: cmd.qr(auth_uri.as_str())?,
+    };
+
+    // NOTE: the temporary secret never leaves this process, so there
+    // is no `auth resume` to hand off to: every run waits on the
+    // listener, the output flushed first for the reader to act on.
+    if events.is_enabled() {
+        events.emit(&Event::Oauth1AuthorizationRequest {
+            authorization_uri: auth_uri.as_str(),
+            redirect_uri: redirect_uri.as_str(),
+        });
+    } else if printer.is_json() || !interactive {
+        printer.out(&view)?;
+        stdout().flush()?;
+    } else {
+        println!("{view}");
+
+        if let Err(err) = cmd.open_browser(account, auth_uri.as_str()) {
+            println!("Cannot open your browser ({err})");
+        }
+
+        println!("Wait for redirection…");
+    }
+
+    let redirect = redirect::accept(&redirect_uri).context("Capture the redirection")?;
+
+    let result = oauth1::verifier(&redirect.uri, &temporary)
+        .and_then(|verifier| {
+            oauth1::request_token_credentials(
+                account,
+                &access_token_endpoint,
+                &temporary,
+                &verifier,
+            )
+        })
+        .and_then(|token| report_token_issued(printer, account, token, events));
+
+    redirect.respond(account, &result);
+    result
+}
+
+/// Printable authorization URI of the OAuth 1.0a grant.
+#[derive(Serialize)]
+struct Oauth1AuthorizationUri<'a> {
+    /// The authorization URI carrying the temporary credentials.
+    authorization_uri: &'a Url,
+    /// Whether the flow was initiated from an interactive shell.
+    interactive: bool,
+    /// QR code of the authorization URI, rendered for the terminal.
+    #[serde(skip)]
+    qr: Option<String>,
+}
+
+impl fmt::Display for Oauth1AuthorizationUri<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.interactive {
+            writeln!(f, "Sending authorization request to your browser:")
+        } else {
+            writeln!(f, "Click on the link to start the authorization process:")
+        }?;
+
+        writeln!(f, "{}", self.authorization_uri)?;
+
+        if let Some(qr) = &self.qr {
+            writeln!(f)?;
+            write!(f, "{qr}")?;
+        }
+
+        Ok(())
+    }
+}
+
 /// Runs the configured client credentials exchange against the token
-/// endpoint and returns the raw token response: Basic auth from the
+/// endpoint and returns the token response: Basic auth from the
 /// client secret on the plain kind, a freshly minted JWT assertion on
 /// the JWT kind. Shared by `auth get` (issue) and the token
-/// re-acquisition path (refresh).
+/// re-acquisition path (refresh), retried on transient failures.
+///
+/// NOTE: io-oauth's client credentials params carry no authorization
+/// details (RFC 9396) nor client assertion, so with either the form
+/// its serializer writes goes through [`http::post_body`], which
+/// appends them.
 pub(crate) fn request_client_credentials_token(
     account: &mut Account,
-) -> Result<Result<Oauth20AccessTokenSuccessParams, Oauth20AccessTokenErrorParams>> {
+) -> Result<Result<StoredToken, Oauth20AccessTokenErrorParams>> {
     let Some(token_endpoint) = account.token_endpoint.clone() else {
         bail!("Missing endpoints.token in the account config");
     };
 
-    let mut client =
-        Oauth20ClientStd::connect(token_endpoint, &account.tls, account.client_id.clone())?;
+    let authorization_details = account.authorization_details()?;
 
     if account.grant == GrantConfig::ClientCredentialsJwt {
-        let Some(key_path) = account.client_key.clone() else {
-            bail!("Missing client-key in the account config");
+        let (key, x5t) = client_signing_key(account)?;
+
+        // NOTE: everything is re-derived at every mint, every attempt
+        // included: the x5t recomputed from the certificate, fresh
+        // iat/exp on a short validity and a unique jti. The assertion
+        // lives only for this request and is never stored.
+        let send = |client: &mut Oauth20ClientStd| {
+            let jti = random_jti();
+
+            let claims = Oauth20JwtBearerClaims {
+                iss: account.client_id.as_str().into(),
+                sub: Some(account.client_id.as_str().into()),
+                jti: Some(jti.into()),
+                ..Default::default()
+            };
+
+            let assertion = client.sign_jwt_bearer_assertion(
+                &key,
+                claims,
+                x5t.as_deref(),
+                JWT_ASSERTION_VALIDITY,
+            )?;
+
+            // NOTE: the assertion authenticates the client, so no
+            // client secret rides along: the Basic header it would
+            // produce conflicts with the assertion authentication.
+            let params = Oauth20JwtBearerClientCredentialsRequestParams {
+                client_id: Some(account.client_id.as_str().into()),
+                scope: account.scopes.iter().map(Into::into).collect(),
+                client_assertion: assertion,
+            };
+
+            match &authorization_details {
+                Some(details) => {
+                    let mut form = params.to_serializer();
+                    form.append_pair("authorization_details", details);
+                    http::post_body(client, account, &token_endpoint, form.finish())?;
+                }
+                None => {
+                    client
+                        .request_jwt_bearer_client_credentials(params)
+                        .map(drop)?;
+                }
+            }
+
+            Ok(())
         };
 
-        // NOTE: everything is re-derived at every mint: the key
-        // re-read from disk, the x5t recomputed from the certificate,
-        // fresh iat/exp on a short validity and a unique jti. The
-        // assertion lives only for this request and is never stored.
-        let pem = fs::read_to_string(&key_path)
-            .with_context(|| format!("Read client key from {}", key_path.display()))?;
-        let key = Oauth20JwtBearerKey::from_pkcs8_pem(&pem).or_else(|_| {
-            Oauth20JwtBearerKey::from_pkcs1_pem(&pem)
-                .context("Parse client key as a PKCS#8 or PKCS#1 PEM private key")
-        })?;
-
-        let x5t = match &account.client_certificate {
-            None => None,
-            Some(path) => {
-                let bytes = fs::read(path)
-                    .with_context(|| format!("Read client certificate from {}", path.display()))?;
-                Some(x5t_from_der(&certificate_der(bytes)?))
+        http::request_token(account, &token_endpoint, Credential::Reusable, send)
+    } else {
+        let client_secret = account.client_secret.clone().map(Secret::get).transpose()?;
+
+        let send = |client: &mut Oauth20ClientStd| {
+            let params = Oauth20ClientCredentialsRequestParams {
+                scope: account.scopes.iter().map(Into::into).collect(),
+            };
+
+            if authorization_details.is_some() || account.private_key_jwt() {
+                let mut form = params.to_serializer();
+
+                if let Some(details) = &authorization_details {
+                    form.append_pair("authorization_details", details);
+                }
+
+                http::post_body(client, account, &token_endpoint, form.finish())?;
+            } else {
+                client.client_secret = client_secret.clone();
+                client.request_client_credentials(params).map(drop)?;
             }
+
+            Ok(())
         };
 
-        // NOTE: reuse the CSRF state generator as the random source
-        // for the unique jti, re-encoded in URL-safe base64.
-        let jti = BASE64_URL_SAFE_NO_PAD.encode(Oauth20State::default().expose());
+        http::request_token(account, &token_endpoint, Credential::Reusable, send)
+    }
+}
 
-        let claims = Oauth20JwtBearerClaims {
-            iss: account.client_id.as_str().into(),
-            sub: Some(account.client_id.as_str().into()),
-            jti: Some(jti.into()),
-            ..Default::default()
-        };
+/// Mints the client assertion authenticating a request of the
+/// `client-auth = "private-key-jwt"` account over `client`, re-derived
+/// like the client credentials JWT ones.
+///
+/// NOTE: the audience is the issuer identifier when set, which FAPI
+/// 2.0 servers must accept at every endpoint, the endpoint `client`
+/// is connected to otherwise.
+pub(crate) fn client_assertion(
+    client: &Oauth20ClientStd,
+    account: &Account,
+) -> Result<SecretString> {
+    let (key, x5t) = client_signing_key(account)?;
+    let jti = random_jti();
+
+    let claims = Oauth20JwtBearerClaims {
+        iss: account.client_id.as_str().into(),
+        sub: Some(account.client_id.as_str().into()),
+        aud: account.issuer.as_deref().unwrap_or_default().into(),
+        jti: Some(jti.into()),
+        ..Default::default()
+    };
 
-        let assertion = client.sign_jwt_bearer_assertion(
-            &key,
-            claims,
-            x5t.as_deref(),
-            JWT_ASSERTION_VALIDITY,
-        )?;
+    Ok(client.sign_jwt_bearer_assertion(&key, claims, x5t.as_deref(), JWT_ASSERTION_VALIDITY)?)
+}
 
-        // NOTE: the assertion authenticates the client, so the client
-        // secret stays unset: the Basic header it would produce
-        // conflicts with the assertion authentication.
-        let res = client.request_jwt_bearer_client_credentials(
-            Oauth20JwtBearerClientCredentialsRequestParams {
-                client_id: Some(account.client_id.as_str().into()),
-                scope: account.scopes.iter().map(Into::into).collect(),
-                client_assertion: assertion,
-            },
-        )?;
+/// Reads the account `client-key` and derives the `x5t` thumbprint of
+/// its `client-certificate`, when set: what signs the client
+/// assertions and the request objects alike.
+pub(crate) fn client_signing_key(
+    account: &Account,
+) -> Result<(Oauth20JwtBearerKey, Option<String>)> {
+    let pem = client_key_pem(account)?;
+    let key = Oauth20JwtBearerKey::from_pkcs8_pem(&pem).or_else(|_| {
+        Oauth20JwtBearerKey::from_pkcs1_pem(&pem)
+            .context("Parse client key as a PKCS#8 or PKCS#1 PEM private key")
+    })?;
+
+    Ok((key, client_certificate_x5t(account)?))
+}
 
-        Ok(res)
-    } else {
-        let client_secret = account.client_secret.clone().map(Secret::get).transpose()?;
-        client.client_secret = client_secret;
+/// Reads the PEM document of the account `client-key`.
+pub(crate) fn client_key_pem(account: &Account) -> Result<String> {
+    let Some(key_path) = &account.client_key else {
+        bail!("Missing client-key in the account config");
+    };
 
-        let res = client.request_client_credentials(Oauth20ClientCredentialsRequestParams {
-            scope: account.scopes.iter().map(Into::into).collect(),
-        })?;
+    fs::read_to_string(key_path)
+        .with_context(|| format!("Read client key from {}", key_path.display()))
+}
 
-        Ok(res)
-    }
+/// Derives the `x5t` thumbprint of the account `client-certificate`,
+/// when set.
+pub(crate) fn client_certificate_x5t(account: &Account) -> Result<Option<String>> {
+    let Some(path) = &account.client_certificate else {
+        return Ok(None);
+    };
+
+    let bytes = fs::read(path)
+        .with_context(|| format!("Read client certificate from {}", path.display()))?;
+
+    Ok(Some(x5t_from_der(&certificate_der(bytes)?)))
+}
+
+/// Returns a unique JWT identifier.
+///
+/// NOTE: reuse the CSRF state generator as the random source, re-encoded
+/// in URL-safe base64.
+pub(crate) fn random_jti() -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Oauth20State::default().expose())
 }
 
 /// Returns the DER bytes of a PEM or DER encoded certificate,
@@ -588,16 +1439,34 @@ pub(crate) fn client_credentials_error(
     }
 }
 
-/// Persist the token, fire on-issue success hooks, print the success
-/// message. Shared by the authorization-code and device grants.
+/// Compare the granted scopes with the requested ones, persist the
+/// token, fire on-issue success hooks, print the success message.
+/// Shared by the authorization-code and device grants.
 pub(crate) fn report_token_issued(
     printer: &mut impl Printer,
     account: &mut Account,
-    res: &Oauth20AccessTokenSuccessParams,
+    res: StoredToken,
+    events: Events,
 ) -> Result<()> {
-    account.write_to_storage(res.clone())?;
+    account.check_scopes(&res)?;
+
+    // NOTE: under the refresh lock, so a refresh running meanwhile
+    // does not overwrite the issued token with the one it replaces.
+    let token =
+        RefreshLock::acquire(&account.name).and_then(|_lock| account.write_to_storage(res))?;
     debug!("execute issue access token success hook");
-    account.execute_on_issue_success_hook(res);
+    account.execute_on_issue_success_hook(&token);
+    let res = &token.params;
+
+    if events.is_enabled() {
+        events.emit(&Event::Issued {
+            token_type: &res.token_type,
+            expires_in: res.expires_in,
+            scope: res.scope.as_deref(),
+        });
+        return Ok(());
+    }
+
     let msg = "Access token successfully issued";
     let msg = match res.expires_in {
         None => format!("{msg} (unknown expiry)"),
@@ -624,6 +1493,44 @@ fn shell_single_quote(s: &str) -> String {
     out
 }
 
+/// Clap value parser for the `KEY=VALUE` arguments (`--extra`,
+/// `token sign --param`).
+pub(crate) fn extra_parser(extra: &str) -> Result<(String, String), String> {
+    match extra.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("Expected KEY=VALUE, got `{extra}`")),
+    }
+}
+
+/// Printable / JSON backchannel authentication response for resume
+/// handoff.
+#[derive(Serialize)]
+struct BackchannelAuthentication<'a> {
+    auth_req_id: &'a str,
+    binding_message: Option<&'a str>,
+    expires_in: usize,
+    interval: usize,
+    interactive: bool,
+}
+
+impl fmt::Display for BackchannelAuthentication<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Created backchannel authentication request with:")?;
+        // NOTE: interactive sessions poll in-process; hide the request id.
+        if !self.interactive {
+            writeln!(f, " - auth request id: {}", self.auth_req_id)?;
+        }
+        writeln!(f, " - expires in: {}s", self.expires_in)?;
+        writeln!(f, " - interval: {}s", self.interval)?;
+        writeln!(f)?;
+
+        match self.binding_message {
+            Some(message) => writeln!(f, "Approve the request showing {message:?} on your device"),
+            None => writeln!(f, "Approve the request on your device"),
+        }
+    }
+}
+
 /// Printable / JSON device-authorization response for resume handoff.
 #[derive(Serialize)]
 struct DeviceAuthorization {
@@ -634,6 +1541,10 @@ struct DeviceAuthorization {
     expires_in: usize,
     interval: usize,
     interactive: bool,
+    /// QR code of the complete verification URI (the bare one when
+    /// the server sent none), rendered for the terminal.
+    #[serde(skip)]
+    qr: Option<String>,
 }
 
 impl fmt::Display for DeviceAuthorization {
@@ -655,12 +1566,26 @@ impl fmt::Display for DeviceAuthorization {
             f,
             "Navigate to {} and enter the code {}",
             self.verification_uri, self.user_code
-        )
+        )?;
+
+        if let Some(qr) = &self.qr {
+            writeln!(f)?;
+            writeln!(f, "Or scan the QR code:")?;
+            write!(f, "{qr}")?;
+        }
+
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
+
+    use pimalaya_config::toml::TomlConfig;
+
+    use crate::config::Config;
+
     use super::*;
 
     #[test]
@@ -683,4 +1608,107 @@ mod tests {
         assert_eq!(shell_single_quote("a;b"), "'a;b'");
         assert_eq!(shell_single_quote("it's"), "'it'\\''s'");
     }
+
+    #[test]
+    fn flags_overlay_the_account_for_one_run() {
+        let cmd = AuthGetCommand::try_parse_from([
+            "get",
+            "--scope",
+            "openid email",
+            "--scope=profile",
+            "--login-hint=me@example.com",
+            "--extra",
+            "access_type=offline",
+            "--extra=prompt=none",
+            "--prompt=consent",
+            "--redirect-uri=http://localhost:8080",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd.extras,
+            [
+                ("access_type".into(), "offline".into()),
+                ("prompt".into(), "none".into())
+            ]
+        );
+        assert!(extra_parser("no-equal-sign").is_err());
+        assert!(extra_parser("=value").is_err());
+        assert_eq!(extra_parser("k=a=b").unwrap(), ("k".into(), "a=b".into()));
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let toml = r#"
+[accounts.test]
+client-id = "app-id"
+scopes = ["mail"]
+extras.prompt = "login"
+endpoints.authorization = "https://idp.example.com/authorize"
+endpoints.token = "https://idp.example.com/token"
+endpoints.redirection = "http://127.0.0.1:9999/callback"
+storage.read.command = ["true"]
+storage.write.command = ["true"]
+"#;
+        file.write_all(toml.as_bytes()).unwrap();
+        let mut config = Config::from_paths(&[file.path().to_path_buf()]).unwrap();
+        let mut account = Account::from(config.take_named_account("test").unwrap());
+
+        let overlaid = cmd.overlay(&mut account);
+        assert_eq!(account.scopes, ["openid", "email", "profile"]);
+        assert_eq!(account.extras["prompt"], "consent");
+        assert_eq!(account.extras["login_hint"], "me@example.com");
+        assert_eq!(account.extras["access_type"], "offline");
+        assert_eq!(
+            account.redirection_endpoint.as_ref().map(Url::as_str),
+            Some("http://localhost:8080/")
+        );
+
+        overlaid.restore(&mut account);
+        assert_eq!(account.scopes, ["mail"]);
+        assert_eq!(
+            account.extras,
+            HashMap::from([("prompt".into(), "login".into())])
+        );
+        assert_eq!(
+            account.redirection_endpoint.as_ref().map(Url::as_str),
+            Some("http://127.0.0.1:9999/callback")
+        );
+    }
+
+    #[test]
+    fn only_an_asked_for_qr_code_fails_the_flow() {
+        let uri = format!("https://idp.example.com/authorize?{}", "x".repeat(8000));
+
+        let cmd = AuthGetCommand::try_parse_from(["get"]).unwrap();
+        assert!(cmd.render_qr(&uri, true).unwrap().is_none());
+        assert!(
+            cmd.render_qr("https://idp.example.com", true)
+                .unwrap()
+                .is_some()
+        );
+
+        let cmd = AuthGetCommand::try_parse_from(["get", "--qr"]).unwrap();
+        assert!(cmd.render_qr(&uri, false).is_err());
+
+        let path = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+        let export = format!("--qr-export={}", path.path().display());
+        let cmd = AuthGetCommand::try_parse_from(["get", &export]).unwrap();
+        assert!(cmd.render_qr(&uri, true).is_err());
+    }
+
+    #[test]
+    fn the_manual_resume_repeats_the_redirect_uri_override() {
+        let state =
+            Oauth20State::deserialize(StringDeserializer::<Error>::new(String::from("-st")))
+                .unwrap();
+        let uri = Url::parse("http://localhost:8080/cb").unwrap();
+
+        assert_eq!(
+            manual_resume_command(&state, None, None),
+            "ortie auth resume --state='-st' <REDIRECTED_URI>"
+        );
+        assert_eq!(
+            manual_resume_command(&state, None, Some(&uri)),
+            "ortie auth resume --state='-st' --redirect-uri='http://localhost:8080/cb' <REDIRECTED_URI>"
+        );
+    }
 }
@@ -0,0 +1,184 @@
+//! Hand-over of private-use scheme redirections.
+//!
+//! A redirection on a reverse-DNS private-use scheme (RFC 8252 section
+//! 7.1) dead-ends in the browser, which hands it to the desktop's URI
+//! scheme handler instead of any listener of ours. Once `ortie scheme
+//! install` registered one, the handler runs `ortie auth resume
+//! --from-handler <URI>`, which has no flow of its own to resume: it
+//! passes the URI over a per-user unix socket to the `auth get` still
+//! waiting on it, the process holding the state and the PKCE verifier.
+//! Without a registered handler, `auth get` keeps printing the manual
+//! `auth resume` steps.
+
+use std::{
+    fs,
+    io::{Read, Write},
+    os::unix::{
+        fs::DirBuilderExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use log::debug;
+
+use crate::scheme::install::desktop_entry_path;
+
+/// File name of the socket, under the per-user runtime directory.
+const SOCKET_NAME: &str = "redirection.sock";
+
+/// An `auth get` waiting for the scheme handler to hand its redirection
+/// over. The socket is removed once the waiter drops.
+pub struct Waiter {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Waiter {
+    /// Blocks until the handler connects, then returns the redirected
+    /// URI it handed over.
+    pub fn accept(&self) -> Result<String> {
+        loop {
+            let (mut stream, _) = self.listener.accept()?;
+            let mut uri = String::new();
+            stream
+                .read_to_string(&mut uri)
+                .context("Read redirected URI from the scheme handler")?;
+
+            // NOTE: another `auth get` probing whether this one is alive
+            // connects and hangs up without a word: keep waiting.
+            let uri = uri.trim();
+            if !uri.is_empty() {
+                return Ok(uri.to_owned());
+            }
+        }
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Listens for the hand-over when a handler for `scheme` is installed.
+/// Returns `None` when none is, or when the socket cannot be bound (an
+/// other `auth get` already waiting on it), so the caller falls back
+/// to the manual resume.
+pub fn listen(scheme: &str) -> Option<Waiter> {
+    let installed = desktop_entry_path(scheme).is_ok_and(|path| path.is_file());
+
+    if !installed {
+        debug!("no handler installed for scheme {scheme}");
+        return None;
+    }
+
+    match socket_path().and_then(|path| listen_at(&path)) {
+        Ok(waiter) => Some(waiter),
+        Err(err) => {
+            debug!("listen for scheme handler error: {err:?}");
+            None
+        }
+    }
+}
+
+/// Hands the redirected URI over to the waiting `auth get`.
+pub fn hand_over(uri: &str) -> Result<()> {
+    let path = socket_path()?;
+    hand_over_to(&path, uri)
+}
+
+/// Path of the per-user socket: under `$XDG_RUNTIME_DIR`, or the cache
+/// directory when the session exposes none. Its parent is created
+/// private to the user.
+fn socket_path() -> Result<PathBuf> {
+    let Some(dir) = dirs::runtime_dir().or_else(dirs::cache_dir) else {
+        return Err(anyhow!(
+            "Cannot find a runtime directory for the handler socket"
+        ));
+    };
+
+    let dir = dir.join(env!("CARGO_PKG_NAME"));
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .with_context(|| format!("Create handler socket directory {}", dir.display()))?;
+
+    Ok(dir.join(SOCKET_NAME))
+}
+
+fn listen_at(path: &Path) -> Result<Waiter> {
+    // NOTE: a socket nobody answers on is the leftover of an interrupted
+    // `auth get`, safe to replace; one that answers is a live flow.
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(anyhow!("Another auth get waits on {}", path.display()));
+        }
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Bind handler socket {}", path.display()))?;
+
+    Ok(Waiter {
+        listener,
+        path: path.to_owned(),
+    })
+}
+
+fn hand_over_to(path: &Path, uri: &str) -> Result<()> {
+    let mut stream = UnixStream::connect(path).with_context(|| {
+        format!(
+            "No auth get is waiting for the redirection on {}",
+            path.display()
+        )
+    })?;
+
+    stream
+        .write_all(uri.as_bytes())
+        .context("Hand redirected URI over to auth get")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn the_handed_over_uri_reaches_the_waiter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SOCKET_NAME);
+
+        let waiter = listen_at(&path).unwrap();
+        assert!(listen_at(&path).is_err(), "a live waiter is never replaced");
+
+        let handler = {
+            let path = path.clone();
+            thread::spawn(move || hand_over_to(&path, "org.pimalaya.ortie://redirect?code=c\n"))
+        };
+
+        assert_eq!(
+            waiter.accept().unwrap(),
+            "org.pimalaya.ortie://redirect?code=c"
+        );
+        handler.join().unwrap().unwrap();
+
+        drop(waiter);
+        assert!(!path.exists(), "the socket is removed once the flow ends");
+        assert!(hand_over_to(&path, "uri").is_err());
+    }
+
+    #[test]
+    fn a_stale_socket_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SOCKET_NAME);
+
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        assert!(listen_at(&path).is_ok());
+    }
+}
@@ -0,0 +1,385 @@
+//! OAuth 1.0a (RFC 5849), for the legacy APIs speaking nothing else.
+//!
+//! The three legs share one signing scheme: every request carries an
+//! `Authorization: OAuth` header whose signature covers the method,
+//! the URL and every parameter, keyed with the consumer secret and the
+//! token secret (HMAC-SHA1), or signed with the `client-key`
+//! (RSA-SHA1). io-oauth speaks OAuth 2.0 only, so the requests go
+//! through [`http::post_authorized`], and `token sign` reuses
+//! [`authorization`] for the requests the token then authorizes.
+//!
+//! Refs: <https://datatracker.ietf.org/doc/html/rfc5849>
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use hmac::{Hmac, KeyInit, Mac};
+use rsa::{
+    RsaPrivateKey,
+    pkcs1::DecodeRsaPrivateKey,
+    pkcs1v15::SigningKey,
+    pkcs8::DecodePrivateKey,
+    signature::{SignatureEncoding, Signer},
+};
+use secrecy::{ExposeSecret, SecretString};
+use sha1::Sha1;
+use url::{Url, form_urlencoded};
+
+use io_oauth::rfc6749::issue_access_token::Oauth20AccessTokenSuccessParams;
+
+use crate::{
+    account::Account, auth::get::random_jti, config::Oauth1SignatureMethodConfig, http,
+    token::stored::StoredToken,
+};
+
+/// Token type stored with OAuth 1.0a token credentials, in place of
+/// the OAuth 2.0 `Bearer`.
+pub const TOKEN_TYPE: &str = "OAuth";
+
+/// A token and its secret: the temporary credentials of the flow, or
+/// the token credentials it ends with.
+pub struct Credentials {
+    /// The `oauth_token` identifier.
+    pub token: String,
+    /// The `oauth_token_secret`, keying the signatures made with it.
+    pub secret: SecretString,
+}
+
+/// Obtains the temporary credentials (RFC 5849 section 2.1), handing
+/// `callback` over as the redirection of the authorization step.
+pub fn request_temporary_credentials(
+    account: &Account,
+    endpoint: &Url,
+    callback: &Url,
+) -> Result<Credentials> {
+    let callback = [("oauth_callback", callback.as_str())];
+    let authorization = authorization(account, "POST", endpoint, &[], None, &callback)?;
+
+    let res = http::post_authorized(account, endpoint, &authorization)?;
+    let params = response_params(&res.body, *res.status, "Request temporary credentials")?;
+
+    if params.get("oauth_callback_confirmed").map(String::as_str) != Some("true") {
+        bail!("Temporary credentials response does not confirm the callback");
+    }
+
+    credentials(params)
+}
+
+/// The authorization URI the user is sent to with the temporary
+/// credentials (RFC 5849 section 2.2).
+pub fn authorization_uri(endpoint: &Url, temporary: &Credentials) -> Url {
+    let mut uri = endpoint.clone();
+    uri.query_pairs_mut()
+        .append_pair("oauth_token", &temporary.token);
+    uri
+}
+
+/// Reads the verifier off the redirected URI, checking it answers the
+/// `temporary` credentials.
+pub fn verifier(redirected_uri: &Url, temporary: &Credentials) -> Result<String> {
+    let param = |name: &str| {
+        redirected_uri
+            .query_pairs()
+            .find_map(|(key, value)| (key == name).then(|| value.into_owned()))
+    };
+
+    if param("oauth_token").as_deref() != Some(temporary.token.as_str()) {
+        bail!("Authorization response does not answer the temporary credentials");
+    }
+
+    param("oauth_verifier")
+        .ok_or_else(|| anyhow!("Authorization response is missing oauth_verifier"))
+}
+
+/// Exchanges the authorized temporary credentials for the token
+/// credentials (RFC 5849 section 2.3), stored as a token response
+/// whose access token is the `oauth_token`.
+pub fn request_token_credentials(
+    account: &Account,
+    endpoint: &Url,
+    temporary: &Credentials,
+    verifier: &str,
+) -> Result<StoredToken> {
+    let token = Some((temporary.token.as_str(), &temporary.secret));
+    let verifier = [("oauth_verifier", verifier)];
+    let authorization = authorization(account, "POST", endpoint, &[], token, &verifier)?;
+
+    let res = http::post_authorized(account, endpoint, &authorization)?;
+    let params = response_params(&res.body, *res.status, "Request token credentials")?;
+    let credentials = credentials(params)?;
+
+    let mut token = StoredToken::new(Oauth20AccessTokenSuccessParams {
+        access_token: SecretString::from(credentials.token),
+        token_type: TOKEN_TYPE.to_owned(),
+        expires_in: None,
+        refresh_token: None,
+        scope: None,
+        issued_at: None,
+    });
+    token.oauth_token_secret = Some(credentials.secret);
+
+    Ok(token)
+}
+
+/// Builds the `Authorization` header value of a request to `url`,
+/// signed with the account consumer credentials and `token` when
+/// given.
+///
+/// The `form` parameters are the URL-encoded body of the request, if
+/// any, which the signature covers like the query; `protocol` adds
+/// `oauth_*` parameters of the flow (callback, verifier).
+pub fn authorization(
+    account: &Account,
+    method: &str,
+    url: &Url,
+    form: &[(String, String)],
+    token: Option<(&str, &SecretString)>,
+    protocol: &[(&str, &str)],
+) -> Result<String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let method_name = match account.oauth1_signature_method {
+        Oauth1SignatureMethodConfig::HmacSha1 => "HMAC-SHA1",
+        Oauth1SignatureMethodConfig::RsaSha1 => "RSA-SHA1",
+    };
+
+    let mut params = BTreeMap::from_iter([
+        ("oauth_consumer_key", account.client_id.clone()),
+        ("oauth_nonce", random_jti()),
+        ("oauth_signature_method", method_name.to_owned()),
+        ("oauth_timestamp", timestamp.to_string()),
+        ("oauth_version", "1.0".to_owned()),
+    ]);
+
+    if let Some((token, _)) = token {
+        params.insert("oauth_token", token.to_owned());
+    }
+
+    for (name, value) in protocol {
+        params.insert(name, (*value).to_owned());
+    }
+
+    let base = signature_base(method, url, form, &params);
+    let token_secret = token
+        .map(|(_, secret)| secret.expose_secret())
+        .unwrap_or("");
+    let signature = sign(account, &base, token_secret)?;
+    params.insert("oauth_signature", signature);
+
+    Ok(header(&params))
+}
+
+/// Signs the signature base string with the account signature method.
+fn sign(account: &Account, base: &str, token_secret: &str) -> Result<String> {
+    let signature = match account.oauth1_signature_method {
+        Oauth1SignatureMethodConfig::HmacSha1 => {
+            let Some(secret) = account.client_secret.clone() else {
+                bail!("Missing client-secret in the account config, required by hmac-sha1");
+            };
+            let secret = secret.get().context("Get client secret")?;
+            let key = format!(
+                "{}&{}",
+                encode(secret.expose_secret()),
+                encode(token_secret)
+            );
+            hmac_sha1(key.as_bytes(), base.as_bytes())
+        }
+        Oauth1SignatureMethodConfig::RsaSha1 => {
+            let key = SigningKey::<Sha1>::new(rsa_key(account)?);
+            key.try_sign(base.as_bytes())
+                .context("Sign OAuth 1.0a request")?
+                .to_vec()
+        }
+    };
+
+    Ok(BASE64_STANDARD.encode(signature))
+}
+
+/// Reads the `client-key` RSA private key of the RSA-SHA1 method.
+fn rsa_key(account: &Account) -> Result<RsaPrivateKey> {
+    let Some(path) = &account.client_key else {
+        bail!("Missing client-key in the account config, required by rsa-sha1");
+    };
+
+    let pem = fs::read_to_string(path)
+        .with_context(|| format!("Read client key from {}", path.display()))?;
+
+    RsaPrivateKey::from_pkcs8_pem(&pem).or_else(|_| {
+        RsaPrivateKey::from_pkcs1_pem(&pem)
+            .context("Parse client key as a PKCS#8 or PKCS#1 PEM private key")
+    })
+}
+
+/// The signature base string (RFC 5849 section 3.4.1): the method,
+/// the base string URI, and every parameter, query and form included,
+/// encoded then sorted.
+fn signature_base(
+    method: &str,
+    url: &Url,
+    form: &[(String, String)],
+    protocol: &BTreeMap<&str, String>,
+) -> String {
+    let mut params: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| (encode(&name), encode(&value)))
+        .chain(
+            form.iter()
+                .map(|(name, value)| (encode(name), encode(value))),
+        )
+        .chain(
+            protocol
+                .iter()
+                .map(|(name, value)| (encode(name), encode(value))),
+        )
+        .collect();
+    params.sort();
+
+    let params: Vec<String> = params
+        .into_iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+
+    // NOTE: the URL keeps its port only when it is not the scheme
+    // default, which `Url::port` already leaves out.
+    let host = url.host_str().unwrap_or("");
+    let uri = match url.port() {
+        Some(port) => format!("{}://{host}:{port}{}", url.scheme(), url.path()),
+        None => format!("{}://{host}{}", url.scheme(), url.path()),
+    };
+
+    format!(
+        "{}&{}&{}",
+        method.to_ascii_uppercase(),
+        encode(&uri),
+        encode(&params.join("&"))
+    )
+}
+
+/// Renders the protocol parameters into the `OAuth` header value
+/// (RFC 5849 section 3.5.1).
+fn header(params: &BTreeMap<&str, String>) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", encode(name), encode(value)))
+        .collect();
+
+    format!("{TOKEN_TYPE} {}", params.join(", "))
+}
+
+/// Percent-encodes everything but the unreserved characters (RFC 5849
+/// section 3.6), which the form encoding of the url crate does not.
+fn encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(byte as char)
+            }
+            byte => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    out
+}
+
+/// HMAC (RFC 2104) over SHA-1.
+fn hmac_sha1(key: &[u8], message: &[u8]) -> Vec<u8> {
+    // NOTE: HMAC takes keys of any length, hashing the longer ones.
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Parses a form-encoded credentials response, failing with the
+/// `oauth_problem` the server reported, if any.
+fn response_params(body: &[u8], status: u16, action: &str) -> Result<BTreeMap<String, String>> {
+    let params: BTreeMap<String, String> = form_urlencoded::parse(body).into_owned().collect();
+
+    if !(200..300).contains(&status) {
+        let err = anyhow!("{action} error (status {status})");
+        return Err(match params.get("oauth_problem") {
+            Some(problem) => anyhow!("{problem}").context(err),
+            None => err,
+        });
+    }
+
+    Ok(params)
+}
+
+/// Takes the token and its secret out of a credentials response.
+fn credentials(mut params: BTreeMap<String, String>) -> Result<Credentials> {
+    let Some(token) = params.remove("oauth_token") else {
+        bail!("Credentials response is missing oauth_token");
+    };
+    let Some(secret) = params.remove("oauth_token_secret") else {
+        bail!("Credentials response is missing oauth_token_secret");
+    };
+
+    Ok(Credentials {
+        token,
+        secret: SecretString::from(secret),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_sha1_matches_rfc_2202() {
+        let mac = hmac_sha1(b"Jefe", b"what do ya want for nothing?");
+        let hex: String = mac.iter().map(|b| format!("{b:02x}")).collect();
+        assert_eq!(hex, "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79");
+
+        let mac = hmac_sha1(
+            &[0xaa; 80],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+        );
+        let hex: String = mac.iter().map(|b| format!("{b:02x}")).collect();
+        assert_eq!(hex, "aa4ae5e15272d00e95705637ce8a3b55ed402112");
+    }
+
+    #[test]
+    fn signature_matches_the_oauth_core_example() {
+        let url =
+            Url::parse("http://photos.example.net/photos?file=vacation.jpg&size=original").unwrap();
+        let protocol = BTreeMap::from_iter([
+            ("oauth_consumer_key", "dpf43f3p2l4k3l03".to_owned()),
+            ("oauth_nonce", "kllo9940pd9333jh".to_owned()),
+            ("oauth_signature_method", "HMAC-SHA1".to_owned()),
+            ("oauth_timestamp", "1191242096".to_owned()),
+            ("oauth_token", "nnch734d00sl2jdk".to_owned()),
+            ("oauth_version", "1.0".to_owned()),
+        ]);
+
+        let base = signature_base("GET", &url, &[], &protocol);
+        assert_eq!(
+            base,
+            "GET&http%3A%2F%2Fphotos.example.net%2Fphotos&file%3Dvacation.jpg%26\
+             oauth_consumer_key%3Ddpf43f3p2l4k3l03%26oauth_nonce%3Dkllo9940pd9333jh%26\
+             oauth_signature_method%3DHMAC-SHA1%26oauth_timestamp%3D1191242096%26\
+             oauth_token%3Dnnch734d00sl2jdk%26oauth_version%3D1.0%26size%3Doriginal"
+        );
+
+        let signature = hmac_sha1(b"kd94hf93k423kf44&pfkkdhi9sl3r4s00", base.as_bytes());
+        assert_eq!(
+            BASE64_STANDARD.encode(signature),
+            "tR3+Ty81lMeYAr/Fid0kMTYa/WM="
+        );
+    }
+
+    #[test]
+    fn encoding_keeps_only_unreserved_characters() {
+        assert_eq!(encode("a b+c~d*"), "a%20b%2Bc~d%2A");
+        assert_eq!(encode("é"), "%C3%A9");
+    }
+}
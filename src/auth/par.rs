@@ -0,0 +1,99 @@
+//! Pushed authorization requests (RFC 9126).
+//!
+//! With `endpoints.pushed-authorization-request` set, the
+//! authorization code grant POSTs its authorization request
+//! parameters to the authorization server, authenticated like a token
+//! request, instead of handing them to the browser. The server answers
+//! a `request_uri` standing for them, and the authorization URI
+//! carries nothing else but the `client_id`.
+//!
+//! The pushed parameters are the query the authorization URI would
+//! have carried: plain ones, or the signed request object (RFC 9101)
+//! as `request` next to the few parameters OpenID Connect requires.
+//!
+//! Refs: <https://datatracker.ietf.org/doc/html/rfc9126>
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use url::Url;
+
+use io_oauth::rfc6749::{
+    auth_request::Oauth20AuthRequestParams, issue_access_token::Oauth20AccessTokenErrorParams,
+};
+
+use crate::{account::Account, auth::request_object, config::RequestObjectConfig, http};
+
+/// The successful response of the pushed authorization request
+/// endpoint. Its `expires_in` is left out: the URI is used at once.
+#[derive(Debug, Deserialize)]
+struct ParSuccessParams {
+    /// The reference to the pushed parameters.
+    request_uri: String,
+}
+
+/// Pushes `params` to the pushed authorization request `endpoint`,
+/// then builds the authorization URI referencing them.
+pub fn build_url(
+    account: &Account,
+    params: &Oauth20AuthRequestParams,
+    authorization_endpoint: &Url,
+    endpoint: &Url,
+) -> Result<Url> {
+    // NOTE: build against the bare endpoint, so the pushed form holds
+    // the request parameters only.
+    let mut bare = authorization_endpoint.clone();
+    bare.set_query(None);
+
+    let request = match account.request_object {
+        RequestObjectConfig::Off => params.build_url(&bare),
+        RequestObjectConfig::Sign => request_object::build_url(account, params, &bare)?,
+    };
+
+    let form: Vec<_> = request.query_pairs().into_owned().collect();
+    let form = form
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()));
+
+    let res = http::post_form(account, endpoint, form)?;
+    let body = res.body.as_slice();
+
+    if !res.status.is_success() {
+        let res = Oauth20AccessTokenErrorParams::try_from(body)
+            .context("Parse pushed authorization error response")
+            .with_context(|| format!("Pushed authorization endpoint answered {}", *res.status))?;
+
+        let err = anyhow!("Push authorization request error (code {:?})", res.error);
+        return Err(match (res.error_description, res.error_uri) {
+            (None, None) => err,
+            (Some(desc), None) => anyhow!("{desc}").context(err),
+            (None, Some(uri)) => anyhow!("{uri}").context(err),
+            (Some(desc), Some(uri)) => anyhow!("{desc}: {uri}").context(err),
+        });
+    }
+
+    let pushed: ParSuccessParams =
+        serde_json::from_slice(body).context("Parse pushed authorization response")?;
+
+    let mut uri = authorization_endpoint.clone();
+    uri.query_pairs_mut()
+        .append_pair("client_id", &account.client_id)
+        .append_pair("request_uri", &pushed.request_uri);
+
+    Ok(uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_request_uri_is_read_from_the_response() {
+        let body = br#"{"request_uri":"urn:ietf:params:oauth:request_uri:6esc_11ACC5bwc014ltc14eY22c","expires_in":60}"#;
+        let params: ParSuccessParams = serde_json::from_slice(body).unwrap();
+
+        assert_eq!(
+            params.request_uri,
+            "urn:ietf:params:oauth:request_uri:6esc_11ACC5bwc014ltc14eY22c"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! Resource owner password credentials grant (RFC 6749 section 4.3).
+//!
+//! Kept for legacy providers offering nothing else (older ADFS,
+//! Keycloak realms of service users): the client sends the user's own
+//! credentials to the token endpoint. OAuth 2.1 drops the grant, which
+//! `auth get` reminds at every run. io-oauth has no request for it, so
+//! it goes through [`http::post_form`].
+//!
+//! Refs: <https://datatracker.ietf.org/doc/html/rfc6749#section-4.3>
+
+use anyhow::{Context, Result, bail};
+use secrecy::ExposeSecret;
+
+use io_oauth::rfc6749::issue_access_token::Oauth20AccessTokenErrorParams;
+
+use crate::{account::Account, http, token::stored::StoredToken};
+
+/// Warning `auth get` prints at every run of the grant.
+pub const DEPRECATION_WARNING: &str = "Warning: the password grant is deprecated: OAuth 2.1 removes \
+it, and it hands your credentials to the client. Switch to another grant as soon as your \
+provider offers one.";
+
+/// Exchanges the account username and password for a token.
+pub fn request_access_token(
+    account: &Account,
+) -> Result<Result<StoredToken, Oauth20AccessTokenErrorParams>> {
+    let Some(token_endpoint) = &account.token_endpoint else {
+        bail!("Missing endpoints.token in the account config");
+    };
+    let Some(username) = &account.username else {
+        bail!("Missing username in the account config");
+    };
+    let Some(password) = account.password.clone() else {
+        bail!("Missing password in the account config");
+    };
+
+    let password = password.get().context("Get password")?;
+    let scope = account.scopes.join(" ");
+    let authorization_details = account.authorization_details()?;
+
+    let mut form = vec![
+        ("grant_type", "password"),
+        ("username", username.as_str()),
+        ("password", password.expose_secret()),
+        ("client_id", account.client_id.as_str()),
+    ];
+
+    if !scope.is_empty() {
+        form.push(("scope", scope.as_str()));
+    }
+
+    if let Some(details) = &authorization_details {
+        form.push(("authorization_details", details.as_str()));
+    }
+
+    http::parse_token_response(http::post_form(account, token_endpoint, form)?)
+}
@@ -0,0 +1,130 @@
+//! QR codes of the URIs the auth flows ask the user to open.
+//!
+//! Typing a long `verification_uri_complete` on a phone is what the
+//! device grant is meant to avoid: `auth get` renders the URI as a QR
+//! code in the terminal, and can export it as a PNG or SVG image for
+//! kiosk setups.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+use png::{BitDepth, ColorType, Encoder};
+use qrcode::{
+    Color, QrCode,
+    render::{svg, unicode::Dense1x2},
+};
+
+/// Size of one module of the exported PNG, in pixels.
+const PNG_MODULE_SIZE: usize = 8;
+
+/// Width of the quiet zone around the exported PNG, in modules.
+const PNG_QUIET_ZONE: usize = 4;
+
+/// Renders `data` as a QR code made of Unicode half blocks, two
+/// modules per character cell.
+///
+/// NOTE: light modules are drawn as blocks, the way `qrencode -t UTF8`
+/// does: terminals mostly paint light text on a dark background, and
+/// that layout keeps the code readable there.
+pub fn render(data: &str) -> Result<String> {
+    let code = encode(data)?;
+
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build())
+}
+
+/// Writes `data` as a QR code image at `path`, PNG or SVG after its
+/// extension.
+pub fn export(data: &str, path: &Path) -> Result<()> {
+    let code = encode(data)?;
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+
+    let image = match extension.as_deref() {
+        Some("svg") => code
+            .render::<svg::Color>()
+            .min_dimensions(256, 256)
+            .build()
+            .into_bytes(),
+        Some("png") => encode_png(&code)?,
+        _ => bail!(
+            "Cannot export QR code to {}: expected a .png or .svg file",
+            path.display()
+        ),
+    };
+
+    fs::write(path, image).with_context(|| format!("Write QR code to {}", path.display()))
+}
+
+fn encode(data: &str) -> Result<QrCode> {
+    QrCode::new(data).map_err(|err| anyhow!("Encode QR code error: {err}"))
+}
+
+/// Encodes the code as an 8-bit grayscale PNG, quiet zone included.
+fn encode_png(code: &QrCode) -> Result<Vec<u8>> {
+    let modules = code.width();
+    let colors = code.to_colors();
+    let side = (modules + 2 * PNG_QUIET_ZONE) * PNG_MODULE_SIZE;
+
+    let mut pixels = vec![u8::MAX; side * side];
+    for (i, color) in colors.iter().enumerate() {
+        if *color == Color::Light {
+            continue;
+        }
+
+        let x = (i % modules + PNG_QUIET_ZONE) * PNG_MODULE_SIZE;
+        let y = (i / modules + PNG_QUIET_ZONE) * PNG_MODULE_SIZE;
+
+        for row in y..y + PNG_MODULE_SIZE {
+            pixels[row * side + x..row * side + x + PNG_MODULE_SIZE].fill(0);
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = Encoder::new(&mut png, side as u32, side as u32);
+    encoder.set_color(ColorType::Grayscale);
+    encoder.set_depth(BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "https://example.com/device?user_code=WDJB-MJHT";
+
+    #[test]
+    fn terminal_code_is_made_of_half_blocks() {
+        let qr = render(URI).unwrap();
+        assert!(qr.lines().count() > 10);
+        assert!(
+            qr.chars()
+                .all(|c| " \u{2580}\u{2584}\u{2588}\n".contains(c))
+        );
+    }
+
+    #[test]
+    fn export_picks_the_format_after_the_extension() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let png = dir.path().join("code.png");
+        export(URI, &png).unwrap();
+        assert!(fs::read(&png).unwrap().starts_with(b"\x89PNG"));
+
+        let svg = dir.path().join("code.SVG");
+        export(URI, &svg).unwrap();
+        assert!(fs::read_to_string(&svg).unwrap().contains("<svg"));
+
+        assert!(export(URI, &dir.path().join("code.jpg")).is_err());
+    }
+}
@@ -0,0 +1,334 @@
+//! Loopback listener capturing the authorization redirect.
+//!
+//! Stands in for io-oauth's `await_redirect`, which answers the
+//! browser with a fixed placeholder before the code is even exchanged.
+//! This one keeps the connection open across the exchange, so the page
+//! the browser lands on can tell which account was authorized, with
+//! which scope, or what went wrong. The pages are the account's
+//! `redirection.success-page` and `redirection.error-page` templates,
+//! built-in ones standing in for those left unset.
+
+use std::{
+    borrow::Cow,
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+};
+
+use anyhow::{Context, Result, anyhow};
+use log::debug;
+use url::Url;
+
+use crate::{account::Account, config::PageConfig};
+
+/// Page answered when the token was issued.
+const SUCCESS_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Ortie</title></head>
+<body>
+<h1>Account $ACCOUNT authorized</h1>
+<p>Granted scope: $SCOPE</p>
+<p>You can close this tab and return to your terminal.</p>
+</body>
+</html>
+"#;
+
+/// Page answered when the authorization or the exchange failed.
+const ERROR_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Ortie</title></head>
+<body>
+<h1>Account $ACCOUNT not authorized</h1>
+<p>$ERROR</p>
+<p>$ERROR_DESCRIPTION</p>
+<p>Details are printed in your terminal.</p>
+</body>
+</html>
+"#;
+
+/// Script asking the browser to close the tab, injected when
+/// `redirection.auto-close` is set. Browsers only honour it for tabs a
+/// script opened, so the page text still has to stand on its own.
+const AUTO_CLOSE_SCRIPT: &str = "<script>window.close()</script>";
+
+/// A captured redirect whose browser connection is still open, waiting
+/// for the page.
+pub struct Redirect {
+    /// The URI the browser was redirected to, carrying `code` and
+    /// `state` (or the authorization error).
+    pub uri: Url,
+    stream: TcpStream,
+}
+
+/// Binds the loopback redirection URI and waits for the browser to
+/// land on it. Single-shot: the first request is the redirect.
+pub fn accept(redirect_uri: &Url) -> Result<Redirect> {
+    let scheme = redirect_uri.scheme();
+    let Some(host) = redirect_uri.host_str() else {
+        return Err(anyhow!("Redirection {redirect_uri} has no host"));
+    };
+    let Some(port) = redirect_uri.port_or_known_default() else {
+        return Err(anyhow!("Redirection {redirect_uri} has no port"));
+    };
+
+    let listener = TcpListener::bind((host, port))
+        .with_context(|| format!("Bind redirection listener on {host}:{port}"))?;
+    let (stream, _) = listener.accept()?;
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // NOTE: drain the request headers, so the browser is not answered
+    // before it finished talking.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let Some(path) = request_line.split_whitespace().nth(1) else {
+        return Err(anyhow!("Malformed redirection request: {request_line}"));
+    };
+
+    let uri = format!("{scheme}://{host}:{port}{path}")
+        .parse()
+        .map_err(|err| anyhow!("Malformed redirection request {request_line}: {err}"))?;
+
+    Ok(Redirect {
+        uri,
+        stream: reader.into_inner(),
+    })
+}
+
+impl Redirect {
+    /// Answers the browser with the page matching the flow `result`,
+    /// then closes the connection. The flow is over by then, so a
+    /// browser gone away is logged, never reported.
+    pub fn respond(mut self, account: &Account, result: &Result<()>) {
+        let (status, page) = match result {
+            Ok(()) => ("200 OK", render_success(account)),
+            Err(err) => ("400 Bad Request", render_error(account, &self.uri, err)),
+        };
+
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{page}",
+            page.len()
+        );
+
+        if let Err(err) = self.stream.write_all(response.as_bytes()) {
+            debug!("answer redirection page error: {err}");
+        }
+
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Renders the success page, exposing the granted scope (the requested
+/// one when the server echoed none).
+fn render_success(account: &Account) -> String {
+    let scope = match account
+        .token
+        .as_ref()
+        .and_then(|token| token.params.scope.clone())
+    {
+        Some(scope) => scope,
+        None => account.scopes.join(" "),
+    };
+
+    let template = load(account.redirection_success_page.as_ref(), SUCCESS_PAGE);
+
+    render(&template, account.redirection_auto_close, |key| match key {
+        "ACCOUNT" => Some(account.name.clone()),
+        "SCOPE" => Some(scope.clone()),
+        "ERROR" | "ERROR_DESCRIPTION" => Some(String::new()),
+        _ => None,
+    })
+}
+
+/// Renders the error page. An error the server sent back on the
+/// redirect is exposed as is; a failure of the exchange itself exposes
+/// the reported error and its causes.
+fn render_error(account: &Account, uri: &Url, err: &anyhow::Error) -> String {
+    let query_pair = |name: &str| {
+        uri.query_pairs()
+            .find_map(|(key, value)| (key == name).then(|| value.into_owned()))
+    };
+
+    let (error, description) = match query_pair("error") {
+        Some(error) => (error, query_pair("error_description").unwrap_or_default()),
+        None => {
+            let causes: Vec<String> = err.chain().skip(1).map(ToString::to_string).collect();
+            (err.to_string(), causes.join(": "))
+        }
+    };
+
+    let template = load(account.redirection_error_page.as_ref(), ERROR_PAGE);
+
+    render(&template, account.redirection_auto_close, |key| match key {
+        "ACCOUNT" => Some(account.name.clone()),
+        "SCOPE" => Some(account.scopes.join(" ")),
+        "ERROR" => Some(error.clone()),
+        "ERROR_DESCRIPTION" => Some(description.clone()),
+        _ => None,
+    })
+}
+
+/// Reads the configured template, falling back to the built-in page
+/// when none is set or the file cannot be read.
+fn load(page: Option<&PageConfig>, default: &'static str) -> Cow<'static, str> {
+    match page {
+        None => Cow::Borrowed(default),
+        Some(PageConfig::Html(html)) => Cow::Owned(html.clone()),
+        Some(PageConfig::Path(path)) => match fs::read_to_string(path) {
+            Ok(html) => Cow::Owned(html),
+            Err(err) => {
+                debug!("read redirection page {} error: {err}", path.display());
+                Cow::Borrowed(default)
+            }
+        },
+    }
+}
+
+/// Expands the `$VAR` placeholders of `template` through `get_var`,
+/// HTML-escaping every value since they partly come from the redirect
+/// the browser was handed, then injects the auto-close script.
+/// Unknown variables are left untouched.
+fn render(template: &str, auto_close: bool, get_var: impl Fn(&str) -> Option<String>) -> String {
+    let mut page =
+        shellexpand::env_with_context_no_errors(template, |key| get_var(key).map(escape_html))
+            .into_owned();
+
+    if auto_close {
+        match page.rfind("</body>") {
+            Some(pos) => page.insert_str(pos, AUTO_CLOSE_SCRIPT),
+            None => page.push_str(AUTO_CLOSE_SCRIPT),
+        }
+    }
+
+    page
+}
+
+/// Escapes the characters HTML reserves in text and attribute values.
+fn escape_html(value: String) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for ch in value.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            ch => out.push(ch),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, thread};
+
+    use pimalaya_config::toml::TomlConfig;
+
+    use crate::config::Config;
+
+    use super::*;
+
+    fn vars(key: &str) -> Option<String> {
+        match key {
+            "ACCOUNT" => Some("work".into()),
+            "ERROR_DESCRIPTION" => Some("<script>alert(1)</script>".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn placeholders_expand_escaped_and_unknown_ones_stay() {
+        let page = render("$ACCOUNT: $ERROR_DESCRIPTION ($UNKNOWN)", false, vars);
+        assert_eq!(
+            page,
+            "work: &lt;script&gt;alert(1)&lt;/script&gt; ($UNKNOWN)"
+        );
+    }
+
+    #[test]
+    fn auto_close_script_lands_before_the_body_end() {
+        let page = render("<body>$ACCOUNT</body>", true, vars);
+        assert_eq!(page, "<body>work<script>window.close()</script></body>");
+
+        let page = render("$ACCOUNT", true, vars);
+        assert_eq!(page, "work<script>window.close()</script>");
+    }
+
+    /// Sends the browser's redirect to the listener, answers it with
+    /// `result` and returns the raw response the browser read.
+    fn serve(account: &Account, result: &Result<()>) -> String {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let redirect_uri: Url = format!("http://127.0.0.1:{port}").parse().unwrap();
+
+        let browser = thread::spawn(move || {
+            loop {
+                let Ok(mut stream) = TcpStream::connect(("127.0.0.1", port)) else {
+                    thread::yield_now();
+                    continue;
+                };
+                stream
+                    .write_all(b"GET /?code=c&state=s HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n")
+                    .unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                break response;
+            }
+        });
+
+        let redirect = accept(&redirect_uri).unwrap();
+        assert_eq!(redirect.uri.query(), Some("code=c&state=s"));
+        redirect.respond(account, result);
+
+        browser.join().unwrap()
+    }
+
+    #[test]
+    fn the_browser_gets_its_page_once_the_flow_is_over() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(
+            br#"
+[accounts.work]
+client-id = "app-id"
+redirection.success-page.html = "<p>$ACCOUNT authorized</p>"
+redirection.error-page.html = "<p>$ERROR: $ERROR_DESCRIPTION</p>"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config::from_paths(&[file.path().to_path_buf()]).unwrap();
+        let account = Account::from(config.take_named_account("work").unwrap());
+
+        let response = serve(&account, &Ok(()));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.contains("Content-Length: 22\r\n"), "{response}");
+        assert!(
+            response.ends_with("\r\n\r\n<p>work authorized</p>"),
+            "{response}"
+        );
+
+        let err = anyhow::anyhow!("invalid_grant").context("Issue access token error");
+        let response = serve(&account, &Err(err));
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+            "{response}"
+        );
+        assert!(
+            response.ends_with("<p>Issue access token error: invalid_grant</p>"),
+            "{response}"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! JWT-secured authorization requests (RFC 9101).
+//!
+//! With `request-object = "sign"`, the authorization request
+//! parameters travel as the claims of a JWT signed with the account
+//! `client-key`, so nothing can be tampered with in the browser. The
+//! claims are the exact query io-oauth would have built, and the key
+//! and `x5t` header are the client assertion ones, signed RS256.
+//!
+//! The request object travels by value in the `request` parameter,
+//! of the authorization URI, or of the pushed authorization request
+//! (RFC 9126) when the account has an endpoint for it, which the
+//! authorization URI then references by its `request_uri`.
+//!
+//! Refs: <https://datatracker.ietf.org/doc/html/rfc9101>
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use rsa::{
+    RsaPrivateKey,
+    pkcs1::DecodeRsaPrivateKey,
+    pkcs1v15::SigningKey,
+    pkcs8::DecodePrivateKey,
+    signature::{SignatureEncoding, Signer},
+};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+use url::Url;
+
+use io_oauth::{
+    rfc6749::auth_request::Oauth20AuthRequestParams, rfc7523::assertion::Oauth20JwtBearerClaims,
+};
+
+use crate::{
+    account::Account,
+    auth::get::{client_certificate_x5t, client_key_pem, random_jti},
+};
+
+/// Lifetime of a request object: long enough for the user to reach
+/// the authorization endpoint, short enough to limit replay.
+const VALIDITY: Duration = Duration::from_secs(600);
+
+/// Media type of a request object (RFC 9101 §10.8), set as the `typ`
+/// header so the object cannot pass for another kind of JWT.
+const TYP: &str = "oauth-authz-req+jwt";
+
+/// Parameters whose value is JSON, carried as such in the request
+/// object rather than as the string the query needs.
+const JSON_PARAMS: [&str; 3] = ["authorization_details", "claims", "max_age"];
+
+/// Builds the authorization URI carrying `params` as a signed request
+/// object.
+///
+/// Only `response_type`, `client_id` and `scope` stay in the query
+/// next to `request`, as OpenID Connect requires them there, but the
+/// server reads them from the request object.
+pub fn build_url(
+    account: &Account,
+    params: &Oauth20AuthRequestParams,
+    endpoint: &Url,
+) -> Result<Url> {
+    let request = sign(account, params, endpoint)?;
+
+    let params = Oauth20AuthRequestParams {
+        client_id: params.client_id.clone(),
+        redirect_uri: None,
+        scope: params.scope.clone(),
+        state: None,
+        pkce_code_challenge: None,
+        extras: BTreeMap::from_iter([("request".into(), request.expose_secret().into())]),
+    };
+
+    Ok(params.build_url(endpoint))
+}
+
+/// Signs the query io-oauth builds from `params` into a request
+/// object.
+fn sign(
+    account: &Account,
+    params: &Oauth20AuthRequestParams,
+    endpoint: &Url,
+) -> Result<SecretString> {
+    let pem = client_key_pem(account)?;
+    let key = RsaPrivateKey::from_pkcs8_pem(&pem)
+        .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&pem))
+        .context("Parse client key as a PKCS#8 or PKCS#1 PEM private key")?;
+    let x5t = client_certificate_x5t(account)?;
+
+    // NOTE: build against the bare endpoint, so the query holds the
+    // request parameters only.
+    let mut bare = endpoint.clone();
+    bare.set_query(None);
+
+    let extra = query_claims(&params.build_url(&bare));
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    // NOTE: the audience is the authorization server, identified by
+    // its issuer, or by the origin of its authorization endpoint when
+    // the account does not know it.
+    let aud = match &account.issuer {
+        Some(issuer) => issuer.clone(),
+        None => endpoint.origin().ascii_serialization(),
+    };
+
+    let claims = Oauth20JwtBearerClaims {
+        iss: account.client_id.as_str().into(),
+        aud: aud.into(),
+        iat: Some(now),
+        exp: Some(now + VALIDITY.as_secs()),
+        jti: Some(random_jti().into()),
+        extra,
+        ..Default::default()
+    };
+
+    // NOTE: io-oauth signs with the `JWT` type only, so the compact
+    // JWS is assembled here, the way it does for client assertions.
+    let header = Header {
+        alg: "RS256",
+        typ: TYP,
+        x5t: x5t.as_deref(),
+    };
+
+    let mut jws = BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_string(&header)?);
+    jws.push('.');
+    jws.push_str(&BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_string(&claims)?));
+
+    let signature = SigningKey::<Sha256>::new(key)
+        .try_sign(jws.as_bytes())
+        .context("Sign request object")?;
+    jws.push('.');
+    jws.push_str(&BASE64_URL_SAFE_NO_PAD.encode(signature.to_bytes()));
+
+    Ok(jws.into())
+}
+
+/// The JOSE header of a request object.
+#[derive(Serialize)]
+struct Header<'a> {
+    alg: &'static str,
+    typ: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x5t: Option<&'a str>,
+}
+
+/// Turns the query parameters of `url` into request object claims.
+fn query_claims(url: &Url) -> BTreeMap<String, Value> {
+    url.query_pairs()
+        .map(|(name, value)| {
+            let value = match serde_json::from_str(&value) {
+                Ok(json) if JSON_PARAMS.contains(&name.as_ref()) => json,
+                _ => Value::String(value.into_owned()),
+            };
+            (name.into_owned(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn json_params_keep_their_type_in_the_claims() {
+        let params = Oauth20AuthRequestParams {
+            client_id: "app".into(),
+            redirect_uri: Some(Cow::Borrowed("http://127.0.0.1:8080")),
+            scope: ["openid".into()].into(),
+            state: None,
+            pkce_code_challenge: None,
+            extras: BTreeMap::from_iter([
+                ("max_age".into(), "0".into()),
+                ("login_hint".into(), "42".into()),
+                ("authorization_details".into(), r#"[{"type":"t"}]"#.into()),
+            ]),
+        };
+
+        let endpoint = Url::parse("https://idp.example.com/authorize").unwrap();
+        let claims = query_claims(&params.build_url(&endpoint));
+
+        assert_eq!(claims["max_age"], json!(0));
+        assert_eq!(claims["login_hint"], json!("42"));
+        assert_eq!(claims["authorization_details"], json!([{"type": "t"}]));
+        assert_eq!(claims["redirect_uri"], json!("http://127.0.0.1:8080"));
+    }
+}
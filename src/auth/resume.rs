@@ -1,11 +1,12 @@
 //! `auth resume` subcommand: complete an OAuth grant flow.
 
-use std::borrow::Cow;
+use std::{borrow::Cow, fmt};
 
 use anyhow::{Result, anyhow, bail};
 use clap::Parser;
 use log::debug;
 use pimalaya_cli::printer::Printer;
+use pimalaya_config::secret::Secret;
 use secrecy::SecretString;
 use serde::{
     Deserialize,
@@ -13,8 +14,6 @@ use serde::{
 };
 use url::Url;
 
-use pimalaya_config::secret::Secret;
-
 use io_oauth::{
     client::Oauth20ClientStd,
     rfc6749::{
@@ -28,30 +27,46 @@ use io_oauth::{
 
 use crate::{
     account::Account,
-    auth::get::{complete_device_token_poll, report_token_issued},
+    auth::{
+        ciba::{self, CibaAuthSuccessParams},
+        events::Events,
+        get::{complete_ciba_token_poll, complete_device_token_poll, report_token_issued},
+    },
     config::GrantConfig,
+    http::{self, Credential},
+    profile,
 };
 
 /// Resume an existing OAuth 2.0 grant flow.
 ///
 /// Completes the grant configured on the account: the redirected URI
-/// for the authorization code grant, or the device code for the
-/// device grant. Authorization-code-only flags (`--state`, `--pkce`,
-/// `--redirect-uri`) are rejected on device accounts. The client
-/// credentials grants complete in a single auth get and are rejected
-/// here.
+/// for the authorization code grant, the device code for the device
+/// grant, or the auth request id for the CIBA grant.
+/// Authorization-code-only flags (`--state`, `--pkce`,
+/// `--redirect-uri`) are rejected on device and CIBA accounts. The
+/// password and client credentials grants complete in a single auth
+/// get and are rejected here.
 #[derive(Debug, Parser)]
 pub struct AuthResumeCommand {
-    /// Redirected URI (authorization-code grant) or device code
-    /// (device grant).
+    /// Redirected URI (authorization-code grant), device code (device
+    /// grant) or auth request id (CIBA grant).
     ///
     /// For the authorization code grant this is the URI the browser
     /// was redirected to after consent, not the registered redirect
-    /// URI. For the device grant this is the `device_code` returned by
-    /// a non-interactive or `--json` auth get.
-    #[arg(value_name = "URI|DEVICE_CODE")]
+    /// URI. For the device and CIBA grants this is the `device_code`
+    /// or `auth_req_id` returned by a non-interactive or `--json` auth
+    /// get.
+    #[arg(value_name = "URI|DEVICE_CODE|AUTH_REQ_ID")]
     pub input: String,
 
+    /// Hand the redirected URI over to the waiting auth get.
+    ///
+    /// Used by the desktop entry `ortie scheme install` registers:
+    /// the browser hands private-use scheme redirections to it, and
+    /// the auth get still waiting on them completes the flow.
+    #[arg(long, conflicts_with_all = ["state", "pkce", "redirect_uri"])]
+    pub from_handler: bool,
+
     /// The state generated during the authorization flow initiation.
     ///
     /// Authorization-code grant only. If a state was generated during
@@ -76,19 +91,50 @@ pub struct AuthResumeCommand {
     /// during auth get, it must match here.
     #[arg(long, short, value_parser = uri_parser)]
     pub redirect_uri: Option<Url>,
+
+    /// Where the flow reports its progress when an `auth get --wait
+    /// --json` resumed it.
+    #[arg(skip)]
+    pub events: Events,
 }
 
 impl AuthResumeCommand {
+    /// Relays the redirected URI to the auth get waiting on it, which
+    /// holds the state and the PKCE verifier.
+    pub fn hand_over(self) -> Result<()> {
+        #[cfg(unix)]
+        return crate::auth::handler::hand_over(self.input.trim());
+
+        #[cfg(not(unix))]
+        bail!("The scheme handler hand-over is only available on unix");
+    }
+
     /// Completes the account's configured grant into a stored token.
     pub fn execute(self, printer: &mut impl Printer, account: &mut Account) -> Result<()> {
+        if self.from_handler {
+            return self.hand_over();
+        }
+
         if account.grant.is_client_credentials() {
             bail!("The client credentials grants complete in a single auth get, nothing to resume");
         }
 
+        if account.grant == GrantConfig::Password {
+            bail!("The password grant completes in a single auth get, nothing to resume");
+        }
+
+        if account.grant == GrantConfig::Oauth1 {
+            bail!("The oauth1 grant completes in a single auth get, nothing to resume");
+        }
+
         if account.grant == GrantConfig::Device {
             return self.execute_device(printer, account);
         }
 
+        if account.grant == GrantConfig::Ciba {
+            return self.execute_ciba(printer, account);
+        }
+
         let Some(token_endpoint) = account.token_endpoint.clone() else {
             bail!("Missing endpoints.token in the account config");
         };
@@ -97,6 +143,14 @@ impl AuthResumeCommand {
         let redirected_uri = Url::parse(self.input.trim())
             .map_err(|err| anyhow!("Invalid redirected URI: {err}"))?;
 
+        // NOTE: checked before the response is read, error responses
+        // included: a mixed-up response is not this server's to report.
+        verify_issuer(
+            account.issuer.as_deref(),
+            account.authorization_response_iss,
+            &redirected_uri,
+        )?;
+
         let code = match Oauth20AuthParams::from(&redirected_uri).validate(self.state.as_ref()) {
             Ok(code) => code,
             Err(Oauth20AuthParamsValidationError::Server(params)) => {
@@ -118,7 +172,9 @@ impl AuthResumeCommand {
             }
         };
 
-        let client_secret = account.client_secret.clone().map(Secret::get).transpose()?;
+        if let Some(uri) = &self.redirect_uri {
+            profile::check_redirect(account.profile, uri)?;
+        }
 
         let redirect_uri = self
             .redirect_uri
@@ -131,20 +187,44 @@ impl AuthResumeCommand {
                     .map(|uri| Cow::Owned(uri.to_string()))
             });
 
-        let mut client =
-            Oauth20ClientStd::connect(token_endpoint, &account.tls, account.client_id.clone())?;
-        client.client_secret = client_secret;
+        let client_secret = account.client_secret.clone().map(Secret::get).transpose()?;
+        let authorization_details = account.authorization_details()?;
 
-        let res = client.request_access_token(Oauth20AccessTokenRequestParams {
-            code,
-            redirect_uri,
-            client_id: account.client_id.as_str().into(),
-            client_secret: None,
-            pkce_code_verifier: self.pkce.as_ref().map(Cow::Borrowed),
-        })?;
+        // NOTE: the code is single-use, so it is never sent twice once
+        // the server may have received it.
+        let send = |client: &mut Oauth20ClientStd| {
+            let params = Oauth20AccessTokenRequestParams {
+                code: code.clone(),
+                redirect_uri: redirect_uri.clone(),
+                client_id: account.client_id.as_str().into(),
+                client_secret: None,
+                pkce_code_verifier: self.pkce.as_ref().map(Cow::Borrowed),
+            };
+
+            // NOTE: io-oauth's token request params carry no
+            // authorization details (RFC 9396) nor client assertion, so
+            // with either the form its serializer writes goes through
+            // the hand-made request, which appends them.
+            if authorization_details.is_some() || account.private_key_jwt() {
+                let mut form = params.to_form_url_encoded_serializer();
+
+                if let Some(details) = &authorization_details {
+                    form.append_pair("authorization_details", details);
+                }
+
+                http::post_body(client, account, &token_endpoint, form.finish())?;
+            } else {
+                client.client_secret = client_secret.clone();
+                client.request_access_token(params).map(drop)?;
+            }
+
+            Ok(())
+        };
+
+        let res = http::request_token(account, &token_endpoint, Credential::SingleUse, send)?;
 
         match res {
-            Ok(res) => report_token_issued(printer, account, &res),
+            Ok(res) => report_token_issued(printer, account, res, self.events),
             Err(res) => {
                 debug!("execute issue access token error hook");
                 account.execute_on_issue_error_hook(&res);
@@ -188,10 +268,89 @@ impl AuthResumeCommand {
             interval: 5,
         };
 
-        complete_device_token_poll(printer, account, &token_endpoint, &device)
+        complete_device_token_poll(printer, account, &token_endpoint, &device, self.events)
+    }
+
+    /// CIBA grant: treat `input` as the auth request id and poll.
+    fn execute_ciba(self, printer: &mut impl Printer, account: &mut Account) -> Result<()> {
+        if self.state.is_some() || self.pkce.is_some() || self.redirect_uri.is_some() {
+            bail!(
+                "The --state, --pkce and --redirect-uri flags are only valid \
+                 for the authorization-code grant"
+            );
+        }
+
+        let auth_req_id = self.input.trim();
+        if auth_req_id.is_empty() {
+            bail!("Missing auth request id");
+        }
+
+        let Some(token_endpoint) = account.token_endpoint.clone() else {
+            bail!("Missing endpoints.token in the account config");
+        };
+
+        // NOTE: bare auth request id, the device grant defaults for
+        // the poll loop.
+        let ciba = CibaAuthSuccessParams {
+            auth_req_id: auth_req_id.to_owned(),
+            expires_in: 1800,
+            interval: ciba::DEFAULT_INTERVAL,
+        };
+
+        complete_ciba_token_poll(printer, account, &token_endpoint, &ciba, self.events)
+    }
+}
+
+/// Checks the `iss` parameter of an authorization response against
+/// the account issuer (RFC 9207), against mix-up attacks.
+///
+/// Identifiers compare as plain strings, as the RFC requires. A
+/// response without `iss` passes unless the server is known to always
+/// send it, and one with `iss` passes when there is no issuer to
+/// compare it with.
+fn verify_issuer(issuer: Option<&str>, required: bool, redirected_uri: &Url) -> Result<()> {
+    let iss = redirected_uri
+        .query_pairs()
+        .find_map(|(name, value)| (name == "iss").then_some(value));
+
+    match (issuer, iss) {
+        (None, _) if required => {
+            bail!("Missing issuer in the account config, required by authorization-response-iss")
+        }
+        (Some(issuer), Some(iss)) if iss != issuer => Err(IssuerMismatch {
+            expected: issuer.to_owned(),
+            got: iss.into_owned(),
+        }
+        .into()),
+        (Some(issuer), None) if required => {
+            bail!("Authorization response is missing iss, expected {issuer}")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The refusal of an authorization response whose `iss` names
+/// another authorization server than the account `issuer`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct IssuerMismatch {
+    /// The account issuer.
+    pub expected: String,
+    /// The `iss` the response carried.
+    pub got: String,
+}
+
+impl fmt::Display for IssuerMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Authorization response issuer {} does not match {}",
+            self.got, self.expected
+        )
     }
 }
 
+impl std::error::Error for IssuerMismatch {}
+
 /// Clap value parser for URI arguments.
 pub fn uri_parser(url: &str) -> Result<Url, String> {
     Url::parse(url).map_err(|err| err.to_string())
@@ -225,4 +384,30 @@ mod tests {
         assert!(!err.contains(secret), "{err}");
         assert!(err.contains("Invalid 0x"), "{err}");
     }
+
+    #[test]
+    fn issuer_is_compared_verbatim_and_required_when_advertised() {
+        let issuer = Some("https://idp.example.com");
+        let uri = |query: &str| Url::parse(&format!("http://127.0.0.1/?code=c{query}")).unwrap();
+
+        let matching = uri("&iss=https%3A%2F%2Fidp.example.com");
+        assert!(verify_issuer(issuer, true, &matching).is_ok());
+
+        let slashed = uri("&iss=https%3A%2F%2Fidp.example.com%2F");
+        let err = verify_issuer(issuer, false, &slashed).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<IssuerMismatch>(),
+            Some(&IssuerMismatch {
+                expected: "https://idp.example.com".to_owned(),
+                got: "https://idp.example.com/".to_owned(),
+            })
+        );
+
+        assert!(verify_issuer(issuer, false, &uri("")).is_ok());
+        let err = verify_issuer(issuer, true, &uri("")).unwrap_err();
+        assert!(err.to_string().contains("missing iss"), "{err}");
+
+        assert!(verify_issuer(None, false, &slashed).is_ok());
+        assert!(verify_issuer(None, true, &matching).is_err());
+    }
 }
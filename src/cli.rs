@@ -23,7 +23,9 @@ use crate::{
     account::Account,
     auth::AuthCommand,
     config::Config,
+    profile,
     repl::ReplCommand,
+    scheme::SchemeCommand,
     token::TokenCommand,
     wizard::{self, CONFIG_SAMPLE_URL, ConfigureCommand},
 };
@@ -71,6 +73,9 @@ pub enum Command {
 
     Repl(ReplCommand),
 
+    #[command(subcommand)]
+    Scheme(SchemeCommand),
+
     #[command(alias = "mans")]
     Manuals(ManualCommand),
     Completions(CompletionCommand),
@@ -96,6 +101,7 @@ impl Command {
                 let account = take_account(printer, config_paths, account_name)?;
                 cmd.execute(printer, account)
             }
+            Self::Scheme(cmd) => cmd.execute(printer),
             Self::Manuals(cmd) => cmd.execute(printer, Cli::command()),
             Self::Completions(cmd) => cmd.execute(printer, Cli::command()),
         }
@@ -183,13 +189,16 @@ pub(crate) fn take_account(
         );
     }
 
-    let Some((_, account)) = config.take_account(account_name)? else {
+    let Some(account) = config.take_account(account_name)? else {
         bail!(
             "No default account found, name one with `-a <NAME>` or mark one with `default = true`"
         );
     };
 
-    Ok(Account::from(account))
+    let account = Account::from(account);
+    profile::check(&account)?;
+
+    Ok(account)
 }
 
 /// Path(s) to the TOML configuration file(s).
@@ -13,14 +13,12 @@
 //! Override with `-c, --config <PATH>`, repeated once per file: the
 //! first one is the base and the rest are deep-merged on top.
 
-use std::{collections::HashMap, fmt, path::PathBuf, process::Command};
+use std::{collections::HashMap, fmt, path::PathBuf, process::Command, time::Duration};
 
 use pimalaya_config::{command, secret::Secret, toml, toml::TomlConfig};
 use pimalaya_stream::tls::{Rustls, RustlsCrypto, Tls, TlsProvider};
-#[cfg(feature = "notify")]
-use serde::Serialize;
 use serde::{
-    Deserialize, Deserializer,
+    Deserialize, Deserializer, Serialize,
     de::{self, Visitor},
 };
 use url::Url;
@@ -31,6 +29,20 @@ use url::Url;
 pub struct Config {
     /// Accounts indexed by name, one per `[accounts.<name>]` block.
     pub accounts: HashMap<String, AccountConfig>,
+    /// Browser launcher of the accounts setting none of their own.
+    pub browser: Option<BrowserConfig>,
+}
+
+impl Config {
+    /// Hands the global settings down to `account` where it sets none
+    /// of its own.
+    fn inherit(&mut self, (name, mut account): (String, AccountConfig)) -> (String, AccountConfig) {
+        if account.browser.is_none() {
+            account.browser = self.browser.take();
+        }
+
+        (name, account)
+    }
 }
 
 impl TomlConfig for Config {
@@ -45,11 +57,13 @@ impl TomlConfig for Config {
             .accounts
             .iter()
             .find_map(|(name, account)| account.default.then(|| name.clone()))?;
-        self.accounts.remove_entry(&name)
+        let account = self.accounts.remove_entry(&name)?;
+        Some(self.inherit(account))
     }
 
     fn take_named_account(&mut self, name: &str) -> Option<(String, Self::Account)> {
-        self.accounts.remove_entry(name)
+        let account = self.accounts.remove_entry(name)?;
+        Some(self.inherit(account))
     }
 }
 
@@ -68,7 +82,8 @@ pub struct AccountConfig {
     pub client_secret: Option<Secret>,
     /// Path to the private key signing JWT client assertions
     /// (PKCS#8 or PKCS#1 PEM), used by `grant =
-    /// "client-credentials-jwt"`. Re-read at every mint.
+    /// "client-credentials-jwt"`, `client-auth = "private-key-jwt"`
+    /// and the request objects. Re-read at every mint.
     #[serde(default, deserialize_with = "opt_shell_expanded_path")]
     pub client_key: Option<PathBuf>,
     /// Path to the client certificate (PEM or DER) whose SHA-1
@@ -76,13 +91,48 @@ pub struct AccountConfig {
     /// Microsoft certificate credentials. Recomputed at every mint.
     #[serde(default, deserialize_with = "opt_shell_expanded_path")]
     pub client_certificate: Option<PathBuf>,
+    /// How the client authenticates to the authorization server.
+    #[serde(default)]
+    pub client_auth: ClientAuthConfig,
+    /// Resource owner username, used by `grant = "password"`.
+    pub username: Option<String>,
+    /// Resource owner password, used by `grant = "password"`.
+    pub password: Option<Secret>,
 
     /// OAuth 2.0 grant flow run by the auth commands.
     #[serde(default)]
     pub grant: GrantConfig,
+    /// Security profile the account must comply with, refusing to run
+    /// otherwise.
+    pub profile: Option<ProfileConfig>,
+    /// Issuer identifier of the authorization server, as its metadata
+    /// advertises it (RFC 8414), compared verbatim with the `iss`
+    /// parameter of authorization responses (RFC 9207).
+    pub issuer: Option<String>,
+    /// Whether the authorization server always sends `iss` in its
+    /// authorization responses, mirroring its
+    /// `authorization_response_iss_parameter_supported` metadata.
+    #[serde(default)]
+    pub authorization_response_iss: bool,
     /// Endpoints of the OAuth 2.0 authorization server.
     #[serde(default)]
     pub endpoints: EndpointsConfig,
+    /// Pages the redirection listener answers the browser with.
+    #[serde(default)]
+    pub redirection: RedirectionConfig,
+    /// Who the backchannel authentication request reaches, and what
+    /// it shows them.
+    #[serde(default)]
+    pub ciba: CibaConfig,
+    /// How the OAuth 1.0a requests are signed.
+    #[serde(default)]
+    pub oauth1: Oauth1Config,
+    /// How token requests are retried on transient failures.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Browser launcher of the auth commands, overriding the global
+    /// one.
+    pub browser: Option<BrowserConfig>,
     /// TLS provider used for the HTTPS connections.
     #[serde(default, deserialize_with = "tls")]
     pub tls: Tls,
@@ -90,16 +140,61 @@ pub struct AccountConfig {
     /// OAuth 2.0 scopes requested for the access token.
     #[serde(default)]
     pub scopes: Vec<String>,
+    /// Whether a token granted fewer scopes than requested fails
+    /// the command, instead of a warning.
+    #[serde(default)]
+    pub require_all_scopes: bool,
     /// PKCE posture of the authorization code grant.
     #[serde(default)]
     pub pkce: PkceConfig,
+    /// How the authorization code grant sends its authorization
+    /// request parameters (RFC 9101).
+    #[serde(default)]
+    pub request_object: RequestObjectConfig,
     /// Extra parameters forwarded verbatim to the authorization
     /// request query; keys are wire names, never kebab-renamed.
     #[serde(default)]
     pub extras: HashMap<String, String>,
+    /// Authentication context classes requested by default (RFC 9470),
+    /// most preferred first.
+    #[serde(default)]
+    pub acr_values: Vec<String>,
+    /// Rich authorization request details (RFC 9396), sent along the
+    /// scopes.
+    pub authorization_details: Option<AuthorizationDetailsConfig>,
     /// Whether `token show` refreshes an expired token by itself.
     #[serde(default)]
     pub auto_refresh: bool,
+    /// How long before its expiry a token is treated as expired, in
+    /// humantime syntax (`1m`, `5m 30s`).
+    #[serde(default, deserialize_with = "opt_duration")]
+    pub refresh_before: Option<Duration>,
+    /// Lifetime assumed for a token issued without `expires_in`, in
+    /// humantime syntax.
+    #[serde(default, deserialize_with = "opt_duration")]
+    pub default_expires_in: Option<Duration>,
+    /// Whether `token show` prints a token not yet expired when its
+    /// refresh fails, instead of failing.
+    #[serde(default)]
+    pub stale_on_error: bool,
+    /// How long a failed refresh is not retried when the stored token
+    /// is served stale, in humantime syntax.
+    #[serde(default, deserialize_with = "opt_duration")]
+    pub refresh_cooldown: Option<Duration>,
+    /// What a refresh token rejected with `invalid_grant` leads to.
+    #[serde(default)]
+    pub on_invalid_grant: InvalidGrantConfig,
+    /// What a token authorized under another configuration leads to.
+    #[serde(default)]
+    pub on_drift: DriftConfig,
+    /// Lifetime of a refresh token the server issues without
+    /// `refresh_token_expires_in`, in humantime syntax.
+    #[serde(default, deserialize_with = "opt_duration")]
+    pub refresh_token_lifetime: Option<Duration>,
+    /// How long before the refresh token expires the
+    /// on-refresh-token-expiring hook fires, in humantime syntax.
+    #[serde(default, deserialize_with = "opt_duration")]
+    pub refresh_token_expiring_before: Option<Duration>,
 
     /// Shell commands reading and writing the persisted token.
     pub storage: StoragesConfig,
@@ -109,7 +204,7 @@ pub struct AccountConfig {
 }
 
 /// OAuth 2.0 grant flow run by the auth commands.
-#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum GrantConfig {
     /// The authorization code grant (RFC 6749 section 4.1), the
@@ -126,6 +221,18 @@ pub enum GrantConfig {
     /// client assertion (RFC 7523 section 2.2), the Microsoft
     /// certificate credentials flow.
     ClientCredentialsJwt,
+    /// The OpenID Client Initiated Backchannel Authentication grant,
+    /// poll mode: the user approves on their own device, reached
+    /// through a login hint.
+    Ciba,
+    /// The resource owner password credentials grant (RFC 6749
+    /// section 4.3), headless with the user's own credentials, for
+    /// legacy providers offering nothing else. Dropped by OAuth 2.1.
+    Password,
+    /// OAuth 1.0a (RFC 5849), the three-legged flow of legacy APIs:
+    /// temporary credentials, browser authorization, token
+    /// credentials.
+    Oauth1,
 }
 
 impl GrantConfig {
@@ -137,6 +244,23 @@ impl GrantConfig {
     }
 }
 
+/// Displays the grant as the `grant` setting spells it.
+impl fmt::Display for GrantConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let grant = match self {
+            Self::AuthorizationCode => "authorization-code",
+            Self::Device => "device",
+            Self::ClientCredentials => "client-credentials",
+            Self::ClientCredentialsJwt => "client-credentials-jwt",
+            Self::Ciba => "ciba",
+            Self::Password => "password",
+            Self::Oauth1 => "oauth1",
+        };
+
+        write!(f, "{grant}")
+    }
+}
+
 /// Endpoints of the OAuth 2.0 authorization server.
 ///
 /// All optional at parse time: each command checks the endpoints it
@@ -151,12 +275,132 @@ pub struct EndpointsConfig {
     /// Device authorization endpoint (RFC 8628), used when
     /// `grant = "device"`.
     pub device_authorization: Option<Url>,
+    /// Backchannel authentication endpoint (OpenID CIBA), used when
+    /// `grant = "ciba"`.
+    pub backchannel_authentication: Option<Url>,
+    /// Pushed authorization request endpoint (RFC 9126): when set, the
+    /// authorization code grant pushes its request parameters there.
+    pub pushed_authorization_request: Option<Url>,
     /// Token endpoint, where grants and refreshes exchange for a
     /// token.
     pub token: Option<Url>,
     /// Redirection endpoint the provider sends the browser back to.
     /// When omitted, a random `http://127.0.0.1:<port>` is bound.
     pub redirection: Option<Url>,
+    /// Temporary credential request endpoint (RFC 5849), used when
+    /// `grant = "oauth1"`.
+    pub request_token: Option<Url>,
+    /// Token request endpoint (RFC 5849), used when `grant =
+    /// "oauth1"`.
+    pub access_token: Option<Url>,
+}
+
+/// The `oauth1` block: how the OAuth 1.0a requests are signed.
+///
+/// The consumer key is the `client-id`, the consumer secret the
+/// `client-secret`, and the RSA key the `client-key`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Oauth1Config {
+    /// Signature method of every request.
+    #[serde(default)]
+    pub signature_method: Oauth1SignatureMethodConfig,
+}
+
+/// OAuth 1.0a signature method (RFC 5849 section 3.4).
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Oauth1SignatureMethodConfig {
+    /// HMAC-SHA1 keyed with the consumer and token secrets.
+    #[default]
+    HmacSha1,
+    /// RSA-SHA1 (PKCS#1 v1.5) signed with the `client-key`.
+    RsaSha1,
+}
+
+/// The `retry` block: how the token requests of refreshes,
+/// re-acquisitions and code exchanges are sent again after a network
+/// error, a 5xx or a 429, with exponential backoff and jitter. Unset
+/// fields keep their defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Attempts in all, the first one included; 1 disables retries.
+    pub attempts: Option<usize>,
+    /// Delay before the first retry, doubled at each next one, in
+    /// humantime syntax.
+    #[serde(default, deserialize_with = "opt_duration")]
+    pub delay: Option<Duration>,
+    /// Longest delay between two attempts, in humantime syntax. A
+    /// `Retry-After` asking for longer ends the retries.
+    #[serde(default, deserialize_with = "opt_duration")]
+    pub max_delay: Option<Duration>,
+}
+
+/// The `redirection` block: what the browser lands on once the local
+/// listener captured the authorization redirect.
+///
+/// Both pages are templates expanding `$ACCOUNT`, `$SCOPE`, `$ERROR`
+/// and `$ERROR_DESCRIPTION`, rendered after the code is exchanged so
+/// they tell which account was authorized or what went wrong. Built-in
+/// pages stand in for the ones left unset.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RedirectionConfig {
+    /// Page answered when the token was issued.
+    pub success_page: Option<PageConfig>,
+    /// Page answered when the authorization or the exchange failed.
+    pub error_page: Option<PageConfig>,
+    /// Whether the pages ask the browser to close their tab.
+    #[serde(default)]
+    pub auto_close: bool,
+}
+
+/// The `ciba` block: the backchannel authentication request
+/// parameters of `grant = "ciba"`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CibaConfig {
+    /// The `login_hint` identifying the user to reach.
+    pub login_hint: Option<String>,
+    /// The `binding_message` shown on both devices, tying the
+    /// approval to the session that asked for it.
+    pub binding_message: Option<String>,
+}
+
+/// The `authorization-details` array (RFC 9396), inline or read from
+/// a JSON file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AuthorizationDetailsConfig {
+    /// Inline TOML tables, one per authorization details object.
+    Inline(Vec<serde_json::Value>),
+    /// Path to a JSON file holding the array, re-read at every
+    /// request.
+    #[serde(deserialize_with = "toml::shell_expanded_path")]
+    Path(PathBuf),
+}
+
+/// One redirection page template, inline or read from a file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PageConfig {
+    /// Inline HTML.
+    Html(String),
+    /// Path to an HTML file, re-read at every render.
+    #[serde(deserialize_with = "toml::shell_expanded_path")]
+    Path(PathBuf),
+}
+
+/// The `browser` block: how the auth commands open the authorization
+/// and verification URIs, instead of the system default browser.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BrowserConfig {
+    /// The shell command, as a `sh -c` string or an exec-style array,
+    /// where `$URL` stands for the URI to open.
+    #[serde(alias = "cmd", with = "command")]
+    pub command: Command,
 }
 
 /// PKCE posture of the authorization code grant.
@@ -204,6 +448,78 @@ impl<'de> Deserialize<'de> for PkceConfig {
     }
 }
 
+/// Security profile an account is held to, on top of what its server
+/// enforces.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum ProfileConfig {
+    /// OAuth 2.1: S256 PKCE, exact redirects, no password grant.
+    #[serde(rename = "oauth2.1")]
+    Oauth21,
+    /// FAPI 2.0: OAuth 2.1, plus pushed authorization requests and
+    /// private key JWT client authentication.
+    #[serde(rename = "fapi2")]
+    Fapi2,
+}
+
+/// How the client authenticates its requests to the authorization
+/// server, the client credentials JWT grant aside.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClientAuthConfig {
+    /// HTTP Basic with the `client-secret` when set, the client id
+    /// alone in the form otherwise, the default.
+    #[default]
+    ClientSecret,
+    /// A JWT client assertion (RFC 7523) signed with the `client-key`,
+    /// the `private_key_jwt` method of OpenID Connect.
+    PrivateKeyJwt,
+}
+
+/// How the authorization request parameters travel to the
+/// authorization endpoint.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RequestObjectConfig {
+    /// Plain query parameters, the default.
+    #[default]
+    Off,
+    /// A request object (RFC 9101) signed with the `client-key` like
+    /// the client assertions, sent as the `request` parameter, of the
+    /// pushed authorization request when the account has one.
+    Sign,
+}
+
+/// What a refresh token rejected with `invalid_grant`, revoked or
+/// expired, leads to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InvalidGrantConfig {
+    /// The refresh fails, the default.
+    #[default]
+    Fail,
+    /// Interactive terminals run the account grant again on the spot;
+    /// other runs notify, as with `Notify`.
+    Reauth,
+    /// The on-invalid-grant hook fires and the stored token is marked
+    /// as needing a new authorization.
+    Notify,
+}
+
+/// What a stored token authorized under another account
+/// configuration (client, token endpoint, scopes, resources or grant)
+/// leads to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DriftConfig {
+    /// `token show` warns on stderr and prints the token, the default.
+    #[default]
+    Warn,
+    /// `token show` authorizes again first: the client credentials
+    /// grants re-acquire, interactive terminals run the account grant,
+    /// other runs fail.
+    Reauth,
+}
+
 /// The `storage` block: how the token is persisted.
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
@@ -227,12 +543,23 @@ pub struct StorageConfig {
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct HooksConfig {
+    /// Hook fired when a flow needs the user: an authorization URI to
+    /// open, or a device code to enter.
+    #[serde(default)]
+    pub on_authorize: HookConfig,
     /// Hooks fired when a new access token is issued.
     #[serde(default)]
     pub on_issue: HookStatusConfig,
     /// Hooks fired when the access token is refreshed.
     #[serde(default)]
     pub on_refresh: HookStatusConfig,
+    /// Hook fired when the server rejects the refresh token, and
+    /// `on-invalid-grant` does not re-authorize on the spot.
+    #[serde(default)]
+    pub on_invalid_grant: HookConfig,
+    /// Hook fired once per refresh token, when it is about to expire.
+    #[serde(default)]
+    pub on_refresh_token_expiring: HookConfig,
 }
 
 /// Hooks of one event, split by outcome.
@@ -285,6 +612,13 @@ fn opt_shell_expanded_path<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Pa
     toml::shell_expanded_path(de).map(Some)
 }
 
+fn opt_duration<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Duration>, D::Error> {
+    let duration = String::deserialize(de)?;
+    humantime::parse_duration(&duration)
+        .map(Some)
+        .map_err(de::Error::custom)
+}
+
 /// TLS provider selector, converted into the pimalaya-stream config.
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -328,7 +662,10 @@ fn tls<'de, D: Deserializer<'de>>(d: D) -> Result<Tls, D::Error> {
 
 #[cfg(test)]
 mod tests {
-    use std::{io::Write, path::PathBuf};
+    use std::{
+        io::Write,
+        path::{Path, PathBuf},
+    };
 
     use super::*;
 
@@ -365,6 +702,42 @@ storage.write.command = ["tee", "token.json"]
         );
     }
 
+    #[test]
+    fn refresh_windows_parse_as_humantime_durations() {
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+refresh-before = "5m"
+default-expires-in = "1h 30m"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        );
+
+        assert_eq!(account.refresh_before, Some(Duration::from_secs(300)));
+        assert_eq!(account.default_expires_in, Some(Duration::from_secs(5400)));
+    }
+
+    #[test]
+    fn retry_parses_with_humantime_delays() {
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+retry.attempts = 5
+retry.delay = "250ms"
+retry.max-delay = "1m"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        );
+
+        assert_eq!(account.retry.attempts, Some(5));
+        assert_eq!(account.retry.delay, Some(Duration::from_millis(250)));
+        assert_eq!(account.retry.max_delay, Some(Duration::from_secs(60)));
+    }
+
     #[test]
     fn client_credentials_jwt_account_parses() {
         let account = parse(
@@ -394,6 +767,113 @@ storage.write.command = ["tee", "token.json"]
         );
     }
 
+    #[test]
+    fn redirection_pages_parse_inline_and_from_a_file() {
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+redirection.success-page.html = "<p>$ACCOUNT authorized</p>"
+redirection.error-page.path = "/etc/ortie/error.html"
+redirection.auto-close = true
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        );
+
+        assert!(matches!(
+            account.redirection.success_page,
+            Some(PageConfig::Html(html)) if html == "<p>$ACCOUNT authorized</p>"
+        ));
+        assert!(matches!(
+            account.redirection.error_page,
+            Some(PageConfig::Path(path)) if path == Path::new("/etc/ortie/error.html")
+        ));
+        assert!(account.redirection.auto_close);
+    }
+
+    #[test]
+    fn accounts_inherit_the_global_browser_unless_they_set_one() {
+        let toml = r#"
+browser.command = ["firefox", "-P", "work", "$URL"]
+
+[accounts.test]
+client-id = "app-id"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#;
+
+        let browser = parse(toml).browser.unwrap().command;
+        assert_eq!(browser.get_program(), "firefox");
+
+        let account = parse(&format!(
+            "{toml}browser.command = \"firefox --private-window \\\"$URL\\\"\"\n"
+        ));
+        let browser = account.browser.unwrap().command;
+        let args: Vec<_> = browser.get_args().collect();
+        assert_eq!(args.last().unwrap(), &"firefox --private-window \"$URL\"");
+    }
+
+    #[test]
+    fn password_account_parses() {
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+grant = "password"
+username = "svc-backup"
+password.command = ["pass", "show", "adfs/svc-backup"]
+endpoints.token = "https://adfs.example.com/adfs/oauth2/token"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        );
+
+        assert_eq!(account.grant, GrantConfig::Password);
+        assert!(!account.grant.is_client_credentials());
+        assert_eq!(account.username.as_deref(), Some("svc-backup"));
+        assert!(account.password.is_some());
+    }
+
+    #[test]
+    fn authorization_details_parse_inline_or_as_a_path() {
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+
+[[accounts.test.authorization-details]]
+type = "payment_initiation"
+actions = ["initiate"]
+instructedAmount = { currency = "EUR", amount = "123.50" }
+"#,
+        );
+
+        let Some(AuthorizationDetailsConfig::Inline(details)) = account.authorization_details
+        else {
+            panic!("expected inline authorization details");
+        };
+        assert_eq!(details[0]["type"], "payment_initiation");
+        assert_eq!(details[0]["instructedAmount"]["amount"], "123.50");
+
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+authorization-details = "/etc/ortie/details.json"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        );
+
+        assert!(matches!(
+            account.authorization_details,
+            Some(AuthorizationDetailsConfig::Path(path)) if path == Path::new("/etc/ortie/details.json")
+        ));
+    }
+
     #[test]
     fn interactive_grants_are_not_client_credentials() {
         assert!(!GrantConfig::AuthorizationCode.is_client_credentials());
@@ -0,0 +1,648 @@
+//! Form POSTs io-oauth has no coroutine for.
+//!
+//! io-oauth drives every request it knows through Oauth20ClientStd,
+//! but its parameter structs are closed: the device authorization
+//! request, for one, carries no extras. Those requests go through
+//! [`post_form`] instead, which reuses the client's connection (TLS
+//! provider included) and authentication, and io-http's HTTP/1.1
+//! coroutine, around a form Ortie composes itself. The caller parses
+//! the response with the io-oauth params types.
+//!
+//! The OAuth 1.0a requests carry no form at all: [`post_authorized`]
+//! sends them with the signed `Authorization` header in place of the
+//! client authentication.
+//!
+//! The wizard reads the authorization server metadata members
+//! io-pim-discovery drops through [`get`].
+//!
+//! Token requests go through [`request_token`], which retries them
+//! around the io-oauth client call over a recorded stream, and parses
+//! the response read back into a [`StoredToken`] so the members
+//! io-oauth drops survive. Requests io-oauth knows but whose params
+//! lack a parameter go through [`post_body`] there, over the client
+//! already connected for them, with the form its params serializer
+//! wrote and the missing parameter appended.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    mem,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, anyhow};
+use humantime::format_duration;
+use io_http::{
+    coroutine::{HttpCoroutine, HttpCoroutineState},
+    rfc7617::basic::HttpAuthBasic,
+    rfc9110::{
+        request::HttpRequest,
+        response::HttpResponse,
+        send::{HttpSendOutput, HttpSendYield},
+    },
+    rfc9112::send::Http11Send,
+};
+use io_oauth::{
+    client::{Oauth20ClientStd, Oauth20ClientStdError, Oauth20Stream},
+    rfc6749::{
+        issue_access_token::{
+            Oauth20AccessTokenErrorCode, Oauth20AccessTokenErrorParams, parse_http_date,
+        },
+        state::Oauth20State,
+    },
+};
+use log::{debug, trace};
+use pimalaya_config::secret::Secret;
+use pimalaya_stream::tls::Tls;
+use secrecy::ExposeSecret;
+use url::{Url, form_urlencoded::Serializer};
+
+use crate::{account::Account, auth::get, token::stored::StoredToken};
+
+/// Size of the buffer the response is read through.
+const READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// The `client_assertion_type` of the JWT client assertions (RFC 7523).
+const JWT_BEARER_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// POSTs `form` URL-encoded to `endpoint` over a fresh connection,
+/// authenticated with HTTP Basic when the account has a client secret
+/// (as io-oauth does) or with a client assertion (see [`post_body`]),
+/// and returns the response whatever its status.
+pub fn post_form<'a>(
+    account: &Account,
+    endpoint: &Url,
+    form: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<HttpResponse> {
+    let mut client =
+        Oauth20ClientStd::connect(endpoint.clone(), &account.tls, account.client_id.clone())?;
+
+    let body = Serializer::new(String::new()).extend_pairs(form).finish();
+    post_body(&mut client, account, endpoint, body)
+}
+
+/// POSTs an already URL-encoded form `body` to `endpoint` over the
+/// `client` connection, authenticated the way [`post_form`] does.
+///
+/// A `client-auth = "private-key-jwt"` account authenticates with a
+/// client assertion appended to the form instead (RFC 7523), which
+/// io-oauth only sends on the client credentials JWT grant.
+pub fn post_body(
+    client: &mut Oauth20ClientStd,
+    account: &Account,
+    endpoint: &Url,
+    mut body: String,
+) -> Result<HttpResponse> {
+    if account.private_key_jwt() {
+        let assertion = get::client_assertion(client, account)?;
+
+        // NOTE: the whole body is the form, so pairs follow its own
+        // after a `&`.
+        body = Serializer::for_suffix(body, 0)
+            .append_pair("client_assertion_type", JWT_BEARER_ASSERTION_TYPE)
+            .append_pair("client_assertion", assertion.expose_secret())
+            .finish();
+    }
+
+    let host = endpoint.host_str().unwrap_or("");
+    let port = endpoint.port_or_known_default().unwrap_or(0);
+
+    let mut request = HttpRequest {
+        method: "POST".into(),
+        url: endpoint.clone(),
+        headers: Vec::new(),
+        body: Vec::new(),
+    }
+    .header("Host", format!("{host}:{port}"))
+    .header("Content-Type", "application/x-www-form-urlencoded")
+    .body(body.into_bytes());
+
+    // NOTE: the assertion authenticates the client, a Basic header
+    // next to it would be a second method the server must refuse.
+    let secret = match account.private_key_jwt() {
+        true => None,
+        false => account.client_secret.clone().map(Secret::get).transpose()?,
+    };
+
+    if let Some(secret) = secret {
+        let creds = HttpAuthBasic::new(account.client_id.clone(), secret.expose_secret());
+        request = request.header("Authorization", creds.to_authorization());
+    }
+
+    debug!("send form request to {endpoint}");
+    send(client, endpoint, request)
+}
+
+/// POSTs an empty body to `endpoint` over a fresh connection,
+/// authenticated by the `authorization` header alone: the OAuth 1.0a
+/// requests, whose parameters all ride the signed header.
+pub fn post_authorized(
+    account: &Account,
+    endpoint: &Url,
+    authorization: &str,
+) -> Result<HttpResponse> {
+    let mut client =
+        Oauth20ClientStd::connect(endpoint.clone(), &account.tls, account.client_id.clone())?;
+
+    let host = endpoint.host_str().unwrap_or("");
+    let port = endpoint.port_or_known_default().unwrap_or(0);
+
+    let request = HttpRequest {
+        method: "POST".into(),
+        url: endpoint.clone(),
+        headers: Vec::new(),
+        body: Vec::new(),
+    }
+    .header("Host", format!("{host}:{port}"))
+    .header("Content-Type", "application/x-www-form-urlencoded")
+    .header("Authorization", authorization);
+
+    debug!("send authorized request to {endpoint}");
+    send(&mut client, endpoint, request)
+}
+
+/// GETs `url` over a fresh connection and returns the response
+/// whatever its status: the documents io-pim-discovery parses into
+/// closed structs, read again for the members they drop.
+pub fn get(url: &Url, tls: &Tls) -> Result<HttpResponse> {
+    // NOTE: the client only lends its connection, no client id rides
+    // along.
+    let mut client = Oauth20ClientStd::connect(url.clone(), tls, String::new())?;
+
+    let host = url.host_str().unwrap_or("");
+    let port = url.port_or_known_default().unwrap_or(0);
+
+    let request = HttpRequest {
+        method: "GET".into(),
+        url: url.clone(),
+        headers: Vec::new(),
+        body: Vec::new(),
+    }
+    .header("Host", format!("{host}:{port}"))
+    .header("Accept", "application/json");
+
+    debug!("send request to {url}");
+    send(&mut client, url, request)
+}
+
+/// Sends `request` over the `client` connection and returns the
+/// response whatever its status.
+fn send(
+    client: &mut Oauth20ClientStd,
+    endpoint: &Url,
+    request: HttpRequest,
+) -> Result<HttpResponse> {
+    let mut send = Http11Send::new(request);
+    let mut buf = [0u8; READ_BUFFER_SIZE];
+    let mut arg: Option<&[u8]> = None;
+
+    loop {
+        match send.resume(arg.take()) {
+            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. })) => {
+                trace!("status: {}", *response.status);
+                break Ok(response);
+            }
+            HttpCoroutineState::Complete(Err(err)) => {
+                break Err(anyhow!(err).context(format!("Send request to {endpoint}")));
+            }
+            HttpCoroutineState::Yielded(HttpSendYield::WantsRead) => {
+                let n = client.stream.read(&mut buf)?;
+                arg = Some(&buf[..n]);
+            }
+            HttpCoroutineState::Yielded(HttpSendYield::WantsWrite(bytes)) => {
+                client.stream.write_all(&bytes)?;
+            }
+            HttpCoroutineState::Yielded(HttpSendYield::WantsRedirect { url, response, .. }) => {
+                let code = *response.status;
+                break Err(anyhow!("Unexpected redirection {code} to {url}"));
+            }
+        }
+    }
+}
+
+/// Whether a token request may be sent again once it may have
+/// reached the server.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Credential {
+    /// Nothing the server consumes: the client credentials grants,
+    /// resent after any network failure.
+    Reusable,
+    /// A single-use authorization code, device code or rotating
+    /// refresh token: resent only when the connection failed, or when
+    /// the server answered a transient status, since a replay the
+    /// server already spent ends in `invalid_grant`, and a reused
+    /// refresh token may get its whole family revoked.
+    SingleUse,
+}
+
+/// Runs a token request against `endpoint` and parses the response,
+/// success into a [`StoredToken`] keeping every member Ortie stores.
+///
+/// `send` issues the request over the connected client, through its
+/// io-oauth call (or [`post_body`] when the params lack a parameter).
+/// Its stream is recorded, so the response is read back whole
+/// whatever io-oauth made of it, which is dropped: its status and
+/// headers decide the retries, and its body keeps the members
+/// io-oauth drops.
+///
+/// Transient failures are retried per the account `retry` policy: a
+/// network failure (after the request was sent only for a
+/// [`Credential::Reusable`] one), or a 5xx or 429 answer not carrying
+/// `invalid_grant` or `invalid_client`. Each attempt connects afresh
+/// and runs `send` again, so a JWT client assertion is minted anew
+/// rather than replayed. The delay doubles from `retry.delay` up to
+/// `retry.max-delay`, jittered, unless the server sends a
+/// `Retry-After`, which ends the retries when it asks for longer than
+/// `retry.max-delay`.
+pub fn request_token(
+    account: &Account,
+    endpoint: &Url,
+    credential: Credential,
+    mut send: impl FnMut(&mut Oauth20ClientStd) -> Result<()>,
+) -> Result<Result<StoredToken, Oauth20AccessTokenErrorParams>> {
+    let attempts = account.retry_attempts;
+    let mut attempt = 1;
+
+    loop {
+        debug!("send token request to {endpoint} (attempt {attempt}/{attempts})");
+
+        let transient = match try_request_token(account, endpoint, credential, &mut send)? {
+            Ok(res) => return parse_token_response(res),
+            Err(transient) => transient,
+        };
+
+        let delay = match &transient {
+            Transient::Network(err) => {
+                debug!("token request failed: {err}");
+                None
+            }
+            Transient::Status(res) => {
+                debug!("token request answered {}", *res.status);
+                retry_after(res)
+            }
+        };
+
+        if attempt >= attempts {
+            debug!("give up token request after {attempt} attempts");
+            return transient.give_up(attempt);
+        }
+
+        if let Some(delay) = delay
+            && delay > account.retry_max_delay
+        {
+            debug!(
+                "give up token request, server asks to retry in {}",
+                format_duration(delay)
+            );
+            return transient.give_up(attempt);
+        }
+
+        let delay = delay.unwrap_or_else(|| backoff(account, attempt));
+        debug!("retry token request in {}", format_duration(delay));
+        thread::sleep(delay);
+
+        attempt += 1;
+    }
+}
+
+/// A token request failure worth another attempt.
+enum Transient {
+    /// The connection failed, or broke before the response came.
+    Network(anyhow::Error),
+    /// The server answered 5xx or 429.
+    Status(HttpResponse),
+}
+
+impl Transient {
+    /// The outcome once no attempt is left: the network error, or the
+    /// last answer parsed like any other, failures marked
+    /// [`Unanswered`].
+    fn give_up(
+        self,
+        attempts: usize,
+    ) -> Result<Result<StoredToken, Oauth20AccessTokenErrorParams>> {
+        let res = match self {
+            Self::Network(err) => Err(err),
+            Self::Status(res) => parse_token_response(res),
+        };
+
+        res.map_err(|err| err.context(Unanswered { attempts }))
+    }
+}
+
+/// The failure of a token request the endpoint gave no usable answer
+/// to, the connection failing or a transient status outlasting the
+/// retries. Found in the error chain, it tells those apart from the
+/// local failures composing the request.
+#[derive(Debug)]
+pub struct Unanswered {
+    /// Number of attempts made.
+    pub attempts: usize,
+}
+
+impl fmt::Display for Unanswered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Token request failed after {} attempt(s)", self.attempts)
+    }
+}
+
+/// Sends one attempt of a token request, telling transient failures
+/// apart. A failure before anything was sent is final, as is a
+/// broken connection after a [`Credential::SingleUse`] request was
+/// sent.
+fn try_request_token(
+    account: &Account,
+    endpoint: &Url,
+    credential: Credential,
+    send: &mut impl FnMut(&mut Oauth20ClientStd) -> Result<()>,
+) -> Result<Result<HttpResponse, Transient>> {
+    let connect =
+        Oauth20ClientStd::connect(endpoint.clone(), &account.tls, account.client_id.clone());
+
+    let mut client = match connect {
+        Ok(client) => client,
+        Err(err @ (Oauth20ClientStdError::Io(_) | Oauth20ClientStdError::Tls(_))) => {
+            return Ok(Err(Transient::Network(err.into())));
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let transcript = Arc::new(Mutex::new(Transcript::default()));
+    let stream = mem::replace(&mut client.stream, Box::new(io::empty()));
+    client.set_stream(Recorded {
+        inner: stream,
+        transcript: Arc::clone(&transcript),
+    });
+
+    let sent = send(&mut client);
+    let transcript = mem::take(&mut *transcript.lock().unwrap_or_else(|err| err.into_inner()));
+
+    // NOTE: a complete answer wins over the outcome of `send`: io-oauth
+    // fails to parse a 5xx page the retries still want to see.
+    if let Some(res) = transcript.response() {
+        return match is_transient(&res) {
+            true => Ok(Err(Transient::Status(res))),
+            false => Ok(Ok(res)),
+        };
+    }
+
+    let err = match sent {
+        Ok(()) => anyhow!("Token endpoint closed the connection without answering"),
+        Err(err) => err,
+    };
+
+    if !transcript.failed && !transcript.sent {
+        return Err(err);
+    }
+
+    if transcript.sent && credential == Credential::SingleUse {
+        let err = err.context("Token request not retried, the server may have received it");
+        return Err(err.context(Unanswered { attempts: 1 }));
+    }
+
+    Ok(Err(Transient::Network(err)))
+}
+
+/// What went through a recorded connection.
+#[derive(Default)]
+struct Transcript {
+    /// Whether any byte of the request was written.
+    sent: bool,
+    /// Whether reading or writing failed.
+    failed: bool,
+    /// The bytes read back.
+    received: Vec<u8>,
+}
+
+impl Transcript {
+    /// The response read back, `None` unless it came whole.
+    fn response(&self) -> Option<HttpResponse> {
+        read_response(&self.received)
+    }
+}
+
+/// A client stream recording what goes through it.
+struct Recorded {
+    inner: Box<dyn Oauth20Stream>,
+    transcript: Arc<Mutex<Transcript>>,
+}
+
+impl Recorded {
+    fn record(&self, f: impl FnOnce(&mut Transcript)) {
+        f(&mut self
+            .transcript
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()));
+    }
+}
+
+impl Read for Recorded {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let res = self.inner.read(buf);
+
+        self.record(|transcript| match &res {
+            Ok(n) => transcript.received.extend_from_slice(&buf[..*n]),
+            Err(_) => transcript.failed = true,
+        });
+
+        res
+    }
+}
+
+impl Write for Recorded {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let res = self.inner.write(buf);
+
+        self.record(|transcript| match &res {
+            Ok(0) => (),
+            Ok(_) => transcript.sent = true,
+            Err(_) => transcript.failed = true,
+        });
+
+        res
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Parses raw response bytes through io-http's HTTP/1.1 coroutine,
+/// `None` unless they hold a whole response.
+fn read_response(raw: &[u8]) -> Option<HttpResponse> {
+    let request = HttpRequest {
+        method: "POST".into(),
+        url: Url::parse("http://localhost/").ok()?,
+        headers: Vec::new(),
+        body: Vec::new(),
+    };
+
+    let mut send = Http11Send::new(request);
+    let mut arg = None;
+    let mut chunks = [raw, &[]].into_iter();
+
+    loop {
+        match send.resume(arg.take()) {
+            HttpCoroutineState::Complete(Ok(HttpSendOutput { response, .. })) => {
+                break Some(response);
+            }
+            HttpCoroutineState::Yielded(HttpSendYield::WantsRedirect { response, .. }) => {
+                break Some(response);
+            }
+            HttpCoroutineState::Yielded(HttpSendYield::WantsWrite(_)) => (),
+            // NOTE: the bytes read back, then the end of the stream a
+            // response without a length ends on; wanting more means it
+            // was cut short.
+            HttpCoroutineState::Yielded(HttpSendYield::WantsRead) => {
+                arg = Some(chunks.next()?);
+            }
+            HttpCoroutineState::Complete(Err(_)) => break None,
+        }
+    }
+}
+
+/// Parses a token endpoint response, whatever its status.
+pub fn parse_token_response(
+    res: HttpResponse,
+) -> Result<Result<StoredToken, Oauth20AccessTokenErrorParams>> {
+    let body = res.body.as_slice();
+
+    if res.status.is_success() {
+        let token = StoredToken::from_response(body).context("Parse access token response")?;
+        Ok(Ok(token))
+    } else {
+        let err = Oauth20AccessTokenErrorParams::try_from(body)
+            .context("Parse access token error response")
+            .with_context(|| format!("Token endpoint answered {}", *res.status))?;
+        Ok(Err(err))
+    }
+}
+
+/// Whether `res` is a 5xx or a 429 worth retrying: never when it
+/// carries `invalid_grant` or `invalid_client`, which no retry fixes.
+fn is_transient(res: &HttpResponse) -> bool {
+    let status = *res.status;
+
+    if status != 429 && !(500..600).contains(&status) {
+        return false;
+    }
+
+    let Ok(err) = Oauth20AccessTokenErrorParams::try_from(res.body.as_slice()) else {
+        return true;
+    };
+
+    !matches!(
+        err.error,
+        Oauth20AccessTokenErrorCode::InvalidGrant | Oauth20AccessTokenErrorCode::InvalidClient
+    )
+}
+
+/// The delay a `Retry-After` header asks for (RFC 9110 section
+/// 10.2.3), in seconds or as an HTTP date.
+fn retry_after(res: &HttpResponse) -> Option<Duration> {
+    let value = res.header("retry-after")?.trim();
+
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = Duration::from_secs(parse_http_date(value)?);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Some(date.saturating_sub(now))
+}
+
+/// A random number, drawn from the CSRF state generator.
+fn random_u64() -> u64 {
+    Oauth20State::default()
+        .expose()
+        .iter()
+        .fold(0, |n, &byte| n.rotate_left(7) ^ u64::from(byte))
+}
+
+/// The delay before the retry following `attempt`: `retry.delay`
+/// doubled per attempt, capped at `retry.max-delay`, its upper half
+/// jittered so concurrent clients spread out.
+fn backoff(account: &Account, attempt: usize) -> Duration {
+    let exp = u32::try_from(attempt - 1).unwrap_or(u32::MAX);
+    let delay = account
+        .retry_delay
+        .saturating_mul(2u32.saturating_pow(exp))
+        .min(account.retry_max_delay);
+
+    let half = delay / 2;
+    let jitter = random_u64() % (half.as_millis() as u64 + 1);
+
+    half + Duration::from_millis(jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> HttpResponse {
+        let head: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\r\n"))
+            .collect();
+        let raw = format!(
+            "HTTP/1.1 {status} X\r\n{head}Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+
+        read_response(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn server_errors_and_throttling_are_transient() {
+        assert!(is_transient(&response(503, &[], "")));
+        assert!(is_transient(&response(429, &[], "")));
+        assert!(is_transient(&response(
+            500,
+            &[],
+            r#"{"error":"temporarily_unavailable"}"#
+        )));
+
+        assert!(!is_transient(&response(
+            400,
+            &[],
+            r#"{"error":"invalid_request"}"#
+        )));
+        assert!(!is_transient(&response(
+            503,
+            &[],
+            r#"{"error":"invalid_grant"}"#
+        )));
+        assert!(!is_transient(&response(
+            500,
+            &[],
+            r#"{"error":"invalid_client"}"#
+        )));
+    }
+
+    #[test]
+    fn only_a_whole_response_is_read_back() {
+        let raw = "HTTP/1.1 200 OK\r\nContent-Length: 20\r\n\r\n{\"access_token\":";
+        assert!(read_response(raw.as_bytes()).is_none());
+        assert!(read_response(b"").is_none());
+
+        let raw = "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{}";
+        let res = read_response(raw.as_bytes()).unwrap();
+        assert_eq!(res.body, b"{}");
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_dates() {
+        let res = response(429, &[("Retry-After", "7")], "");
+        assert_eq!(retry_after(&res), Some(Duration::from_secs(7)));
+
+        let res = response(503, &[("Retry-After", "Sun, 06 Nov 1994 08:49:37 GMT")], "");
+        assert_eq!(retry_after(&res), Some(Duration::ZERO));
+
+        assert_eq!(retry_after(&response(503, &[], "")), None);
+    }
+}
@@ -14,6 +14,10 @@
 //! [io-pim-discovery]. This repository only contains the CLI glue
 //! between the user's config and those two crates.
 //!
+//! The few requests io-oauth cannot express (its parameter
+//! structs are closed) go through [`http`], which sends a form Ortie
+//! composed over the same connection and authentication.
+//!
 //! [io-oauth]: https://docs.rs/io-oauth
 //! [io-pim-discovery]: https://docs.rs/io-pim-discovery
 //!
@@ -22,7 +26,7 @@
 //! contact with the tool; otherwise it routes into two command trees:
 //! [`auth`] obtains tokens by running the OAuth grant configured on
 //! the account (get, resume), while [`token`] works on the
-//! token already persisted in storage (show, inspect, refresh).
+//! token already persisted in storage (show, inspect, check, refresh).
 //! [`repl`] is those same two trees held open against one account, so
 //! the secret store is unlocked once instead of per command.
 //!
@@ -44,6 +48,11 @@
 //! the runtime [`account::Account`] view that commands consume, along
 //! with the driver methods for storage and hooks.
 //!
+//! An account may name a security [`profile`] (OAuth 2.1, FAPI 2.0):
+//! it is checked once the account is flattened, and again where a
+//! command flag changes a checked setting, so a non-compliant account
+//! never reaches the network.
+//!
 //! ## Conventions
 //!
 //! Endpoints are optional at parse time: each command checks the ones
@@ -59,18 +68,33 @@
 //! never logged.
 //!
 //! Everything the user asked for goes to stdout, data and errors
-//! alike (JSON with `--json`), distinguished only by the exit code;
-//! stderr carries logs. Doc comments on the command structs double as
-//! the CLI help: the first paragraph (two lines at most) is the `-h`
-//! summary, the following paragraphs complete the `--help` page.
+//! alike (JSON with `--json`), distinguished only by the exit code,
+//! 30 when the account needs authorizing again, 1 for other errors;
+//! stderr carries logs. `token check` exits with the token state
+//! instead, its 30 meaning the same. Doc comments on the command
+//! structs double as the CLI help: the first paragraph (two lines at
+//! most) is the `-h` summary, the following paragraphs complete the
+//! `--help` page.
 //!
 //! Device authorization (RFC 8628) is selected with `grant = "device"`.
+//! OpenID CIBA, poll mode, with `grant = "ciba"`: it polls through
+//! the device grant's loop, with an `auth_req_id` for a device code.
+//! OAuth 1.0a (RFC 5849), with `grant = "oauth1"`, runs its three
+//! legs in one `auth get` and signs requests through `token sign`.
+//! The deprecated password grant (RFC 6749 section 4.3), with
+//! `grant = "password"`, runs headlessly and warns at every run.
 //! The headless client credentials grants are selected with
 //! `grant = "client-credentials"` (RFC 6749 section 4.4, secret) and
 //! `grant = "client-credentials-jwt"` (RFC 7523 section 2.2, JWT
 //! client assertion signed with `client-key`, `x5t` thumbprint from
 //! `client-certificate`); they issue no refresh token, so auto-refresh
 //! silently re-runs the grant instead of exchanging a refresh token.
+//! Every refresh runs under a per-account file lock, so concurrent
+//! callers share one refresh instead of racing on a rotating refresh
+//! token.
+//! Token requests are retried with backoff on network failures, 5xx
+//! and 429 (`retry.*`), never on `invalid_grant`, and never once a
+//! single-use code or refresh token may have reached the server.
 //! The remaining roadmap (discovery upgrades, revocation) lives in
 //! cairn/changes/; current truth is in cairn/spec/ and landed history
 //! in cairn/log/ (see <https://github.com/pimalaya/cairn>).
@@ -79,21 +103,33 @@ mod account;
 mod auth;
 mod cli;
 mod config;
+mod http;
+mod profile;
 mod repl;
+mod scheme;
 mod token;
 mod wizard;
 
 use std::{
     io::{IsTerminal, stdin},
     path::PathBuf,
+    process,
 };
 
-use anyhow::Result;
+use anyhow::{Error, Result};
 use clap::{CommandFactory, Parser};
 use pimalaya_cli::{error::ErrorReport, log::Logger, printer::Printer, printer::StdoutPrinter};
 use pimalaya_config::toml::TomlConfig;
 
-use crate::{cli::Cli, config::Config};
+use crate::{
+    cli::Cli,
+    config::Config,
+    token::{check::TokenChecked, refresh::ReauthRequired},
+};
+
+/// Exit code of a command failing because the server rejected the
+/// refresh token, so scripts can tell it from other failures.
+const REAUTH_REQUIRED_EXIT_CODE: i32 = 30;
 
 fn main() {
     let cli = Cli::parse();
@@ -109,7 +145,26 @@ fn main() {
         None => meet_bare_invocation(&mut printer, config_paths, account_name.is_some()),
     };
 
-    ErrorReport::eval(&mut printer, result)
+    // NOTE: `token check` printed the token state already, its exit
+    // code is all that remains to tell.
+    if let Err(err) = &result
+        && let Some(checked) = err.downcast_ref::<TokenChecked>()
+    {
+        process::exit(checked.state.exit_code());
+    }
+
+    match result {
+        Err(err) if reauth_required(&err) => {
+            let _ = printer.out(ErrorReport::from(err));
+            process::exit(REAUTH_REQUIRED_EXIT_CODE);
+        }
+        result => ErrorReport::eval(&mut printer, result),
+    }
+}
+
+/// Whether the error comes from a rejected refresh token.
+fn reauth_required(err: &Error) -> bool {
+    err.downcast_ref::<ReauthRequired>().is_some()
 }
 
 /// Meets a bare `ortie`, which is where a newcomer lands.
@@ -0,0 +1,294 @@
+//! Security profiles: the OAuth 2.1 and FAPI 2.0 postures.
+//!
+//! A profile holds an account to rules its server may not enforce, so
+//! a regulated account cannot drift into a weaker setting by a typo.
+//! The account is checked once loaded, and again where a command flag
+//! can change what is checked, every violation listed at once so one
+//! round of edits fixes them all.
+//!
+//! FAPI 2.0 is checked as far as the configuration shows it: pushed
+//! authorization requests, private key JWT client authentication, an
+//! explicit HTTPS redirection and the issuer checks. Sender-constrained
+//! tokens (DPoP or mTLS) and the signing algorithm are not: Ortie sends
+//! neither proof, and the io-oauth key signs RS256 only.
+//!
+//! Refs:
+//! - <https://datatracker.ietf.org/doc/html/draft-ietf-oauth-v2-1>
+//! - <https://openid.net/specs/fapi-security-profile-2_0-final.html>
+
+use anyhow::{Result, bail};
+use url::{Host, Url};
+
+use crate::{
+    account::Account,
+    config::{ClientAuthConfig, GrantConfig, PkceConfig, ProfileConfig},
+};
+
+/// Fails listing every rule of its profile `account` breaks, if any.
+pub fn check(account: &Account) -> Result<()> {
+    let Some(profile) = account.profile else {
+        return Ok(());
+    };
+
+    let violations = violations(profile, account);
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    bail!(
+        "Account `{}` does not comply with the {} profile:\n- {}",
+        account.name,
+        name(profile),
+        violations.join("\n- "),
+    )
+}
+
+/// Fails when `uri` is not a redirection endpoint `profile` allows.
+pub fn check_redirect(profile: Option<ProfileConfig>, uri: &Url) -> Result<()> {
+    let Some(profile) = profile else {
+        return Ok(());
+    };
+
+    match redirect_violation(profile, Some(uri)) {
+        Some(violation) => bail!(
+            "The {} profile refuses this redirection: {violation}",
+            name(profile)
+        ),
+        None => Ok(()),
+    }
+}
+
+/// The wire name of `profile`, as the config spells it.
+fn name(profile: ProfileConfig) -> &'static str {
+    match profile {
+        ProfileConfig::Oauth21 => "oauth2.1",
+        ProfileConfig::Fapi2 => "fapi2",
+    }
+}
+
+/// The rules of `profile` that `account` breaks, in config order.
+fn violations(profile: ProfileConfig, account: &Account) -> Vec<String> {
+    let fapi2 = profile == ProfileConfig::Fapi2;
+    let code = account.grant == GrantConfig::AuthorizationCode;
+    let mut violations = Vec::new();
+
+    if account.grant == GrantConfig::Password {
+        violations.push("grant = \"password\" is not allowed".to_owned());
+    }
+
+    if account.grant == GrantConfig::Oauth1 {
+        violations.push("grant = \"oauth1\" is not allowed".to_owned());
+    }
+
+    // NOTE: the shared secret is the symmetric authentication FAPI 2.0
+    // rules out, whichever method the account picked.
+    if fapi2 && account.client_secret.is_some() {
+        violations.push("client-secret must not be set".to_owned());
+    }
+
+    let jwt = account.grant == GrantConfig::ClientCredentialsJwt
+        || account.client_auth == ClientAuthConfig::PrivateKeyJwt;
+
+    if fapi2 && !jwt {
+        violations.push("client-auth must be \"private-key-jwt\"".to_owned());
+    }
+
+    if fapi2 && account.client_key.is_none() {
+        violations.push("client-key must be set".to_owned());
+    }
+
+    if code && account.pkce != PkceConfig::S256 {
+        violations.push("pkce must be \"s256\"".to_owned());
+    }
+
+    if code {
+        violations.extend(redirect_violation(
+            profile,
+            account.redirection_endpoint.as_ref(),
+        ));
+    }
+
+    if fapi2 && code && account.pushed_authorization_request_endpoint.is_none() {
+        violations.push("endpoints.pushed-authorization-request must be set".to_owned());
+    }
+
+    if fapi2 && code && account.issuer.is_none() {
+        violations.push("issuer must be set".to_owned());
+    }
+
+    if fapi2 && code && !account.authorization_response_iss {
+        violations.push("authorization-response-iss must be true".to_owned());
+    }
+
+    violations
+}
+
+/// Why `uri` is not a redirection endpoint `profile` allows, `None`
+/// standing for the ephemeral loopback listener.
+///
+/// OAuth 2.1 takes HTTPS, private-use schemes, and plain HTTP on a
+/// loopback IP literal only (`localhost` can resolve elsewhere). FAPI
+/// 2.0 takes HTTPS only, so it needs an explicit one.
+fn redirect_violation(profile: ProfileConfig, uri: Option<&Url>) -> Option<String> {
+    let Some(uri) = uri else {
+        return match profile {
+            ProfileConfig::Oauth21 => None,
+            ProfileConfig::Fapi2 => Some("endpoints.redirection must be set".to_owned()),
+        };
+    };
+
+    if uri.fragment().is_some() {
+        return Some(format!("redirection {uri} must not carry a fragment"));
+    }
+
+    let loopback = match uri.host() {
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        _ => false,
+    };
+
+    match (profile, uri.scheme()) {
+        (_, "https") => None,
+        (ProfileConfig::Oauth21, "http") if loopback => None,
+        (ProfileConfig::Oauth21, "http") => Some(format!(
+            "redirection {uri} must use HTTPS, or HTTP on a loopback IP literal"
+        )),
+        (ProfileConfig::Oauth21, _) => None,
+        (ProfileConfig::Fapi2, _) => Some(format!("redirection {uri} must use HTTPS")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pimalaya_config::toml::TomlConfig;
+
+    use crate::config::Config;
+
+    use super::*;
+
+    fn parse(toml: &str) -> Account {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(toml.as_bytes()).unwrap();
+
+        let mut config = Config::from_paths(&[file.path().to_path_buf()]).unwrap();
+        Account::from(config.take_named_account("test").unwrap())
+    }
+
+    #[test]
+    fn every_violation_is_listed() {
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+profile = "oauth2.1"
+pkce = "plain"
+endpoints.redirection = "http://localhost:8080"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        );
+
+        let err = check(&account).unwrap_err().to_string();
+        assert!(err.contains("oauth2.1 profile"), "{err}");
+        assert!(err.contains("- pkce must be \"s256\""), "{err}");
+        assert!(
+            err.contains("- redirection http://localhost:8080/"),
+            "{err}"
+        );
+
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+profile = "oauth2.1"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        );
+
+        assert!(check(&account).is_ok());
+    }
+
+    fn redirect(profile: ProfileConfig, uri: &str) -> Option<String> {
+        redirect_violation(profile, Some(&Url::parse(uri).unwrap()))
+    }
+
+    #[test]
+    fn oauth21_redirects_are_https_private_or_loopback_literals() {
+        let profile = ProfileConfig::Oauth21;
+
+        assert_eq!(redirect_violation(profile, None), None);
+        assert_eq!(redirect(profile, "http://127.0.0.1:8080/cb"), None);
+        assert_eq!(redirect(profile, "http://[::1]/cb"), None);
+        assert_eq!(redirect(profile, "https://app.example.com/cb"), None);
+        assert_eq!(redirect(profile, "org.pimalaya.ortie:/cb"), None);
+
+        assert!(redirect(profile, "http://localhost/cb").is_some());
+        assert!(redirect(profile, "http://app.example.com/cb").is_some());
+        assert!(redirect(profile, "https://app.example.com/cb#x").is_some());
+    }
+
+    #[test]
+    fn fapi2_redirects_are_explicit_https() {
+        let profile = ProfileConfig::Fapi2;
+
+        assert!(redirect_violation(profile, None).is_some());
+        assert!(redirect(profile, "http://127.0.0.1:8080/cb").is_some());
+        assert!(redirect(profile, "org.pimalaya.ortie:/cb").is_some());
+        assert_eq!(redirect(profile, "https://app.example.com/cb"), None);
+    }
+
+    #[test]
+    fn fapi2_requires_pushed_requests_and_private_key_jwt() {
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+client-secret.raw = "secret"
+profile = "fapi2"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        );
+
+        let err = check(&account).unwrap_err().to_string();
+        assert!(err.contains("fapi2 profile"), "{err}");
+        assert!(err.contains("- client-secret must not be set"), "{err}");
+        assert!(
+            err.contains("- client-auth must be \"private-key-jwt\""),
+            "{err}"
+        );
+        assert!(err.contains("- client-key must be set"), "{err}");
+        assert!(err.contains("- endpoints.redirection must be set"), "{err}");
+        assert!(
+            err.contains("- endpoints.pushed-authorization-request must be set"),
+            "{err}"
+        );
+        assert!(err.contains("- issuer must be set"), "{err}");
+        assert!(
+            err.contains("- authorization-response-iss must be true"),
+            "{err}"
+        );
+
+        let account = parse(
+            r#"
+[accounts.test]
+client-id = "app-id"
+client-key = "/etc/ortie/client.pem"
+client-auth = "private-key-jwt"
+profile = "fapi2"
+issuer = "https://auth.example.com"
+authorization-response-iss = true
+endpoints.redirection = "https://app.example.com/cb"
+endpoints.pushed-authorization-request = "https://auth.example.com/par"
+storage.read.command = ["cat", "token.json"]
+storage.write.command = ["tee", "token.json"]
+"#,
+        );
+
+        assert!(check(&account).is_ok());
+    }
+}
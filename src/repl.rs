@@ -55,8 +55,9 @@ struct ReplLine {
 enum ReplCommandTree {
     #[command(subcommand)]
     Token(TokenCommand),
+    // NOTE: boxed, `auth get` flags outweighing every token command.
     #[command(subcommand)]
-    Auth(ReplAuthCommand),
+    Auth(Box<ReplAuthCommand>),
 }
 
 impl ReplCommandTree {
@@ -74,7 +75,7 @@ impl ReplCommandTree {
 /// issue is immediately visible to the following `token` commands.
 #[derive(Debug, Subcommand)]
 enum ReplAuthCommand {
-    Get(AuthGetCommand),
+    Get(Box<AuthGetCommand>),
     #[command(visible_alias = "continue")]
     Resume(AuthResumeCommand),
 }
@@ -0,0 +1,30 @@
+//! `scheme` subcommand tree: register Ortie as the desktop handler of
+//! the private-use redirection scheme.
+
+pub mod install;
+
+use anyhow::Result;
+use clap::Subcommand;
+use pimalaya_cli::printer::Printer;
+
+use crate::scheme::install::SchemeInstallCommand;
+
+/// Manage the desktop URI scheme handler.
+///
+/// Providers mandating a reverse-DNS private-use redirection (as
+/// Fastmail's dynamic registration does) send the browser back to a
+/// URI only the desktop can route. Installing the handler routes it to
+/// the `auth get` waiting for it, instead of a manual `auth resume`.
+#[derive(Subcommand, Debug)]
+pub enum SchemeCommand {
+    Install(SchemeInstallCommand),
+}
+
+impl SchemeCommand {
+    /// Dispatches the scheme leaf; none needs an account.
+    pub fn execute(self, printer: &mut impl Printer) -> Result<()> {
+        match self {
+            Self::Install(cmd) => cmd.execute(printer),
+        }
+    }
+}
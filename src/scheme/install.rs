@@ -0,0 +1,239 @@
+//! `scheme install` subcommand: register the desktop URI scheme
+//! handler.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result, anyhow};
+use clap::Parser;
+use log::debug;
+use pimalaya_cli::printer::{Message, Printer};
+
+/// Scheme of the reverse-DNS private-use redirection the wizard
+/// registers when a provider rejects http redirections (the
+/// `REDIRECT_SCHEME` of the wizard's application step).
+const DEFAULT_SCHEME: &str = "org.pimalaya.ortie";
+
+/// Register Ortie as the handler of a private-use redirection scheme.
+///
+/// Writes an XDG desktop entry running `ortie auth resume
+/// --from-handler %u` and associates it with the
+/// `x-scheme-handler/<SCHEME>` MIME type in mimeapps.list. A waiting
+/// `auth get` then receives the redirection the browser hands to the
+/// desktop, instead of printing the manual `auth resume` steps.
+#[derive(Debug, Parser)]
+pub struct SchemeInstallCommand {
+    /// The private-use scheme to handle.
+    ///
+    /// Defaults to the scheme of the redirection the wizard registers
+    /// for providers rejecting http redirections.
+    #[arg(long, value_name = "SCHEME", default_value = DEFAULT_SCHEME)]
+    pub scheme: String,
+}
+
+impl SchemeInstallCommand {
+    /// Writes the desktop entry and its MIME association, then
+    /// refreshes the desktop database when the tool is around.
+    pub fn execute(self, printer: &mut impl Printer) -> Result<()> {
+        let exe = env::current_exe().context("Locate the ortie executable")?;
+
+        let entry_path = desktop_entry_path(&self.scheme)?;
+        let Some(applications) = entry_path.parent() else {
+            return Err(anyhow!(
+                "Invalid desktop entry path {}",
+                entry_path.display()
+            ));
+        };
+
+        fs::create_dir_all(applications)
+            .with_context(|| format!("Create applications directory {}", applications.display()))?;
+        fs::write(&entry_path, desktop_entry(&exe, &self.scheme))
+            .with_context(|| format!("Write desktop entry {}", entry_path.display()))?;
+
+        let Some(config_dir) = dirs::config_dir() else {
+            return Err(anyhow!("Cannot find the user configuration directory"));
+        };
+
+        let mimeapps_path = config_dir.join("mimeapps.list");
+        let mimeapps = fs::read_to_string(&mimeapps_path).unwrap_or_default();
+
+        let Some(entry_name) = entry_path.file_name() else {
+            return Err(anyhow!(
+                "Invalid desktop entry path {}",
+                entry_path.display()
+            ));
+        };
+
+        let mimeapps = associate(
+            &mimeapps,
+            &format!("x-scheme-handler/{}", self.scheme),
+            &entry_name.to_string_lossy(),
+        );
+
+        fs::create_dir_all(&config_dir)?;
+        fs::write(&mimeapps_path, mimeapps)
+            .with_context(|| format!("Write MIME associations {}", mimeapps_path.display()))?;
+
+        // NOTE: some desktops route schemes through the mimeinfo cache
+        // rather than mimeapps.list; the tool is optional, so its
+        // absence is not an error.
+        match Command::new("update-desktop-database")
+            .arg(applications)
+            .output()
+        {
+            Ok(output) if output.status.success() => (),
+            Ok(output) => debug!("update desktop database error: {output:?}"),
+            Err(err) => debug!("spawn update-desktop-database error: {err}"),
+        }
+
+        printer.out(Message::new(format!(
+            "Handler of {}:// installed at {}",
+            self.scheme,
+            entry_path.display(),
+        )))
+    }
+}
+
+/// Path of the desktop entry handling `scheme`, under the XDG
+/// applications directory.
+pub fn desktop_entry_path(scheme: &str) -> Result<PathBuf> {
+    let Some(data_dir) = dirs::data_dir() else {
+        return Err(anyhow!("Cannot find the user data directory"));
+    };
+
+    let name = format!("{}-{scheme}.desktop", env!("CARGO_PKG_NAME"));
+    Ok(data_dir.join("applications").join(name))
+}
+
+/// Renders the desktop entry running `exe` on the handled URI. The
+/// executable is quoted per the Desktop Entry specification, so a path
+/// holding spaces survives.
+fn desktop_entry(exe: &Path, scheme: &str) -> String {
+    let mut quoted = String::from('"');
+    for ch in exe.to_string_lossy().chars() {
+        if matches!(ch, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(ch);
+    }
+    quoted.push('"');
+
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Ortie\n\
+         Comment=Hand OAuth 2.0 redirections over to a waiting ortie auth get\n\
+         Exec={quoted} auth resume --from-handler %u\n\
+         Terminal=false\n\
+         NoDisplay=true\n\
+         MimeType=x-scheme-handler/{scheme};\n"
+    )
+}
+
+/// Sets `entry` as the default application of `mime` in a mimeapps.list
+/// document, replacing a previous association and keeping every other
+/// line untouched.
+fn associate(mimeapps: &str, mime: &str, entry: &str) -> String {
+    const SECTION: &str = "[Default Applications]";
+
+    let association = format!("{mime}={entry}");
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_section = false;
+    let mut associated = false;
+
+    for line in mimeapps.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with('[') {
+            // NOTE: leaving the section without having met the key:
+            // the association goes last in it.
+            if in_section && !associated {
+                lines.push(association.clone());
+                associated = true;
+            }
+            in_section = trimmed == SECTION;
+        } else if in_section
+            && trimmed
+                .split_once('=')
+                .is_some_and(|(key, _)| key.trim() == mime)
+        {
+            if !associated {
+                lines.push(association.clone());
+                associated = true;
+            }
+            continue;
+        }
+
+        lines.push(line.to_owned());
+    }
+
+    if !associated {
+        if !in_section {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(SECTION.to_owned());
+        }
+        lines.push(association);
+    }
+
+    let mut mimeapps = lines.join("\n");
+    mimeapps.push('\n');
+    mimeapps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIME: &str = "x-scheme-handler/org.pimalaya.ortie";
+    const ENTRY: &str = "ortie-org.pimalaya.ortie.desktop";
+
+    #[test]
+    fn the_entry_resumes_from_the_handler_with_a_quoted_executable() {
+        let entry = desktop_entry(Path::new("/opt/my apps/ortie"), "org.pimalaya.ortie");
+
+        assert!(
+            entry.contains("Exec=\"/opt/my apps/ortie\" auth resume --from-handler %u\n"),
+            "{entry}"
+        );
+        assert!(entry.contains("MimeType=x-scheme-handler/org.pimalaya.ortie;\n"));
+    }
+
+    #[test]
+    fn an_empty_mimeapps_gets_the_section() {
+        assert_eq!(
+            associate("", MIME, ENTRY),
+            format!("[Default Applications]\n{MIME}={ENTRY}\n"),
+        );
+    }
+
+    #[test]
+    fn an_existing_association_is_replaced_in_place() {
+        let mimeapps = format!(
+            "[Default Applications]\ntext/html=firefox.desktop\n{MIME}=other.desktop\n\n[Added Associations]\n{MIME}=other.desktop\n"
+        );
+
+        assert_eq!(
+            associate(&mimeapps, MIME, ENTRY),
+            format!(
+                "[Default Applications]\ntext/html=firefox.desktop\n{MIME}={ENTRY}\n\n[Added Associations]\n{MIME}=other.desktop\n"
+            ),
+        );
+    }
+
+    #[test]
+    fn a_missing_association_closes_its_section() {
+        let mimeapps = "[Default Applications]\ntext/html=firefox.desktop\n[Added Associations]\ntext/plain=vim.desktop\n";
+
+        assert_eq!(
+            associate(mimeapps, MIME, ENTRY),
+            format!(
+                "[Default Applications]\ntext/html=firefox.desktop\n{MIME}={ENTRY}\n[Added Associations]\ntext/plain=vim.desktop\n"
+            ),
+        );
+    }
+}
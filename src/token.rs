@@ -1,9 +1,14 @@
 //! `token` subcommand tree: work on the access token already
 //! persisted in storage.
 
+pub mod check;
 pub mod inspect;
+pub mod lock;
+pub mod provenance;
 pub mod refresh;
 pub mod show;
+pub mod sign;
+pub mod stored;
 
 use anyhow::Result;
 use clap::Subcommand;
@@ -11,20 +16,26 @@ use pimalaya_cli::printer::Printer;
 
 use crate::{
     account::Account,
-    token::{inspect::TokenInspectCommand, refresh::TokenRefreshCommand, show::TokenShowCommand},
+    token::{
+        check::TokenCheckCommand, inspect::TokenInspectCommand, refresh::TokenRefreshCommand,
+        show::TokenShowCommand, sign::TokenSignCommand,
+    },
 };
 
 /// Display and refresh an existing OAuth 2.0 access token.
 ///
 /// This subcommand allows you to show your access token, inspect
-/// metadata associated to it, and refresh your access token using the
-/// refresh token (if available).
+/// metadata associated to it, check its state, refresh your access
+/// token using the refresh token (if available), and sign requests
+/// with OAuth 1.0a token credentials.
 #[derive(Subcommand, Debug)]
 pub enum TokenCommand {
     #[command(visible_alias = "get")]
     Show(TokenShowCommand),
     Inspect(TokenInspectCommand),
+    Check(TokenCheckCommand),
     Refresh(TokenRefreshCommand),
+    Sign(TokenSignCommand),
 }
 
 impl TokenCommand {
@@ -33,7 +44,9 @@ impl TokenCommand {
         match self {
             Self::Show(cmd) => cmd.execute(printer, account),
             Self::Inspect(cmd) => cmd.execute(printer, account),
+            Self::Check(cmd) => cmd.execute(printer, account),
             Self::Refresh(cmd) => cmd.execute(printer, account),
+            Self::Sign(cmd) => cmd.execute(printer, account),
         }
     }
 }
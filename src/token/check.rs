@@ -0,0 +1,321 @@
+//! `token check` subcommand: tell the state of the access token
+//! through the exit code.
+
+use std::{fmt, time::Duration};
+
+use anyhow::{Result, anyhow};
+use clap::Parser;
+use humantime::format_duration;
+use pimalaya_cli::printer::Printer;
+use serde::Serialize;
+
+use crate::{
+    account::Account,
+    config::{GrantConfig, InvalidGrantConfig},
+    token::{
+        lock::RefreshLock,
+        refresh::{ReauthRequired, RefreshAction, TokenRefreshCommand, refresh_action},
+        stored::StoredToken,
+    },
+};
+
+/// Check the state of the access token, without printing it.
+///
+/// This command reads the token from storage and exits with a code
+/// telling its state: 0 when valid, 10 when expiring within
+/// `--within`, 20 when expired but refreshable, 30 when the account
+/// needs authorizing again, 40 when no token can be read. It prints a
+/// one-line summary, or the state as JSON with `--json`, and never
+/// refreshes the token unless `--refresh` is given.
+#[derive(Debug, Parser)]
+pub struct TokenCheckCommand {
+    /// Report a token expiring within this duration as expiring.
+    ///
+    /// Takes a humantime duration (`15m`, `1h 30m`). Defaults to the
+    /// account `refresh-before` window.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub within: Option<Duration>,
+
+    /// Require the token to be granted this scope.
+    ///
+    /// A token lacking one needs authorizing again, since a refresh
+    /// grants no new scope. Can be repeated.
+    #[arg(long = "scope", value_name = "SCOPE")]
+    pub scopes: Vec<String>,
+
+    /// Refresh an expiring or expired token before reporting.
+    ///
+    /// The state reported is the one after the refresh, the failure
+    /// of which is told in the summary.
+    #[arg(long, short = 'r')]
+    pub refresh: bool,
+}
+
+impl TokenCheckCommand {
+    /// Reads the token from storage, refreshing it first with
+    /// `--refresh` when due, then prints its state and fails with
+    /// [`TokenChecked`] unless it is valid.
+    pub fn execute(self, printer: &mut impl Printer, account: &mut Account) -> Result<()> {
+        let mut check = match account.resolve_token() {
+            Ok(token) => self.check(account, &token),
+            Err(err) => Check::missing(account, err),
+        };
+
+        if self.refresh && matches!(check.state, TokenState::Expiring | TokenState::Refreshable) {
+            check = self.refresh(printer, account, check);
+        }
+
+        let state = check.state;
+        printer.out(check)?;
+
+        match state {
+            TokenState::Valid => Ok(()),
+            state => Err(anyhow!(TokenChecked { state })),
+        }
+    }
+
+    /// Evaluates the state of `token` against the account.
+    fn check(&self, account: &Account, token: &StoredToken) -> Check {
+        let within = self.within.unwrap_or(account.refresh_before);
+
+        // NOTE: OAuth 1.0a token credentials do not expire.
+        let remaining = match account.grant {
+            GrantConfig::Oauth1 => None,
+            _ => token
+                .remaining(account.default_expires_in)
+                .map(|remaining| Duration::from_secs(remaining.as_secs())),
+        };
+
+        let missing_scopes = self.missing_scopes(account, token);
+
+        let (state, reason) = if !missing_scopes.is_empty() {
+            let reason = format!("missing scopes {}", missing_scopes.join(", "));
+            (TokenState::ReauthRequired, Some(reason))
+        } else if token.reauth_required {
+            let reason = String::from("refresh token rejected by the server");
+            (TokenState::ReauthRequired, Some(reason))
+        } else {
+            match remaining {
+                Some(remaining) if remaining.is_zero() && refreshable(account, token) => {
+                    (TokenState::Refreshable, None)
+                }
+                Some(remaining) if remaining.is_zero() => {
+                    let reason = String::from("access token expired, no way to refresh it");
+                    (TokenState::ReauthRequired, Some(reason))
+                }
+                Some(remaining) if remaining <= within => (TokenState::Expiring, None),
+                _ => (TokenState::Valid, None),
+            }
+        };
+
+        Check {
+            account: account.name.clone(),
+            state,
+            exit_code: state.exit_code(),
+            remaining_lifetime: remaining.map(|remaining| remaining.as_secs()),
+            missing_scopes,
+            reason,
+        }
+    }
+
+    /// The `--scope` values the token was not granted. A token stored
+    /// without `scope` was granted the scopes it was requested with
+    /// (RFC 6749 §5.1), from its provenance, else from the account.
+    fn missing_scopes(&self, account: &Account, token: &StoredToken) -> Vec<String> {
+        if token.params.scope.is_some() {
+            return token.missing_scopes(&self.scopes);
+        }
+
+        let requested = match &token.provenance {
+            Some(provenance) => &provenance.scopes,
+            None => &account.scopes,
+        };
+
+        self.scopes
+            .iter()
+            .filter(|scope| !requested.contains(scope))
+            .cloned()
+            .collect()
+    }
+
+    /// Refreshes the token under the refresh lock, the way `token
+    /// show` does, and checks it again. A refresh another process ran
+    /// meanwhile is taken as is, and a failed one leaves the state as
+    /// it was, the failure as the reason.
+    fn refresh(&self, printer: &mut impl Printer, account: &mut Account, check: Check) -> Check {
+        let res = RefreshLock::acquire(&account.name).and_then(|lock| {
+            let token = account.reload_token()?;
+            let state = self.check(account, &token).state;
+
+            if !matches!(state, TokenState::Expiring | TokenState::Refreshable) {
+                return Ok(token);
+            }
+
+            match refresh_action(account.grant, token.params.refresh_token.is_some()) {
+                RefreshAction::Reacquire => TokenRefreshCommand::reacquire(account),
+                RefreshAction::Refresh => {
+                    let Some(refresh_token) = token.params.refresh_token else {
+                        return Ok(token);
+                    };
+
+                    // NOTE: a check never starts a grant, so a
+                    // rejected refresh token is reported, not
+                    // re-authorized.
+                    let policy = match account.on_invalid_grant {
+                        InvalidGrantConfig::Reauth => InvalidGrantConfig::Notify,
+                        policy => policy,
+                    };
+
+                    TokenRefreshCommand::refresh(printer, account, &lock, refresh_token, policy)
+                }
+                RefreshAction::Keep => Ok(token),
+            }
+        });
+
+        match res {
+            Ok(token) => self.check(account, &token),
+            // NOTE: the summary tells what to run already, the reason
+            // is what the server answered.
+            Err(err) if err.downcast_ref::<ReauthRequired>().is_some() => {
+                let causes: Vec<_> = err.chain().skip(1).map(ToString::to_string).collect();
+
+                Check {
+                    state: TokenState::ReauthRequired,
+                    exit_code: TokenState::ReauthRequired.exit_code(),
+                    reason: Some(causes.join(": ")).filter(|reason| !reason.is_empty()),
+                    ..check
+                }
+            }
+            Err(err) => Check {
+                reason: Some(format!("refresh failed: {err:#}")),
+                ..check
+            },
+        }
+    }
+}
+
+/// Whether an expired token can get fresh again without the user: a
+/// client credentials grant re-acquires, a refresh token not known to
+/// have expired is exchanged.
+fn refreshable(account: &Account, token: &StoredToken) -> bool {
+    match refresh_action(account.grant, token.params.refresh_token.is_some()) {
+        RefreshAction::Reacquire => true,
+        RefreshAction::Refresh => token
+            .refresh_token_remaining()
+            .is_none_or(|remaining| !remaining.is_zero()),
+        RefreshAction::Keep => false,
+    }
+}
+
+/// The state of the access token, each with its exit code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenState {
+    /// The token is valid beyond the `--within` window.
+    Valid,
+    /// The token expires within the `--within` window.
+    Expiring,
+    /// The token expired, and a refresh can make it fresh again.
+    Refreshable,
+    /// Only a new authorization brings a usable token back.
+    ReauthRequired,
+    /// No token could be read from storage.
+    Missing,
+}
+
+impl TokenState {
+    /// The exit code of `token check` for the state. The re-auth
+    /// code matches the one of the other commands.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Valid => 0,
+            Self::Expiring => 10,
+            Self::Refreshable => 20,
+            Self::ReauthRequired => 30,
+            Self::Missing => 40,
+        }
+    }
+}
+
+/// The failure of `token check` on a token not valid, carrying its
+/// state. `main` exits with the state code, the summary being already
+/// printed.
+#[derive(Debug)]
+pub struct TokenChecked {
+    /// The state of the token.
+    pub state: TokenState,
+}
+
+impl fmt::Display for TokenChecked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Access token check failed with exit code {}",
+            self.state.exit_code()
+        )
+    }
+}
+
+/// Printable state of the access token.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    /// Name of the account checked.
+    account: String,
+    /// The state of the token.
+    state: TokenState,
+    /// The exit code telling the state.
+    exit_code: i32,
+    /// Seconds left before the token expires, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining_lifetime: Option<u64>,
+    /// The `--scope` values the token was not granted.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    missing_scopes: Vec<String>,
+    /// Why the token is not usable, or why its refresh failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+impl Check {
+    /// The state of an account whose token cannot be read.
+    fn missing(account: &Account, err: anyhow::Error) -> Self {
+        Self {
+            account: account.name.clone(),
+            state: TokenState::Missing,
+            exit_code: TokenState::Missing.exit_code(),
+            remaining_lifetime: None,
+            missing_scopes: Vec::new(),
+            reason: Some(format!("{err:#}")),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let remaining = self
+            .remaining_lifetime
+            .map(|secs| format_duration(Duration::from_secs(secs)));
+
+        write!(f, "{}: ", self.account)?;
+
+        match (self.state, remaining) {
+            (TokenState::Valid, None) => write!(f, "valid, expiry unknown")?,
+            (TokenState::Valid, Some(remaining)) => write!(f, "valid, expires in {remaining}")?,
+            (TokenState::Expiring, Some(remaining)) => write!(f, "expiring in {remaining}")?,
+            (TokenState::Expiring, None) => write!(f, "expiring")?,
+            (TokenState::Refreshable, _) => write!(f, "expired, refreshable")?,
+            (TokenState::ReauthRequired, _) => write!(
+                f,
+                "needs authorizing again, run `ortie auth get --account {}`",
+                self.account
+            )?,
+            (TokenState::Missing, _) => write!(f, "no token")?,
+        }
+
+        if let Some(reason) = &self.reason {
+            write!(f, " ({reason})")?;
+        }
+
+        Ok(())
+    }
+}
@@ -11,74 +11,172 @@ use humantime::format_duration;
 use pimalaya_cli::printer::Printer;
 use serde::Serialize;
 
-use io_oauth::rfc6749::issue_access_token::Oauth20AccessTokenSuccessParams;
-
-use crate::account::Account;
+use crate::{
+    account::Account,
+    token::{
+        provenance::{Drift, Provenance},
+        stored::StoredToken,
+    },
+};
 
 /// Inspect metadata associated to the access token.
 ///
 /// Unlike the `token show` command, this command shows you metadata
 /// like the token type, when it was issued, when it expires, the
-/// presence of a refresh token, and the granted scopes.
+/// presence of a refresh token, when it expires and whether the
+/// server rejected it, the granted scopes and authorization details,
+/// and what changed in the account configuration since it was
+/// authorized. The JSON output carries the granted details in full.
 #[derive(Debug, Parser)]
 pub struct TokenInspectCommand;
 
 impl TokenInspectCommand {
     /// Reads the token from storage and prints its metadata.
     pub fn execute(self, printer: &mut impl Printer, account: &mut Account) -> Result<()> {
-        let response = account.resolve_token()?;
-        printer.out(Report(response))
+        let token = account.resolve_token()?;
+        let remaining_lifetime = token
+            .remaining(account.default_expires_in)
+            .map(|remaining| remaining.as_secs());
+        let refresh_token_remaining_lifetime = token
+            .refresh_token_remaining()
+            .map(|remaining| remaining.as_secs());
+        let drift = token
+            .provenance
+            .as_ref()
+            .map(|provenance| provenance.drift(&Provenance::of(account)))
+            .unwrap_or_default();
+
+        printer.out(Report {
+            token,
+            remaining_lifetime,
+            refresh_token_remaining_lifetime,
+            drift,
+        })
     }
 }
 
 /// Printable metadata view over the stored token response.
 #[derive(Debug, Serialize)]
-#[serde(transparent)]
-pub struct Report(Oauth20AccessTokenSuccessParams);
+pub struct Report {
+    /// The stored token response.
+    #[serde(flatten)]
+    token: StoredToken,
+    /// Seconds left before the token expires, from its `expires_at`,
+    /// or its issuance time plus `expires_in` (the account
+    /// `default-expires-in` standing in for a missing one). Unknown
+    /// when neither is stored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining_lifetime: Option<u64>,
+    /// Seconds left before the refresh token expires, when its expiry
+    /// is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token_remaining_lifetime: Option<u64>,
+    /// What changed in the account configuration since the token was
+    /// authorized.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    drift: Vec<Drift>,
+}
 
 impl fmt::Display for Report {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Token type: {}", self.0.token_type.to_lowercase())?;
+        write!(
+            f,
+            "Token type: {}",
+            self.token.params.token_type.to_lowercase()
+        )?;
 
         let now_epoch = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map(|d| d.as_secs())
             .ok();
 
-        if let (Some(issued_at), Some(now)) = (self.0.issued_at, now_epoch) {
+        if let (Some(issued_at), Some(now)) = (self.token.params.issued_at, now_epoch) {
             let elapsed = Duration::from_secs(now.saturating_sub(issued_at));
             writeln!(f)?;
             write!(f, "Issued: {} ago", format_duration(elapsed))?;
         }
 
-        match self.0.expires_in {
-            None => {
+        // NOTE: only a token with neither an absolute expiry nor an
+        // `expires_in` runs on the assumed `default-expires-in`.
+        let known = self.token.expires_at.is_some() || self.token.params.expires_in.is_some();
+        let remaining =
+            self.remaining_lifetime
+                .or(self.token.params.expires_in.map(|exp| exp as u64));
+
+        writeln!(f)?;
+        match remaining {
+            None => write!(f, "Expired: unknown")?,
+            Some(0) => write!(f, "Expired: true")?,
+            Some(remaining) => {
+                let duration = format_duration(Duration::from_secs(remaining));
+                write!(f, "Expires in: {duration}")?;
+
+                if !known {
+                    write!(f, " (assumed)")?;
+                }
+            }
+        }
+
+        writeln!(f)?;
+        write!(
+            f,
+            "With refresh token: {}",
+            self.token.params.refresh_token.is_some()
+        )?;
+
+        match self.refresh_token_remaining_lifetime {
+            None => (),
+            Some(0) => {
                 writeln!(f)?;
-                write!(f, "Expired: unknown")?;
+                write!(f, "Refresh token expired: true")?;
             }
-            Some(exp) => {
-                let remaining = match (self.0.issued_at, now_epoch) {
-                    (Some(issued_at), Some(now)) => (issued_at + exp as u64).saturating_sub(now),
-                    _ => exp as u64,
-                };
+            Some(remaining) => {
+                let duration = format_duration(Duration::from_secs(remaining));
                 writeln!(f)?;
-                if remaining == 0 {
-                    write!(f, "Expired: true")?;
-                } else {
-                    let duration = format_duration(Duration::from_secs(remaining));
-                    write!(f, "Expires in: {duration}")?;
-                }
+                write!(f, "Refresh token expires in: {duration}")?;
             }
         }
 
-        writeln!(f)?;
-        write!(f, "With refresh token: {}", self.0.refresh_token.is_some())?;
+        if self.token.reauth_required {
+            writeln!(f)?;
+            write!(f, "Re-authorization required: true")?;
+        }
 
-        if let Some(scope) = &self.0.scope {
+        if let Some(scope) = &self.token.params.scope {
             writeln!(f)?;
             write!(f, "With scope: {scope}")?;
         }
 
+        if let Some(details) = self
+            .token
+            .authorization_details
+            .as_ref()
+            .and_then(|d| d.as_array())
+        {
+            let types: Vec<_> = details
+                .iter()
+                .map(|detail| detail["type"].as_str().unwrap_or("?"))
+                .collect();
+            writeln!(f)?;
+            write!(f, "With authorization details: {}", types.join(", "))?;
+        }
+
+        if let Some(acr) = &self.token.acr {
+            writeln!(f)?;
+            write!(f, "Authentication context: {acr}")?;
+        }
+
+        if let (Some(auth_time), Some(now)) = (self.token.auth_time, now_epoch) {
+            let elapsed = Duration::from_secs(now.saturating_sub(auth_time));
+            writeln!(f)?;
+            write!(f, "Authenticated: {} ago", format_duration(elapsed))?;
+        }
+
+        for drift in &self.drift {
+            writeln!(f)?;
+            write!(f, "Configuration drift: {drift}")?;
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,182 @@
+//! Cross-process lock around the refresh of a stored token.
+//!
+//! A mail client, a calendar sync and a cron job may all ask for a
+//! fresh token at once. With rotating refresh tokens, two concurrent
+//! refreshes race and the loser's refresh token is invalidated by the
+//! winner's. Each refresh therefore runs under a per-account advisory
+//! file lock, held across read-refresh-write: the caller that waited
+//! re-reads storage once it holds the lock, and finds the token the
+//! other one just wrote.
+//!
+//! The lock file also remembers when the last refresh failed, as the
+//! negative cache of `stale-on-error`: callers served the stale token
+//! skip the token endpoint until the cooldown is over, rather than
+//! each hammering a failing server.
+
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, anyhow};
+use log::debug;
+
+/// The advisory lock of one account, released on drop.
+#[derive(Debug)]
+pub struct RefreshLock {
+    file: File,
+}
+
+impl RefreshLock {
+    /// Blocks until the refresh lock of `account` is held.
+    pub fn acquire(account: &str) -> Result<Self> {
+        let path = lock_path(account)?;
+
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Open refresh lock {}", path.display()))?;
+
+        debug!("acquire refresh lock {}", path.display());
+
+        file.lock()
+            .with_context(|| format!("Acquire refresh lock {}", path.display()))?;
+
+        Ok(Self { file })
+    }
+
+    /// Releases the lock while `f` runs, then blocks until it is held
+    /// again. For work too long to hold other callers up, an
+    /// interactive grant waiting on the user.
+    pub fn released<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        debug!("release refresh lock");
+        self.file.unlock().context("Release refresh lock")?;
+
+        let res = f();
+
+        debug!("acquire refresh lock again");
+        self.file.lock().context("Acquire refresh lock again")?;
+
+        res
+    }
+
+    /// Whether a refresh failed less than `cooldown` ago.
+    pub fn failed_within(&self, cooldown: Duration) -> bool {
+        let mut file = &self.file;
+        let mut content = String::new();
+
+        if let Err(err) = file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| file.read_to_string(&mut content))
+        {
+            debug!("read refresh failure time: {err}");
+            return false;
+        }
+
+        let Ok(failed_at) = content.trim().parse::<u64>() else {
+            return false;
+        };
+
+        now_secs().saturating_sub(failed_at) < cooldown.as_secs()
+    }
+
+    /// Remembers that a refresh failed now.
+    pub fn record_failure(&self) {
+        if let Err(err) = self.write(&now_secs().to_string()) {
+            debug!("write refresh failure time: {err}");
+        }
+    }
+
+    /// Forgets the last refresh failure, after a successful one.
+    pub fn clear_failure(&self) {
+        if let Err(err) = self.write("") {
+            debug!("clear refresh failure time: {err}");
+        }
+    }
+
+    fn write(&self, content: &str) -> std::io::Result<()> {
+        let mut file = &self.file;
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(content.as_bytes())
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Path of the lock file of `account`: under `$XDG_RUNTIME_DIR`, or
+/// the cache directory when the session exposes none. Its parent is
+/// created private to the user.
+fn lock_path(account: &str) -> Result<PathBuf> {
+    let Some(dir) = dirs::runtime_dir().or_else(dirs::cache_dir) else {
+        return Err(anyhow!(
+            "Cannot find a runtime directory for the refresh lock"
+        ));
+    };
+
+    let dir = dir.join(env!("CARGO_PKG_NAME"));
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    builder
+        .create(&dir)
+        .with_context(|| format!("Create refresh lock directory {}", dir.display()))?;
+
+    Ok(dir.join(format!("{}.lock", file_name(account))))
+}
+
+/// `account` as a file name: anything but ASCII alphanumerics, `-`,
+/// `_` and `.` becomes `_`.
+fn file_name(account: &str) -> String {
+    account
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn account_names_make_flat_file_names() {
+        assert_eq!(file_name("work"), "work");
+        assert_eq!(file_name("me@example.com"), "me_example.com");
+        assert_eq!(file_name("../etc/passwd"), ".._etc_passwd");
+    }
+
+    #[test]
+    fn a_released_lock_is_free_until_f_returned() {
+        let account = format!("released-{}", std::process::id());
+        let path = lock_path(&account).unwrap();
+        let lock = RefreshLock::acquire(&account).unwrap();
+        let other = File::open(&path).unwrap();
+        assert!(other.try_lock().is_err());
+
+        lock.released(|| {
+            other.try_lock()?;
+            other.unlock()?;
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(other.try_lock().is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
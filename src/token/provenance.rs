@@ -0,0 +1,225 @@
+//! Where a stored token comes from.
+//!
+//! A token answers for the configuration it was authorized under. Once
+//! the account gains a scope or switches client, the stored token
+//! still works but lacks what the configuration now asks for. The
+//! [`Provenance`] stored with the token records that configuration, so
+//! `token show` and `token inspect` can tell what drifted since.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{account::Account, config::GrantConfig};
+
+/// The account configuration a token was authorized under.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Provenance {
+    /// The OAuth 2.0 client identifier.
+    pub client_id: String,
+    /// Host of the token endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_endpoint: Option<String>,
+    /// The requested scopes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    /// The requested resource indicators (RFC 8707), from the account
+    /// `resource` extra.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resources: Vec<String>,
+    /// The grant the token was issued through.
+    pub grant: GrantConfig,
+}
+
+impl Provenance {
+    /// The provenance of a token the account would issue now.
+    pub fn of(account: &Account) -> Self {
+        Self {
+            client_id: account.client_id.clone(),
+            token_endpoint: account
+                .token_endpoint
+                .as_ref()
+                .and_then(|url| url.host_str())
+                .map(ToOwned::to_owned),
+            scopes: account.scopes.clone(),
+            resources: account
+                .extras
+                .get("resource")
+                .map(|res| res.split_whitespace().map(ToOwned::to_owned).collect())
+                .unwrap_or_default(),
+            grant: account.grant,
+        }
+    }
+
+    /// What changed from this provenance to `current`, one entry per
+    /// field. Scopes and resources compare as sets.
+    pub fn drift(&self, current: &Self) -> Vec<Drift> {
+        let mut drift = Vec::new();
+
+        if self.client_id != current.client_id {
+            drift.push(Drift::changed(
+                "client id",
+                &self.client_id,
+                &current.client_id,
+            ));
+        }
+
+        if self.token_endpoint != current.token_endpoint {
+            let none = String::from("none");
+            drift.push(Drift::changed(
+                "token endpoint",
+                self.token_endpoint.as_ref().unwrap_or(&none),
+                current.token_endpoint.as_ref().unwrap_or(&none),
+            ));
+        }
+
+        if let Some(scopes) = Drift::set("scopes", &self.scopes, &current.scopes) {
+            drift.push(scopes);
+        }
+
+        if let Some(resources) = Drift::set("resources", &self.resources, &current.resources) {
+            drift.push(resources);
+        }
+
+        if self.grant != current.grant {
+            drift.push(Drift::changed("grant", self.grant, current.grant));
+        }
+
+        drift
+    }
+}
+
+/// One field of the account configuration that changed since the
+/// token was authorized.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct Drift {
+    /// The field, as a human reads it.
+    pub field: &'static str,
+    /// The value the token was authorized under, for a single-valued
+    /// field.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stored: Option<String>,
+    /// The value the account configuration holds now, for a
+    /// single-valued field.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<String>,
+    /// Values the configuration added, for a set field.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    /// Values the configuration removed, for a set field.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+impl Drift {
+    fn changed(field: &'static str, stored: impl ToString, current: impl ToString) -> Self {
+        Self {
+            field,
+            stored: Some(stored.to_string()),
+            current: Some(current.to_string()),
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    fn set(field: &'static str, stored: &[String], current: &[String]) -> Option<Self> {
+        let added: Vec<_> = current
+            .iter()
+            .filter(|val| !stored.contains(val))
+            .cloned()
+            .collect();
+        let removed: Vec<_> = stored
+            .iter()
+            .filter(|val| !current.contains(val))
+            .cloned()
+            .collect();
+
+        if added.is_empty() && removed.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            field,
+            stored: None,
+            current: None,
+            added,
+            removed,
+        })
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.field)?;
+
+        if let (Some(stored), Some(current)) = (&self.stored, &self.current) {
+            write!(f, " changed from {stored} to {current}")?;
+        }
+
+        if !self.added.is_empty() {
+            write!(f, " added {}", self.added.join(", "))?;
+        }
+
+        if !self.added.is_empty() && !self.removed.is_empty() {
+            write!(f, ",")?;
+        }
+
+        if !self.removed.is_empty() {
+            write!(f, " removed {}", self.removed.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provenance(client_id: &str, scopes: &[&str]) -> Provenance {
+        Provenance {
+            client_id: client_id.to_owned(),
+            token_endpoint: Some("login.example.com".to_owned()),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            resources: Vec::new(),
+            grant: GrantConfig::AuthorizationCode,
+        }
+    }
+
+    #[test]
+    fn scopes_compare_as_sets() {
+        let stored = provenance("app", &["mail", "calendar"]);
+        assert!(
+            stored
+                .drift(&provenance("app", &["calendar", "mail"]))
+                .is_empty()
+        );
+
+        let drift = stored.drift(&provenance("app", &["mail", "contacts"]));
+        assert_eq!(drift.len(), 1);
+        assert_eq!(
+            drift[0].to_string(),
+            "scopes added contacts, removed calendar"
+        );
+    }
+
+    #[test]
+    fn every_changed_field_is_reported() {
+        let stored = provenance("app", &["mail"]);
+        let mut current = provenance("other-app", &["mail"]);
+        current.grant = GrantConfig::Device;
+
+        let drift: Vec<_> = stored
+            .drift(&current)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            drift,
+            [
+                "client id changed from app to other-app",
+                "grant changed from authorization-code to device",
+            ]
+        );
+    }
+}
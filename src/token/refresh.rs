@@ -1,28 +1,30 @@
 //! `token refresh` subcommand: refresh the current access token.
 
-use std::time::Duration;
+use std::{
+    fmt,
+    io::{IsTerminal, stdout},
+    time::Duration,
+};
 
 use anyhow::{Result, anyhow, bail};
 use clap::Parser;
 use humantime::format_duration;
 use log::debug;
 use pimalaya_cli::printer::{Message, Printer};
-use secrecy::SecretBox;
-
 use pimalaya_config::secret::Secret;
+use secrecy::{ExposeSecret, SecretBox};
 
-use io_oauth::{
-    client::Oauth20ClientStd,
-    rfc6749::{
-        issue_access_token::Oauth20AccessTokenSuccessParams,
-        refresh_access_token::Oauth20AccessTokenRefreshParams,
-    },
+use io_oauth::rfc6749::{
+    issue_access_token::{Oauth20AccessTokenErrorCode, Oauth20AccessTokenErrorParams},
+    refresh_access_token::Oauth20AccessTokenRefreshParams,
 };
 
 use crate::{
     account::Account,
-    auth::get::{client_credentials_error, request_client_credentials_token},
-    config::GrantConfig,
+    auth::get::{AuthGetCommand, client_credentials_error, request_client_credentials_token},
+    config::{GrantConfig, InvalidGrantConfig},
+    http::{self, Credential},
+    token::{lock::RefreshLock, stored::StoredToken},
 };
 
 /// How an expired token gets fresh again, decided per grant.
@@ -69,21 +71,38 @@ impl TokenRefreshCommand {
     /// exchange or client credentials re-acquisition) and reports the
     /// new expiry.
     pub fn execute(self, printer: &mut impl Printer, account: &mut Account) -> Result<()> {
+        if account.grant == GrantConfig::Oauth1 {
+            bail!("OAuth 1.0a token credentials do not expire, nothing to refresh");
+        }
+
+        // NOTE: the refresh token is read under the lock, so a refresh
+        // another process just ran rotates it before this one uses it.
+        let lock = RefreshLock::acquire(&account.name)?;
+
         let token = match refresh_action(account.grant, true) {
             RefreshAction::Reacquire => Self::reacquire(account)?,
             _ => {
-                let token = account.resolve_token()?;
+                let token = account.reload_token()?;
 
-                let Some(refresh_token) = token.refresh_token else {
+                let Some(refresh_token) = token.params.refresh_token else {
                     bail!("Missing refresh token");
                 };
 
-                Self::refresh(account, refresh_token)?
+                Self::refresh(
+                    printer,
+                    account,
+                    &lock,
+                    refresh_token,
+                    account.on_invalid_grant,
+                )?
             }
         };
 
+        drop(lock);
+        account.check_refresh_token_expiry();
+
         let msg = "Access token successfully refreshed";
-        let msg = match token.expires_in {
+        let msg = match token.params.expires_in {
             None => format!("{msg} (unknown expiry)"),
             Some(exp) => {
                 let exp = Duration::from_secs(exp as u64 + 1);
@@ -95,12 +114,19 @@ impl TokenRefreshCommand {
     }
 
     /// Re-acquires a client credentials token by re-running the
-    /// grant, persists it and fires the on-refresh hooks. The JWT
-    /// kind mints a fresh assertion on every run; nothing but the
-    /// token response is ever stored.
-    pub fn reacquire(account: &mut Account) -> Result<Oauth20AccessTokenSuccessParams> {
-        match request_client_credentials_token(account)? {
+    /// grant, compares the granted scopes with the requested ones,
+    /// persists it and fires the on-refresh hooks. The JWT kind mints a
+    /// fresh assertion on every run; nothing but the token response is
+    /// ever stored.
+    pub fn reacquire(account: &mut Account) -> Result<StoredToken> {
+        let res = match request_client_credentials_token(account) {
+            Ok(res) => res,
+            Err(err) => return Err(unanswered(account, err)),
+        };
+
+        match res {
             Ok(res) => {
+                account.check_scopes(&res)?;
                 let res = account.write_to_storage(res)?;
 
                 debug!("execute refresh access token success hook");
@@ -121,36 +147,80 @@ impl TokenRefreshCommand {
         }
     }
 
-    /// Runs the refresh grant against the token endpoint, persists
-    /// the outcome (keeping the previous refresh token when the
-    /// server omits a rotated one) and fires the on-refresh hooks.
+    /// Runs the refresh grant against the token endpoint, retried on
+    /// transient failures, compares the granted scopes with the
+    /// requested ones, persists the outcome (keeping the previous
+    /// refresh token when the server omits a rotated one) and fires
+    /// the on-refresh hooks. A rejected refresh token goes through the
+    /// `on_invalid_grant` policy, the account one unless the caller
+    /// must not start a grant.
     pub fn refresh(
+        printer: &mut impl Printer,
         account: &mut Account,
+        lock: &RefreshLock,
         refresh_token: SecretBox<str>,
-    ) -> Result<Oauth20AccessTokenSuccessParams> {
+        on_invalid_grant: InvalidGrantConfig,
+    ) -> Result<StoredToken> {
         let Some(token_endpoint) = account.token_endpoint.clone() else {
             bail!("Missing endpoints.token in the account config");
         };
 
         let client_secret = account.client_secret.clone().map(Secret::get).transpose()?;
 
-        let mut client =
-            Oauth20ClientStd::connect(token_endpoint, &account.tls, account.client_id.clone())?;
-        client.client_secret = client_secret;
+        // NOTE: a rotating refresh token is single-use, so it is never
+        // sent twice once the server may have received it.
+        let res = http::request_token(account, &token_endpoint, Credential::SingleUse, |client| {
+            let params = Oauth20AccessTokenRefreshParams {
+                client_id: account.client_id.clone(),
+                client_secret: None,
+                refresh_token: refresh_token.clone(),
+                scopes: account.scopes.iter().map(Into::into).collect(),
+            };
+
+            // NOTE: io-oauth's refresh params carry no client
+            // assertion, the hand-made request appends it.
+            if account.private_key_jwt() {
+                let form = params.to_serializer().finish();
+                http::post_body(client, account, &token_endpoint, form)?;
+            } else {
+                client.client_secret = client_secret.clone();
+                client.refresh_access_token(params).map(drop)?;
+            }
 
-        let res = client.refresh_access_token(Oauth20AccessTokenRefreshParams {
-            client_id: account.client_id.clone(),
-            client_secret: None,
-            refresh_token,
-            scopes: account.scopes.iter().map(Into::into).collect(),
-        })?;
+            Ok(())
+        });
+
+        let res = match res {
+            Ok(res) => res,
+            Err(err) => return Err(unanswered(account, err)),
+        };
 
         match res {
             Ok(mut res) => {
-                if res.refresh_token.is_none() {
-                    res.refresh_token = account.resolve_token()?.refresh_token;
+                let previous = account.resolve_token()?;
+
+                // NOTE: a refresh token kept across the refresh,
+                // omitted or sent back as is, keeps its expiry, and the
+                // memory of its expiring hook.
+                let kept = match (&res.params.refresh_token, &previous.params.refresh_token) {
+                    (None, _) => true,
+                    (Some(new), Some(old)) => new.expose_secret() == old.expose_secret(),
+                    (Some(_), None) => false,
+                };
+
+                if kept {
+                    if res.params.refresh_token.is_none() {
+                        res.params.refresh_token = previous.params.refresh_token.clone();
+                    }
+
+                    res.refresh_token_expires_at = res
+                        .refresh_token_expires_at
+                        .or(previous.refresh_token_expires_at);
+                    res.refresh_token_expiring_notified = previous.refresh_token_expiring_notified;
                 }
 
+                let res = res.inherit(&previous);
+                account.check_scopes(&res)?;
                 let res = account.write_to_storage(res)?;
 
                 debug!("execute refresh access token success hook");
@@ -162,19 +232,156 @@ impl TokenRefreshCommand {
                 debug!("execute refresh access token error hook");
                 account.execute_on_refresh_error_hook(&res);
 
-                let err = anyhow!("Refresh access token error (code {:?})", res.error);
+                if res.error != Oauth20AccessTokenErrorCode::InvalidGrant {
+                    return Err(refresh_error(res));
+                }
+
+                match on_invalid_grant {
+                    InvalidGrantConfig::Fail => Err(refresh_error(res)),
+                    InvalidGrantConfig::Reauth if stdout().is_terminal() => {
+                        Self::reauthorize(printer, account, Some(lock), refresh_error(res))
+                    }
+                    InvalidGrantConfig::Reauth | InvalidGrantConfig::Notify => {
+                        debug!("execute invalid grant hook");
+                        account.execute_on_invalid_grant_hook(&res);
+
+                        if let Err(err) = account.mark_reauth_required() {
+                            debug!("mark access token as needing re-authorization: {err:#}");
+                        }
+
+                        Err(refresh_error(res).context(ReauthRequired::of(account)))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handles a token whose refresh token the server already
+    /// rejected, without sending it again: re-authorizes under the
+    /// `reauth` policy, fails otherwise.
+    pub fn rejected(
+        printer: &mut impl Printer,
+        account: &mut Account,
+        lock: &RefreshLock,
+    ) -> Result<StoredToken> {
+        let cause = anyhow!("Refresh token rejected by the server");
+
+        if account.on_invalid_grant == InvalidGrantConfig::Reauth {
+            Self::reauthorize(printer, account, Some(lock), cause)
+        } else {
+            Err(cause.context(ReauthRequired::of(account)))
+        }
+    }
+
+    /// Authorizes the account again: the account grant runs in an
+    /// interactive terminal, other runs fail with `cause`, telling
+    /// what to run.
+    ///
+    /// `auth get` may end without issuing a token (a redirection it
+    /// cannot listen on hands off to `auth resume`), so the token is
+    /// read back from storage and must differ from the one replaced.
+    ///
+    /// The grant waits on the user for as long as they take, so the
+    /// caller's refresh `lock` is released meanwhile rather than
+    /// holding every other caller up. The grant stores the token under
+    /// the lock, and it is held again to read the token back.
+    pub fn reauthorize(
+        printer: &mut impl Printer,
+        account: &mut Account,
+        lock: Option<&RefreshLock>,
+        cause: anyhow::Error,
+    ) -> Result<StoredToken> {
+        if !stdout().is_terminal() {
+            return Err(cause.context(ReauthRequired::of(account)));
+        }
+
+        let replaced = account
+            .resolve_token()
+            .ok()
+            .map(|token| token.params.access_token.expose_secret().to_owned());
+
+        let cmd = AuthGetCommand {
+            wait: true,
+            ..Default::default()
+        };
+        match lock {
+            Some(lock) => lock.released(|| cmd.execute(printer, account))?,
+            None => cmd.execute(printer, account)?,
+        }
 
-                Err(match (res.error_description, res.error_uri) {
-                    (None, None) => err,
-                    (Some(desc), None) => anyhow!("{desc}").context(err),
-                    (None, Some(uri)) => anyhow!("{uri}").context(err),
-                    (Some(desc), Some(uri)) => anyhow!("{desc}: {uri}").context(err),
-                })
+        match account.reload_token() {
+            Ok(token)
+                if !token.reauth_required
+                    && replaced.as_deref() != Some(token.params.access_token.expose_secret()) =>
+            {
+                Ok(token)
             }
+            _ => Err(cause.context(ReauthRequired::of(account))),
+        }
+    }
+}
+
+/// The failure of a command needing the account authorized again, its
+/// refresh token rejected or its configuration changed. `main` exits
+/// with a dedicated code when it finds it in the error chain.
+#[derive(Debug)]
+pub struct ReauthRequired {
+    /// Name of the account to authorize again.
+    pub account: String,
+}
+
+impl ReauthRequired {
+    fn of(account: &Account) -> Self {
+        Self {
+            account: account.name.clone(),
         }
     }
 }
 
+impl fmt::Display for ReauthRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Account {} needs authorizing again, run `ortie auth get --account {}`",
+            self.account, self.account
+        )
+    }
+}
+
+/// Turns a refresh error response into an error.
+fn refresh_error(res: Oauth20AccessTokenErrorParams) -> anyhow::Error {
+    let err = anyhow!("Refresh access token error (code {:?})", res.error);
+
+    match (res.error_description, res.error_uri) {
+        (None, None) => err,
+        (Some(desc), None) => anyhow!("{desc}").context(err),
+        (None, Some(uri)) => anyhow!("{uri}").context(err),
+        (Some(desc), Some(uri)) => anyhow!("{desc}: {uri}").context(err),
+    }
+}
+
+/// Fires the on-refresh error hook for a refresh that failed without
+/// an error response to report, the token endpoint unreachable or
+/// still failing after the retries: `ERROR` is `Unknown` and
+/// `ERROR_DESCRIPTION` the failure. Local failures composing the
+/// request (a missing key, an unreadable secret) fire nothing.
+fn unanswered(account: &mut Account, err: anyhow::Error) -> anyhow::Error {
+    if err.downcast_ref::<http::Unanswered>().is_none() {
+        return err;
+    }
+
+    let res = Oauth20AccessTokenErrorParams {
+        error: Oauth20AccessTokenErrorCode::Unknown,
+        error_description: Some(format!("{err:#}")),
+        error_uri: None,
+    };
+
+    debug!("execute refresh access token error hook");
+    account.execute_on_refresh_error_hook(&res);
+
+    err
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2,29 +2,29 @@
 
 use std::{
     fmt,
-    time::{SystemTime, UNIX_EPOCH},
+    io::{IsTerminal, stdout},
+    time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use clap::Parser;
+use humantime::format_duration;
 use pimalaya_cli::printer::Printer;
 use secrecy::ExposeSecret;
 use serde::Serialize;
 
 use crate::{
     account::Account,
-    token::refresh::{RefreshAction, TokenRefreshCommand, refresh_action},
+    auth::get::AuthGetCommand,
+    config::DriftConfig,
+    token::{
+        lock::RefreshLock,
+        provenance::{Drift, Provenance},
+        refresh::{RefreshAction, TokenRefreshCommand, refresh_action},
+        stored::StoredToken,
+    },
 };
 
-/// Seconds of slack before the real expiry at which a token is treated
-/// as expired, so a token that is about to lapse is refreshed rather
-/// than handed out and rejected mid-request.
-const EXPIRY_SKEW_SECS: u64 = 60;
-
-/// Default access token lifetime assumed when the server sends no
-/// `expires_in`, so a session refreshes roughly hourly instead of never.
-const DEFAULT_EXPIRES_IN_SECS: u64 = 3600;
-
 /// Display the raw access token.
 ///
 /// This command allows you to see your access token. It can easily be
@@ -37,14 +37,36 @@ pub struct TokenShowCommand {
     /// also the `auto-refresh` config option.
     #[arg(long, short = 'r')]
     pub auto_refresh: bool,
+
+    /// Require the token to carry this authentication context class.
+    ///
+    /// A token issued for another `acr` (RFC 9470) is stepped up:
+    /// interactive shells run `auth get --acr-values` first, other
+    /// runs fail instead of printing the weaker token.
+    #[arg(long, value_name = "ACR")]
+    pub min_acr: Option<String>,
+
+    /// Refresh the token when it expires within this duration.
+    ///
+    /// Takes a humantime duration (`15m`, `1h 30m`) and refreshes
+    /// whenever the remaining lifetime is below it, auto-refresh or
+    /// not, so a long IMAP IDLE or a CI job starts with a token that
+    /// lasts. Fails when the token still expires sooner afterwards.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub min_validity: Option<Duration>,
 }
 
 impl TokenShowCommand {
     /// Reads the token from storage, making it fresh first when
     /// auto-refresh is requested (refresh-token exchange, or client
-    /// credentials re-acquisition), then prints it raw.
+    /// credentials re-acquisition), then prints it raw. With
+    /// `stale-on-error`, a failed refresh prints the stored token
+    /// while it has not expired. A token authorized under another
+    /// account configuration goes through the `on-drift` policy first,
+    /// and one missing requested scopes fails under
+    /// `require-all-scopes`.
     pub fn execute(self, printer: &mut impl Printer, account: &mut Account) -> Result<()> {
-        let auto_refresh = self.auto_refresh || account.auto_refresh;
+        let auto_refresh = self.auto_refresh || account.auto_refresh || self.min_validity.is_some();
 
         // NOTE: on an auto-refreshing client credentials account a
         // missing or unreadable stored token re-acquires instead of
@@ -55,53 +77,247 @@ impl TokenShowCommand {
                 if auto_refresh
                     && refresh_action(account.grant, false) == RefreshAction::Reacquire =>
             {
-                TokenRefreshCommand::reacquire(account)?
+                let _lock = RefreshLock::acquire(&account.name)?;
+
+                match account.reload_token() {
+                    Ok(token) => token,
+                    Err(_) => TokenRefreshCommand::reacquire(account)?,
+                }
             }
             Err(err) => return Err(err),
         };
 
-        if auto_refresh && is_expired(token.issued_at, token.expires_in) {
-            match refresh_action(account.grant, token.refresh_token.is_some()) {
-                RefreshAction::Reacquire => {
-                    token = TokenRefreshCommand::reacquire(account)?;
-                }
-                RefreshAction::Refresh => {
-                    if let Some(refresh_token) = token.refresh_token.clone() {
-                        token = TokenRefreshCommand::refresh(account, refresh_token)?;
-                    }
-                }
-                RefreshAction::Keep => (),
+        if let Some(provenance) = &token.provenance {
+            let drift = provenance.drift(&Provenance::of(account));
+
+            if !drift.is_empty() {
+                token = drifted(printer, account, token, &drift)?;
+            }
+        }
+
+        let mut stale = false;
+
+        // NOTE: an expired token is read again under the lock: when
+        // another process refreshed it meanwhile, its token is used
+        // rather than refreshed a second time with a rotated-out
+        // refresh token.
+        if auto_refresh && self.needs_refresh(account, &token) {
+            let lock = RefreshLock::acquire(&account.name)?;
+            token = account.reload_token()?;
+
+            if self.needs_refresh(account, &token) {
+                (token, stale) = refresh(printer, account, &lock, token)?;
             }
         }
 
+        if !stale
+            && let (Some(min), Some(remaining)) = (
+                self.min_validity,
+                token.remaining(account.default_expires_in),
+            )
+            && remaining < min
+        {
+            bail!(
+                "Access token expires in {}, below the minimum validity of {}",
+                format_duration(remaining),
+                format_duration(min),
+            );
+        }
+
+        // NOTE: the mismatch was warned about when the token was
+        // issued, only `require-all-scopes` refuses the token here.
+        if account.require_all_scopes {
+            account.check_scopes(&token)?;
+        }
+
+        if let Some(acr) = &self.min_acr {
+            token = step_up(printer, account, token, acr)?;
+        }
+
+        account.check_refresh_token_expiry();
+
         printer.out(AccessToken {
-            access_token: token.access_token.expose_secret(),
+            access_token: token.params.access_token.expose_secret(),
         })
     }
 }
 
-/// Whether the token has reached (or is within [`EXPIRY_SKEW_SECS`] of)
-/// its real expiry, computed from the issuance time plus its lifetime.
+/// Handles a token authorized under another account configuration,
+/// per the account `on-drift` policy: a warning listing what changed,
+/// or a new authorization. The client credentials grants re-acquire
+/// under the refresh lock, the others authorize again interactively.
+fn drifted(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    token: StoredToken,
+    drift: &[Drift],
+) -> Result<StoredToken> {
+    let changes: Vec<_> = drift.iter().map(ToString::to_string).collect();
+    let changes = changes.join("; ");
+
+    match account.on_drift {
+        DriftConfig::Warn => {
+            eprintln!(
+                "Warning: the access token was authorized under another configuration \
+                 ({changes}), run `ortie auth get --account {}` to authorize it again.",
+                account.name,
+            );
+            Ok(token)
+        }
+        DriftConfig::Reauth if account.grant.is_client_credentials() => {
+            let _lock = RefreshLock::acquire(&account.name)?;
+            let token = account.reload_token()?;
+
+            match &token.provenance {
+                Some(provenance) if provenance.drift(&Provenance::of(account)).is_empty() => {
+                    Ok(token)
+                }
+                _ => TokenRefreshCommand::reacquire(account),
+            }
+        }
+        DriftConfig::Reauth => {
+            let cause = anyhow!("Access token authorized under another configuration: {changes}");
+            TokenRefreshCommand::reauthorize(printer, account, None, cause)
+        }
+    }
+}
+
+/// Makes a token due for a refresh fresh per the account grant,
+/// telling whether the token returned is the stale one.
+///
+/// A token whose refresh token the server already rejected is not
+/// refreshed again: it goes straight to re-authorization.
 ///
-/// `expires_in` alone is the lifetime granted at issuance (e.g. 3599s),
-/// not a live countdown, so it must be added to `issued_at` and
-/// compared against the wall clock. When `issued_at` is unknown the
-/// token is assumed still valid; a missing `expires_in` defaults to
-/// [`DEFAULT_EXPIRES_IN_SECS`] rather than never expiring.
-fn is_expired(issued_at: Option<u64>, expires_in: Option<usize>) -> bool {
-    let Some(issued_at) = issued_at else {
-        return false;
+/// With `stale-on-error`, a failed refresh hands the token back while
+/// it has life left, warning on stderr, and records the failure in
+/// the lock file. Callers within the following `refresh-cooldown` get
+/// the stale token without contacting the token endpoint.
+fn refresh(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    lock: &RefreshLock,
+    token: StoredToken,
+) -> Result<(StoredToken, bool)> {
+    // NOTE: a zero duration means no token to fall back on: stale
+    // tokens are off, or this one is expired or of unknown expiry.
+    let servable = match token.remaining(account.default_expires_in) {
+        Some(remaining) if account.stale_on_error => Duration::from_secs(remaining.as_secs()),
+        _ => Duration::ZERO,
     };
-    let expires_in = expires_in
-        .map(|exp| exp as u64)
-        .unwrap_or(DEFAULT_EXPIRES_IN_SECS);
 
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
+    if !servable.is_zero() && lock.failed_within(account.refresh_cooldown) {
+        eprintln!(
+            "Warning: the last refresh failed less than {} ago, using the stored access \
+             token, which expires in {}.",
+            format_duration(account.refresh_cooldown),
+            format_duration(servable),
+        );
+        return Ok((token, true));
+    }
+
+    let res = match refresh_action(account.grant, token.params.refresh_token.is_some()) {
+        RefreshAction::Reacquire => TokenRefreshCommand::reacquire(account),
+        RefreshAction::Refresh if token.reauth_required => {
+            TokenRefreshCommand::rejected(printer, account, lock)
+        }
+        RefreshAction::Refresh => match token.params.refresh_token.clone() {
+            Some(refresh_token) => {
+                let policy = account.on_invalid_grant;
+                TokenRefreshCommand::refresh(printer, account, lock, refresh_token, policy)
+            }
+            None => return Ok((token, false)),
+        },
+        RefreshAction::Keep => return Ok((token, false)),
+    };
+
+    match res {
+        Ok(token) => {
+            if account.stale_on_error {
+                lock.clear_failure();
+            }
+
+            Ok((token, false))
+        }
+        Err(err) if !servable.is_zero() => {
+            lock.record_failure();
+
+            eprintln!(
+                "Warning: {err:#}. Using the stored access token, which expires in {}.",
+                format_duration(servable),
+            );
+
+            Ok((token, true))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns `token` when it was issued for the `acr` authentication
+/// context class, otherwise re-authorizes asking for it.
+///
+/// Context classes are opaque strings with no order Ortie could rank,
+/// so only the exact class satisfies the requirement.
+fn step_up(
+    printer: &mut impl Printer,
+    account: &mut Account,
+    token: StoredToken,
+    acr: &str,
+) -> Result<StoredToken> {
+    if token.acr.as_deref() == Some(acr) {
+        return Ok(token);
+    }
 
-    issued_at + expires_in <= now + EXPIRY_SKEW_SECS
+    if !stdout().is_terminal() {
+        bail!(
+            "Access token authentication context does not satisfy {acr}, \
+             run `ortie auth get --acr-values {acr}` to step up"
+        );
+    }
+
+    // NOTE: a redirection nothing captures hands the flow off to a
+    // manual `auth resume` even when waiting, leaving the weaker token
+    // in storage: that is no failed step-up, the user has to finish it.
+    let cmd = AuthGetCommand {
+        acr_values: vec![acr.to_owned()],
+        wait: true,
+        ..Default::default()
+    };
+    cmd.execute(printer, account)?;
+
+    let previous = token;
+    let token = account.resolve_token()?;
+
+    if token.params.access_token.expose_secret() == previous.params.access_token.expose_secret() {
+        bail!(
+            "Step-up authorization for {acr} did not complete, finish it with \
+             `ortie auth resume` as above, or run `ortie auth get --acr-values {acr}`"
+        );
+    }
+
+    if token.acr.as_deref() != Some(acr) {
+        let got = token.acr.as_deref().unwrap_or("none");
+        bail!("Step-up authorization for {acr} issued a token for {got}");
+    }
+
+    Ok(token)
+}
+
+impl TokenShowCommand {
+    /// Whether the token is due for a refresh: its remaining lifetime
+    /// is within the account `refresh-before` window, or below
+    /// `--min-validity`. A token of unknown issuance time is assumed
+    /// still valid.
+    fn needs_refresh(&self, account: &Account, token: &StoredToken) -> bool {
+        let Some(remaining) = token.remaining(account.default_expires_in) else {
+            return false;
+        };
+
+        let window = account
+            .refresh_before
+            .max(self.min_validity.unwrap_or_default());
+
+        remaining <= window
+    }
 }
 
 /// Printable raw access token, exposed for piping.
@@ -0,0 +1,502 @@
+//! The token as persisted by the storage commands.
+//!
+//! The token response io-oauth parses carries the RFC 6749 fields
+//! only. [`StoredToken`] flattens it and adds what Ortie learns around
+//! it, so the stored JSON stays a token response any tool can read,
+//! with a few more keys.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize, Serializer};
+
+use io_oauth::rfc6749::issue_access_token::Oauth20AccessTokenSuccessParams;
+
+use crate::token::provenance::Provenance;
+
+/// A token response, with what Ortie stores along with it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StoredToken {
+    /// The token response, as issued.
+    #[serde(flatten)]
+    pub params: Oauth20AccessTokenSuccessParams,
+    /// Unix epoch seconds when the access token expires, computed when
+    /// the response arrives from its `expires_in`, or read from the
+    /// access token `exp` claim when the server sent none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Unix epoch seconds when the refresh token expires, from the
+    /// response or the account `refresh-token-lifetime`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token_expires_at: Option<u64>,
+    /// Whether the on-refresh-token-expiring hook already fired for
+    /// this refresh token.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub refresh_token_expiring_notified: bool,
+    /// Authentication context class the user authenticated with (RFC
+    /// 9470), when the access token or the ID token tells.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<String>,
+    /// Unix epoch seconds when the user authenticated (RFC 9470), when
+    /// the access token or the ID token tells.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<u64>,
+    /// Authorization details granted with the token (RFC 9396), when
+    /// the token response carries them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization_details: Option<serde_json::Value>,
+    /// Token secret of OAuth 1.0a token credentials (RFC 5849), the
+    /// access token being their `oauth_token`.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt_secret"
+    )]
+    pub oauth_token_secret: Option<SecretString>,
+    /// Whether the server rejected the refresh token, so the account
+    /// needs a new authorization before the token can be refreshed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reauth_required: bool,
+    /// The account configuration the token was authorized under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+impl StoredToken {
+    /// Wraps a freshly issued token response, stamping its absolute
+    /// expiry and reading the authentication context from the access
+    /// token claims when it is a JWT (RFC 9068).
+    pub fn new(params: Oauth20AccessTokenSuccessParams) -> Self {
+        Self {
+            params,
+            expires_at: None,
+            refresh_token_expires_at: None,
+            refresh_token_expiring_notified: false,
+            acr: None,
+            auth_time: None,
+            authorization_details: None,
+            oauth_token_secret: None,
+            reauth_required: false,
+            provenance: None,
+        }
+        .with_claims()
+    }
+
+    /// Parses a token response body, keeping the members the io-oauth
+    /// params drop, then reads the access token claims as
+    /// [`StoredToken::new`] does. The authentication context missing
+    /// from them is read from the ID token, if any.
+    ///
+    /// The body goes through [`TokenResponse`], so a server member
+    /// named like a field Ortie computes (`expires_at`, `provenance`)
+    /// is ignored rather than trusted.
+    pub fn from_response(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        let res: TokenResponse = serde_json::from_slice(bytes)?;
+
+        let mut token = Self::new(res.params);
+        token.authorization_details = res.authorization_details;
+
+        // NOTE: the `exp` of the ID token bounds the ID token alone,
+        // so only the authentication context is taken from it.
+        if let Some(claims) = res.id_token.as_deref().and_then(JwtClaims::decode) {
+            token.acr = token.acr.or(claims.acr);
+            token.auth_time = token.auth_time.or(claims.auth_time);
+        }
+
+        if let Some(exp) = res.refresh_token_expires_in {
+            token.refresh_token_expires_at = Some(now_secs() + exp);
+        }
+
+        Ok(token)
+    }
+
+    fn with_claims(mut self) -> Self {
+        let claims = JwtClaims::decode(self.params.access_token.expose_secret());
+        let claims = claims.unwrap_or_default();
+
+        self.expires_at = self
+            .expires_at
+            .or(self.params.expires_in.map(|exp| now_secs() + exp as u64))
+            .or(claims.exp);
+        self.acr = self.acr.or(claims.acr);
+        self.auth_time = self.auth_time.or(claims.auth_time);
+        self
+    }
+
+    /// Keeps what `previous` knew and the new response does not tell.
+    ///
+    /// A refresh does not authenticate the user again, so the
+    /// authentication context of the original grant still holds, and
+    /// neither does it grant other authorization details. Nor does it
+    /// authorize under another configuration: the provenance stays.
+    pub fn inherit(mut self, previous: &StoredToken) -> Self {
+        if self.acr.is_none() {
+            self.acr = previous.acr.clone();
+        }
+
+        if self.auth_time.is_none() {
+            self.auth_time = previous.auth_time;
+        }
+
+        if self.authorization_details.is_none() {
+            self.authorization_details = previous.authorization_details.clone();
+        }
+
+        if self.provenance.is_none() {
+            self.provenance = previous.provenance.clone();
+        }
+
+        self
+    }
+
+    /// Lifetime left before the token expires, against the wall clock.
+    ///
+    /// The absolute `expires_at` wins. A token stored without it
+    /// expires at its local issuance time plus `expires_in`,
+    /// `default_expires_in` standing in when the server sent none:
+    /// `expires_in` alone is the lifetime granted at issuance, not a
+    /// live countdown. `None` when neither is known, such a token
+    /// being assumed still valid.
+    pub fn remaining(&self, default_expires_in: Duration) -> Option<Duration> {
+        let expires_at = match self.expires_at {
+            Some(expires_at) => Duration::from_secs(expires_at),
+            None => {
+                let issued_at = self.params.issued_at?;
+                let expires_in = self
+                    .params
+                    .expires_in
+                    .map(|exp| Duration::from_secs(exp as u64))
+                    .unwrap_or(default_expires_in);
+
+                Duration::from_secs(issued_at) + expires_in
+            }
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Some(expires_at.saturating_sub(now))
+    }
+
+    /// Lifetime left before the refresh token expires, against the
+    /// wall clock. `None` when its expiry is unknown.
+    pub fn refresh_token_remaining(&self) -> Option<Duration> {
+        let expires_at = Duration::from_secs(self.refresh_token_expires_at?);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Some(expires_at.saturating_sub(now))
+    }
+
+    /// The `requested` scopes the server did not grant, in request
+    /// order. A response without `scope` grants every requested scope
+    /// (RFC 6749 §5.1). Scopes compare exactly, case included, or
+    /// through the [`SCOPE_ALIASES`] servers are known to rename.
+    pub fn missing_scopes(&self, requested: &[String]) -> Vec<String> {
+        let Some(granted) = &self.params.scope else {
+            return Vec::new();
+        };
+
+        let granted: Vec<_> = granted.split_whitespace().collect();
+
+        let is_granted = |scope: &str| {
+            granted.contains(&scope)
+                || SCOPE_ALIASES.iter().any(|&(a, b)| {
+                    (scope == a && granted.contains(&b)) || (scope == b && granted.contains(&a))
+                })
+        };
+
+        requested
+            .iter()
+            .filter(|scope| !is_granted(scope))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Scopes granted under another name than the one requested, either
+/// way round. Google answers the OpenID Connect `email` and `profile`
+/// with its own userinfo scopes. Other renames are not known, and
+/// report the scope as missing.
+const SCOPE_ALIASES: [(&str, &str); 2] = [
+    ("email", "https://www.googleapis.com/auth/userinfo.email"),
+    (
+        "profile",
+        "https://www.googleapis.com/auth/userinfo.profile",
+    ),
+];
+
+/// Parses the stored token from JSON bytes.
+impl TryFrom<&[u8]> for StoredToken {
+    type Error = serde_json::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        serde_json::from_slice(bytes)
+    }
+}
+
+/// Serializes the stored token into a JSON string.
+// SAFETY: exposes access and refresh tokens
+impl TryFrom<&StoredToken> for String {
+    type Error = serde_json::Error;
+
+    fn try_from(token: &StoredToken) -> Result<Self, Self::Error> {
+        serde_json::to_string(token)
+    }
+}
+
+/// A token endpoint response: the io-oauth params, plus the members
+/// they drop that Ortie reads. Nothing else of the response reaches
+/// the stored token.
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(flatten)]
+    params: Oauth20AccessTokenSuccessParams,
+    /// Authorization details granted with the token (RFC 9396).
+    #[serde(default)]
+    authorization_details: Option<serde_json::Value>,
+    /// ID token issued with the access token (OpenID Connect Core
+    /// §3.1.3.3), read for its authentication context only.
+    #[serde(default)]
+    id_token: Option<String>,
+    /// Lifetime of the refresh token in seconds, from the non-standard
+    /// member some servers send (Microsoft, GitHub, Dropbox).
+    #[serde(default)]
+    refresh_token_expires_in: Option<u64>,
+}
+
+/// The current Unix epoch seconds.
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Serializes the optional secret in clear, as storage needs it.
+// SAFETY: exposes the OAuth 1.0a token secret
+fn serialize_opt_secret<S: Serializer>(
+    secret: &Option<SecretString>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match secret {
+        Some(secret) => serializer.serialize_some(secret.expose_secret()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// The claims Ortie reads from the access token when it is a JWT, or
+/// from the ID token. Signatures are not verified: the tokens came
+/// straight from the token endpoint, and the claims only inform the
+/// client.
+#[derive(Debug, Default, Deserialize)]
+struct JwtClaims {
+    exp: Option<u64>,
+    acr: Option<String>,
+    auth_time: Option<u64>,
+}
+
+impl JwtClaims {
+    /// Decodes the payload of a JWT, `None` for an opaque token.
+    fn decode(token: &str) -> Option<Self> {
+        let mut parts = token.split('.');
+        let (Some(_), Some(payload), Some(_), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return None;
+        };
+
+        let payload = BASE64_URL_SAFE_NO_PAD.decode(payload).ok()?;
+        serde_json::from_slice(&payload).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(claims: &str) -> String {
+        let header = BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#);
+        let claims = BASE64_URL_SAFE_NO_PAD.encode(claims);
+        format!("{header}.{claims}.sig")
+    }
+
+    fn params(access_token: &str) -> Oauth20AccessTokenSuccessParams {
+        let json = format!(r#"{{"access_token":"{access_token}","token_type":"Bearer"}}"#);
+        Oauth20AccessTokenSuccessParams::try_from(json.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn the_authentication_context_comes_from_jwt_claims() {
+        let token = StoredToken::new(params(&jwt(r#"{"acr":"phr","auth_time":1700000000}"#)));
+        assert_eq!(token.acr.as_deref(), Some("phr"));
+        assert_eq!(token.auth_time, Some(1700000000));
+
+        let token = StoredToken::new(params("opaque-token"));
+        assert_eq!(token.acr, None);
+        assert_eq!(token.auth_time, None);
+    }
+
+    #[test]
+    fn the_id_token_tells_what_an_opaque_access_token_does_not() {
+        let body = format!(
+            r#"{{"access_token":"opaque-token","token_type":"Bearer","id_token":"{}"}}"#,
+            jwt(r#"{"acr":"gold","auth_time":1700000000,"exp":1}"#)
+        );
+        let token = StoredToken::from_response(body.as_bytes()).unwrap();
+        assert_eq!(token.acr.as_deref(), Some("gold"));
+        assert_eq!(token.auth_time, Some(1700000000));
+        assert_eq!(token.expires_at, None);
+
+        let body = format!(
+            r#"{{"access_token":"{}","token_type":"Bearer","id_token":"{}"}}"#,
+            jwt(r#"{"acr":"phr"}"#),
+            jwt(r#"{"acr":"gold"}"#)
+        );
+        let token = StoredToken::from_response(body.as_bytes()).unwrap();
+        assert_eq!(token.acr.as_deref(), Some("phr"));
+    }
+
+    #[test]
+    fn a_refreshed_token_inherits_the_authentication_context() {
+        let previous = StoredToken::new(params(&jwt(r#"{"acr":"phr","auth_time":1}"#)));
+        let token = StoredToken::new(params("opaque-token")).inherit(&previous);
+        assert_eq!(token.acr.as_deref(), Some("phr"));
+        assert_eq!(token.auth_time, Some(1));
+    }
+
+    #[test]
+    fn granted_authorization_details_come_from_the_response() {
+        let body = br#"{"access_token":"a","token_type":"Bearer","authorization_details":[{"type":"account_information"}]}"#;
+        let token = StoredToken::from_response(&body[..]).unwrap();
+        let details = token.authorization_details.clone().unwrap();
+        assert_eq!(details[0]["type"], "account_information");
+
+        let token = StoredToken::new(params("opaque-token")).inherit(&token);
+        assert_eq!(token.authorization_details, Some(details));
+    }
+
+    #[test]
+    fn the_remaining_lifetime_counts_from_the_issuance() {
+        let hour = Duration::from_secs(3600);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut token = StoredToken::new(params("opaque-token"));
+        assert_eq!(token.remaining(hour), None);
+
+        token.params.issued_at = Some(now - 600);
+        let remaining = token.remaining(hour).unwrap();
+        assert!(remaining <= Duration::from_secs(3000), "{remaining:?}");
+        assert!(remaining > Duration::from_secs(2990), "{remaining:?}");
+
+        token.params.expires_in = Some(300);
+        assert_eq!(token.remaining(hour), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn the_absolute_expiry_falls_back_to_the_exp_claim() {
+        let body = br#"{"access_token":"a","token_type":"Bearer","expires_in":600}"#;
+        let token = StoredToken::from_response(&body[..]).unwrap();
+        let expires_at = token.expires_at.unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert!(
+            (now + 599..=now + 600).contains(&expires_at),
+            "{expires_at}"
+        );
+
+        let token = StoredToken::new(params(&jwt(r#"{"exp":1700003600}"#)));
+        assert_eq!(token.expires_at, Some(1700003600));
+        assert_eq!(token.remaining(Duration::ZERO), Some(Duration::ZERO));
+
+        let token = StoredToken::new(params("opaque-token"));
+        assert_eq!(token.expires_at, None);
+    }
+
+    #[test]
+    fn the_refresh_token_expiry_comes_from_the_response() {
+        let body = br#"{"access_token":"a","token_type":"Bearer","refresh_token":"r","refresh_token_expires_in":86400}"#;
+        let token = StoredToken::from_response(&body[..]).unwrap();
+        let remaining = token.refresh_token_remaining().unwrap();
+        assert!(remaining > Duration::from_secs(86390), "{remaining:?}");
+
+        let json = String::try_from(&token).unwrap();
+        assert!(!json.contains("refresh_token_expires_in"), "{json}");
+        let stored = StoredToken::try_from(json.as_bytes()).unwrap();
+        assert_eq!(
+            stored.refresh_token_expires_at,
+            token.refresh_token_expires_at
+        );
+
+        let token = StoredToken::new(params("opaque-token"));
+        assert_eq!(token.refresh_token_remaining(), None);
+    }
+
+    #[test]
+    fn missing_scopes_are_the_requested_ones_not_granted() {
+        let requested = ["mail".to_owned(), "calendar".to_owned()];
+
+        let mut token = StoredToken::new(params("opaque-token"));
+        assert!(token.missing_scopes(&requested).is_empty());
+
+        token.params.scope = Some("calendar  mail offline_access".to_owned());
+        assert!(token.missing_scopes(&requested).is_empty());
+
+        token.params.scope = Some("Mail".to_owned());
+        assert_eq!(token.missing_scopes(&requested), ["mail", "calendar"]);
+    }
+
+    #[test]
+    fn google_userinfo_scopes_stand_for_email_and_profile() {
+        let requested = [
+            "openid".to_owned(),
+            "email".to_owned(),
+            "profile".to_owned(),
+        ];
+
+        let mut token = StoredToken::new(params("opaque-token"));
+        token.params.scope = Some(
+            "https://www.googleapis.com/auth/userinfo.email openid \
+             https://www.googleapis.com/auth/userinfo.profile"
+                .to_owned(),
+        );
+        assert!(token.missing_scopes(&requested).is_empty());
+
+        token.params.scope = Some("openid email".to_owned());
+        let requested = ["https://www.googleapis.com/auth/userinfo.email".to_owned()];
+        assert!(token.missing_scopes(&requested).is_empty());
+    }
+
+    #[test]
+    fn members_named_like_computed_fields_are_not_trusted() {
+        let body = br#"{"access_token":"a","token_type":"Bearer","expires_in":600,"expires_at":"2030-01-01T00:00:00Z","reauth_required":true,"refresh_token_expiring_notified":true,"provenance":{"client_id":"x","grant":"device"}}"#;
+        let token = StoredToken::from_response(&body[..]).unwrap();
+        assert!(token.expires_at.is_some());
+        assert!(!token.reauth_required);
+        assert!(!token.refresh_token_expiring_notified);
+        assert!(token.provenance.is_none());
+    }
+
+    #[test]
+    fn the_stored_json_stays_a_token_response() {
+        let token = StoredToken::new(params(&jwt(r#"{"acr":"phr"}"#)));
+        let json = String::try_from(&token).unwrap();
+        let token = StoredToken::try_from(json.as_bytes()).unwrap();
+        assert_eq!(token.params.token_type, "Bearer");
+        assert_eq!(token.acr.as_deref(), Some("phr"));
+
+        let legacy = br#"{"access_token":"a","token_type":"Bearer","expires_in":60}"#;
+        let token = StoredToken::try_from(&legacy[..]).unwrap();
+        assert_eq!(token.params.expires_in, Some(60));
+        assert_eq!(token.acr, None);
+    }
+}
@@ -129,6 +129,12 @@ fn run(printer: &mut impl Printer, config_paths: &[PathBuf]) -> Result<()> {
     // supports widen the options of a client not bound to a registered
     // set.
     let metadata = probe_metadata(&config);
+    config.issuer = metadata.as_ref().map(|metadata| issuer(&metadata.issuer));
+    // NOTE: io-pim-discovery drops the iss parameter flag, so its
+    // document is read once more, raw, for that one member.
+    config.authorization_response_iss = metadata
+        .as_ref()
+        .is_some_and(|metadata| search::iss_parameter_supported(&metadata.issuer));
 
     // NOTE: application first: what a token may request is a property
     // of the application requesting it.
@@ -382,6 +388,18 @@ fn stop_undiscovered(input: &str) -> Result<OauthConfig> {
     )
 }
 
+/// The issuer identifier as the metadata spells it.
+///
+/// The metadata issuer went through URL parsing, which gives a bare
+/// origin a `/` path: dropped again, as issuers are compared verbatim
+/// and are almost always written without it.
+fn issuer(issuer: &Url) -> String {
+    match issuer.path() {
+        "/" => issuer.as_str().trim_end_matches('/').to_owned(),
+        _ => issuer.to_string(),
+    }
+}
+
 /// Fetches the authorization server metadata behind the chosen grant,
 /// behind a spinner since it is a network round trip. Absent when the
 /// server publishes none, which only means fewer scope options and no
@@ -444,6 +462,14 @@ pub struct OauthConfig {
     /// The wire name of the discovered grant flow.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub grant: Option<&'static str>,
+    /// The issuer identifier read from the authorization server
+    /// metadata, checked against authorization responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Whether the authorization server always sends `iss` in its
+    /// authorization responses, per its metadata.
+    #[serde(skip_serializing_if = "core::ops::Not::not")]
+    pub authorization_response_iss: bool,
     /// The discovered endpoints.
     pub endpoints: Endpoints,
     /// The scopes the token will carry.
@@ -472,6 +498,8 @@ impl OauthConfig {
             client_id: None,
             client_secret: None,
             grant: None,
+            issuer: None,
+            authorization_response_iss: false,
             endpoints: Endpoints::default(),
             scopes: Vec::new(),
             extras: BTreeMap::new(),
@@ -537,6 +565,12 @@ impl fmt::Display for OauthConfig {
         if let Some(grant) = &self.grant {
             writeln!(f, "grant = {}", toml_string(grant))?;
         }
+        if let Some(issuer) = &self.issuer {
+            writeln!(f, "issuer = {}", toml_string(issuer))?;
+        }
+        if self.authorization_response_iss {
+            writeln!(f, "authorization-response-iss = true")?;
+        }
         if let Some(url) = &self.endpoints.authorization {
             writeln!(f, "endpoints.authorization = {}", toml_string(url))?;
         }
@@ -773,6 +807,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn metadata_issuer_keeps_its_written_form() {
+        let origin = Url::parse("https://accounts.google.com").unwrap();
+        assert_eq!(issuer(&origin), "https://accounts.google.com");
+
+        let tenant = Url::parse("https://login.microsoftonline.com/common/v2.0").unwrap();
+        assert_eq!(
+            issuer(&tenant),
+            "https://login.microsoftonline.com/common/v2.0"
+        );
+    }
+
     #[test]
     fn a_discovered_grant_becomes_its_config_shape() {
         let code = OauthConfig::from(Discovered {
@@ -881,6 +927,8 @@ mod tests {
             name: "posteo".to_string(),
             client_id: Some("client".to_string()),
             grant: Some("authorization-code"),
+            issuer: Some("https://as".to_string()),
+            authorization_response_iss: true,
             endpoints: Endpoints {
                 authorization: Some("https://as/auth".to_string()),
                 token: Some("https://as/token".to_string()),
@@ -923,6 +971,8 @@ mod tests {
         assert_eq!(account.client_id, "client");
         assert_eq!(account.grant, GrantConfig::AuthorizationCode);
         assert_eq!(account.scopes, ["mail", "offline_access"]);
+        assert_eq!(account.issuer.as_deref(), Some("https://as"));
+        assert!(account.authorization_response_iss);
         assert_eq!(
             account.extras.get("resource").map(String::as_str),
             Some("https://api.fastmail.com/jmap/session")
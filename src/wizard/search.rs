@@ -30,8 +30,11 @@ use io_pim_discovery::{
 };
 use log::debug;
 use pimalaya_stream::tls::{Rustls, Tls};
+use serde::Deserialize;
 use url::Url;
 
+use crate::http;
+
 /// Fallback DNS resolver when the system one cannot be determined:
 /// Cloudflare's `1.1.1.1` over TCP.
 const DEFAULT_RESOLVER: &str = "tcp://1.1.1.1:53";
@@ -171,6 +174,55 @@ pub fn metadata(hosts: &BTreeSet<String>) -> Option<DiscoveryOauthServerMetadata
     None
 }
 
+/// Whether the authorization server at `issuer` always sends `iss` in
+/// its authorization responses (RFC 9207), per the
+/// `authorization_response_iss_parameter_supported` member of its
+/// metadata, read from the first well-known document answering.
+///
+/// io-pim-discovery's metadata struct drops that member, so the
+/// document is fetched again, raw. Any failure reads as `false`, the
+/// lenient default.
+pub fn iss_parameter_supported(issuer: &Url) -> bool {
+    let urls = [
+        DiscoveryOauthServerMetadata::well_known_url(issuer),
+        DiscoveryOauthServerMetadata::openid_well_known_url(issuer),
+    ];
+
+    for url in urls {
+        let res = match http::get(&url, &wizard_tls()) {
+            Ok(res) if res.status.is_success() => res,
+            Ok(res) => {
+                debug!("metadata {url} answered {}", *res.status);
+                continue;
+            }
+            Err(err) => {
+                debug!("read metadata {url}: {err:#}");
+                continue;
+            }
+        };
+
+        if let Some(supported) = iss_parameter_advertised(&res.body) {
+            return supported;
+        }
+    }
+
+    false
+}
+
+/// Reads `authorization_response_iss_parameter_supported` from a
+/// metadata document, absent meaning `false` (RFC 9207 section 3).
+/// `None` when the body is no metadata document.
+fn iss_parameter_advertised(body: &[u8]) -> Option<bool> {
+    #[derive(Deserialize)]
+    struct Metadata {
+        #[serde(default)]
+        authorization_response_iss_parameter_supported: bool,
+    }
+
+    let metadata: Metadata = serde_json::from_slice(body).ok()?;
+    Some(metadata.authorization_response_iss_parameter_supported)
+}
+
 /// TLS options for the wizard's HTTPS calls, pinned to HTTP/1.1: the
 /// discovery mechanisms only ever speak it to `_well-known` endpoints.
 pub fn wizard_tls() -> Tls {
@@ -636,4 +688,17 @@ mod tests {
         let tokenless = metadata_of(Some("https://as/auth"), None, None, &[]);
         assert!(grants_of(&tokenless).is_empty());
     }
+
+    #[test]
+    fn the_iss_parameter_flag_defaults_to_off() {
+        let advertised =
+            br#"{"issuer":"https://as","authorization_response_iss_parameter_supported":true}"#;
+        assert_eq!(iss_parameter_advertised(advertised), Some(true));
+
+        assert_eq!(
+            iss_parameter_advertised(br#"{"issuer":"https://as"}"#),
+            Some(false)
+        );
+        assert_eq!(iss_parameter_advertised(b"<html>"), None);
+    }
 }
@@ -0,0 +1,31 @@
+//! Authorization details e2e via the real binary and a local mock
+//! authorization server: the details the server granted are stored
+//! whatever the grant issued the token.
+
+mod common;
+
+use serde_json::Value;
+
+/// A token response granting payment initiation details.
+const GRANTED: &str = r#"{"access_token":"at-rar","token_type":"Bearer","expires_in":3600,"authorization_details":[{"type":"payment_initiation","actions":["initiate"],"instructedAmount":{"currency":"EUR","amount":"123.50"}}]}"#;
+
+fn assert_granted(stored: &Value) {
+    let details = &stored["authorization_details"][0];
+    assert_eq!(details["type"], "payment_initiation", "{stored}");
+    assert_eq!(details["instructedAmount"]["amount"], "123.50", "{stored}");
+}
+
+#[test]
+fn the_device_grant_stores_the_granted_details() {
+    assert_granted(&common::issue("device", GRANTED));
+}
+
+#[test]
+fn the_ciba_grant_stores_the_granted_details() {
+    assert_granted(&common::issue("ciba", GRANTED));
+}
+
+#[test]
+fn the_password_grant_stores_the_granted_details() {
+    assert_granted(&common::issue("password", GRANTED));
+}
@@ -0,0 +1,119 @@
+//! `token check` e2e via the real binary and a local mock token
+//! endpoint: the exit code tells the token state, and the token
+//! endpoint is only contacted with `--refresh`.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+use common::{Mock, now_secs, ortie};
+
+/// Starts a mock token endpoint answering every refresh with a fresh
+/// token.
+fn start_mock() -> Mock {
+    Mock::always(
+        "200 OK",
+        r#"{"access_token":"at-1","token_type":"Bearer","expires_in":3600}"#,
+    )
+}
+
+/// Writes a config bound to the mock, with `token` in storage when
+/// given. Returns the config path.
+fn write_config(dir: &Path, mock: &Mock, token: Option<String>) -> PathBuf {
+    common::write_config(dir, mock.addr, token.as_deref(), r#"scopes = ["mail"]"#)
+}
+
+/// A stored token expiring `expires_in` seconds from now, negative
+/// for an expired one, with the given extra members.
+fn token(expires_in: i64, extra: &str) -> Option<String> {
+    Some(format!(
+        r#"{{"access_token":"at-0","token_type":"Bearer","issued_at":1000,"expires_at":{}{extra}}}"#,
+        now_secs() as i64 + expires_in
+    ))
+}
+
+#[test]
+fn a_valid_token_exits_zero_unless_expiring_or_short_of_scopes() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, token(3600, r#","scope":"mail""#));
+
+    let out = ortie(dir.path(), &config, &["token", "check"]);
+    assert_eq!(out.status.code(), Some(0), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("test: valid, expires in"), "{stdout}");
+    assert!(!stdout.contains("at-0"), "{stdout}");
+
+    let out = ortie(dir.path(), &config, &["token", "check", "--within", "2h"]);
+    assert_eq!(out.status.code(), Some(10), "{out:?}");
+
+    let out = ortie(
+        dir.path(),
+        &config,
+        &[
+            "--json", "token", "check", "--scope", "mail", "--scope", "calendar",
+        ],
+    );
+    assert_eq!(out.status.code(), Some(30), "{out:?}");
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["state"], "reauth-required");
+    assert_eq!(report["exit_code"], 30);
+    assert_eq!(report["missing_scopes"], serde_json::json!(["calendar"]));
+
+    assert_eq!(mock.served(), 0);
+}
+
+#[test]
+fn an_expired_token_is_only_refreshed_on_demand() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, token(-60, r#","refresh_token":"rt-0""#));
+
+    let out = ortie(dir.path(), &config, &["token", "check"]);
+    assert_eq!(out.status.code(), Some(20), "{out:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "test: expired, refreshable"
+    );
+    assert_eq!(mock.served(), 0);
+
+    let out = ortie(dir.path(), &config, &["token", "check", "--refresh"]);
+    assert_eq!(out.status.code(), Some(0), "{out:?}");
+    assert_eq!(mock.served(), 1);
+
+    let stored = common::stored(dir.path());
+    assert!(stored.contains("at-1"), "{stored}");
+}
+
+#[test]
+fn an_unrefreshable_token_needs_authorizing_again() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+
+    let config = write_config(dir.path(), &mock, token(-60, ""));
+    let out = ortie(dir.path(), &config, &["token", "check", "--refresh"]);
+    assert_eq!(out.status.code(), Some(30), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("auth get --account test"), "{stdout}");
+
+    let rejected = r#","refresh_token":"rt-0","reauth_required":true"#;
+    let config = write_config(dir.path(), &mock, token(-60, rejected));
+    let out = ortie(dir.path(), &config, &["token", "check"]);
+    assert_eq!(out.status.code(), Some(30), "{out:?}");
+}
+
+#[test]
+fn a_missing_token_exits_forty() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, None);
+
+    let out = ortie(dir.path(), &config, &["--json", "token", "check"]);
+    assert_eq!(out.status.code(), Some(40), "{out:?}");
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["state"], "missing");
+    assert!(report["reason"].is_string(), "{report}");
+}
@@ -0,0 +1,193 @@
+//! CIBA grant e2e via the real binary and a local mock AS.
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+    process::Command,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+struct Mock {
+    addr: SocketAddr,
+    polls: Arc<AtomicUsize>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+fn start_mock() -> Mock {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let polls = Arc::new(AtomicUsize::new(0));
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let polls_t = Arc::clone(&polls);
+    let requests_t = Arc::clone(&requests);
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut buf = [0u8; 8192];
+            let Ok(n) = stream.read(&mut buf) else {
+                continue;
+            };
+            let req = String::from_utf8_lossy(&buf[..n]).into_owned();
+            let path = req
+                .lines()
+                .next()
+                .and_then(|l| l.split_whitespace().nth(1))
+                .unwrap_or("/")
+                .to_owned();
+            requests_t.lock().unwrap().push(req);
+            let (status, body): (u16, String) = if path.starts_with("/bc-authorize") {
+                (
+                    200,
+                    r#"{"auth_req_id":"req-test","expires_in":60,"interval":1}"#.into(),
+                )
+            } else if path.starts_with("/token") {
+                if polls_t.fetch_add(1, Ordering::SeqCst) == 0 {
+                    (400, r#"{"error":"authorization_pending"}"#.into())
+                } else {
+                    (
+                        200,
+                        r#"{"access_token":"at-ciba","token_type":"Bearer","expires_in":3600}"#
+                            .into(),
+                    )
+                }
+            } else {
+                (404, r#"{"error":"not_found"}"#.into())
+            };
+            let resp = format!(
+                "HTTP/1.1 {status} OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = stream.write_all(resp.as_bytes());
+        }
+    });
+    thread::sleep(Duration::from_millis(20));
+    Mock {
+        addr,
+        polls,
+        requests,
+    }
+}
+
+fn write_config(dir: &TempDir, addr: SocketAddr) -> (PathBuf, PathBuf) {
+    let token = dir.path().join("token.json");
+    std::fs::write(&token, b"").unwrap();
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+[accounts.ciba]
+default = true
+client-id = "c"
+client-secret.raw = "s"
+grant = "ciba"
+scopes = ["openid"]
+ciba.login-hint = "alice@example.com"
+ciba.binding-message = "W4SCT"
+endpoints.backchannel-authentication = "http://{addr}/bc-authorize"
+endpoints.token = "http://{addr}/token"
+storage.read.command = ["cat", "{t}"]
+storage.write.command = ["tee", "{t}"]
+"#,
+            t = token.display()
+        ),
+    )
+    .unwrap();
+    (config, token)
+}
+
+#[test]
+fn auth_get_json_then_resume_stores_token() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let (config, token) = write_config(&dir, mock.addr);
+    let bin = PathBuf::from(env!("CARGO_BIN_EXE_ortie"));
+
+    let get = Command::new(&bin)
+        .args(["-c", config.to_str().unwrap(), "--json", "auth", "get"])
+        .output()
+        .unwrap();
+    assert!(get.status.success(), "{get:?}");
+    let v: Value = serde_json::from_slice(&get.stdout).unwrap();
+    assert_eq!(v["auth_req_id"], "req-test");
+    assert_eq!(v["binding_message"], "W4SCT");
+    assert_eq!(mock.polls.load(Ordering::SeqCst), 0);
+
+    let authorize = mock.requests.lock().unwrap()[0].clone();
+    assert!(
+        authorize.contains("login_hint=alice%40example.com"),
+        "{authorize}"
+    );
+    assert!(authorize.contains("binding_message=W4SCT"), "{authorize}");
+    assert!(authorize.contains("scope=openid"), "{authorize}");
+
+    let resume = Command::new(&bin)
+        .args(["-c", config.to_str().unwrap(), "auth", "resume", "req-test"])
+        .output()
+        .unwrap();
+    assert!(resume.status.success(), "{resume:?}");
+    assert!(mock.polls.load(Ordering::SeqCst) >= 2);
+
+    let poll = mock.requests.lock().unwrap().last().unwrap().clone();
+    assert!(
+        poll.contains("grant_type=urn%3Aopenid%3Aparams%3Agrant-type%3Aciba"),
+        "{poll}"
+    );
+    assert!(poll.contains("auth_req_id=req-test"), "{poll}");
+
+    let stored: Value = serde_json::from_str(&std::fs::read_to_string(&token).unwrap()).unwrap();
+    assert_eq!(stored["access_token"], "at-ciba");
+}
+
+#[test]
+fn auth_get_login_hint_flag_overrides_the_account_hint() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let (config, _token) = write_config(&dir, mock.addr);
+    let bin = PathBuf::from(env!("CARGO_BIN_EXE_ortie"));
+
+    let get = Command::new(&bin)
+        .args(["-c", config.to_str().unwrap(), "--json", "auth", "get"])
+        .args(["--login-hint", "bob@example.com"])
+        .output()
+        .unwrap();
+    assert!(get.status.success(), "{get:?}");
+
+    let authorize = mock.requests.lock().unwrap()[0].clone();
+    assert!(
+        authorize.contains("login_hint=bob%40example.com"),
+        "{authorize}"
+    );
+    assert!(!authorize.contains("alice"), "{authorize}");
+}
+
+#[test]
+fn auth_get_sends_the_authorization_details() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let (config, _token) = write_config(&dir, mock.addr);
+    let mut toml = std::fs::read_to_string(&config).unwrap();
+    toml.push_str("authorization-details = [{ type = \"account_information\" }]\n");
+    std::fs::write(&config, toml).unwrap();
+    let bin = PathBuf::from(env!("CARGO_BIN_EXE_ortie"));
+
+    let get = Command::new(&bin)
+        .args(["-c", config.to_str().unwrap(), "--json", "auth", "get"])
+        .output()
+        .unwrap();
+    assert!(get.status.success(), "{get:?}");
+
+    let authorize = mock.requests.lock().unwrap()[0].clone();
+    assert!(
+        authorize.contains("authorization_details=%5B%7B%22type%22%3A%22account_information%22"),
+        "{authorize}"
+    );
+}
@@ -1,6 +1,8 @@
-//! Client credentials grants e2e via the real binary and a local
-//! mock authorization server: silent re-acquisition on expiry, fresh
-//! JWT assertion per mint, and the certificate renewal hint.
+//! Headless grants e2e via the real binary and a local mock
+//! authorization server: silent re-acquisition on expiry, fresh JWT
+//! assertion per mint, the certificate renewal hint, authorization
+//! details, the password grant refreshed through its refresh token,
+//! and configuration drift.
 
 use std::{
     io::{Read, Write},
@@ -9,7 +11,7 @@ use std::{
     process::Command,
     sync::{Arc, Mutex},
     thread,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use base64::{Engine, prelude::BASE64_STANDARD, prelude::BASE64_URL_SAFE_NO_PAD};
@@ -215,6 +217,86 @@ fn token_show_reacquires_an_expired_client_credentials_token() {
     );
 }
 
+#[test]
+fn token_show_min_validity_refreshes_a_token_expiring_sooner() {
+    let (addr, requests) = start_mock(200, TOKEN_RESPONSE);
+    let dir = TempDir::new().unwrap();
+    let (config, token) = write_config(
+        dir.path(),
+        addr,
+        "grant = \"client-credentials\"\nclient-secret.raw = \"s3cret\"",
+    );
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    std::fs::write(
+        &token,
+        format!(
+            r#"{{"access_token":"at-stale","token_type":"Bearer","expires_in":3600,"issued_at":{}}}"#,
+            now - 2700
+        ),
+    )
+    .unwrap();
+
+    let out = ortie(&config, &["--json", "token", "inspect"]);
+    assert!(out.status.success(), "{out:?}");
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    let remaining = report["remaining_lifetime"].as_u64().unwrap();
+    assert!((890..=900).contains(&remaining), "{remaining}");
+
+    let out = ortie(&config, &["token", "show", "--min-validity", "10m"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "at-stale");
+    assert!(requests.lock().unwrap().is_empty());
+
+    let out = ortie(&config, &["token", "show", "--min-validity", "30m"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "at-fresh");
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    let out = ortie(&config, &["token", "show", "--min-validity", "2h"]);
+    assert!(!out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("below the minimum validity of 2h"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn the_absolute_expiry_is_stored_and_exposed_to_hooks() {
+    let (addr, _) = start_mock(200, TOKEN_RESPONSE);
+    let dir = TempDir::new().unwrap();
+    let hook = dir.path().join("hook.txt");
+    let (config, token) = write_config(
+        dir.path(),
+        addr,
+        &format!(
+            "grant = \"client-credentials\"\nclient-secret.raw = \"s3cret\"\n\
+             hooks.on-issue.success.command = \"printf '%s' \\\"$EXPIRES_AT\\\" > {}\"",
+            hook.display()
+        ),
+    );
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let out = ortie(&config, &["auth", "get"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let stored: Value = serde_json::from_str(&std::fs::read_to_string(&token).unwrap()).unwrap();
+    let expires_at = stored["expires_at"].as_u64().unwrap();
+    assert!(
+        (now + 3600..=now + 3605).contains(&expires_at),
+        "{expires_at}"
+    );
+
+    let hook = std::fs::read_to_string(&hook).unwrap();
+    assert_eq!(hook, expires_at.to_string());
+}
+
 #[test]
 fn token_show_acquires_when_storage_is_empty() {
     let (addr, requests) = start_mock(200, TOKEN_RESPONSE);
@@ -234,6 +316,88 @@ fn token_show_acquires_when_storage_is_empty() {
     assert_eq!(requests.lock().unwrap().len(), 1);
 }
 
+#[test]
+fn a_configuration_drift_warns_then_reacquires_under_reauth() {
+    let (addr, requests) = start_mock(200, TOKEN_RESPONSE);
+    let dir = TempDir::new().unwrap();
+    let (config, token) = write_config(
+        dir.path(),
+        addr,
+        "grant = \"client-credentials\"\nclient-secret.raw = \"s3cret\"",
+    );
+
+    let out = ortie(&config, &["auth", "get"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let stored: Value = serde_json::from_str(&std::fs::read_to_string(&token).unwrap()).unwrap();
+    assert_eq!(stored["provenance"]["client_id"], "app-id");
+    assert_eq!(stored["provenance"]["grant"], "client-credentials");
+
+    let toml = std::fs::read_to_string(&config).unwrap().replace(
+        r#"scopes = ["https://graph.microsoft.com/.default"]"#,
+        r#"scopes = ["https://graph.microsoft.com/.default", "offline_access"]"#,
+    );
+    std::fs::write(&config, &toml).unwrap();
+
+    let out = ortie(&config, &["token", "show"]);
+    assert!(out.status.success(), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("scopes added offline_access"), "{stderr}");
+
+    let out = ortie(&config, &["--json", "token", "inspect"]);
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["drift"][0]["field"], "scopes");
+    assert_eq!(report["drift"][0]["added"][0], "offline_access");
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    std::fs::write(&config, format!("{toml}on-drift = \"reauth\"\n")).unwrap();
+
+    let out = ortie(&config, &["token", "show"]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(out.stderr.is_empty(), "{out:?}");
+    assert_eq!(requests.lock().unwrap().len(), 2);
+
+    let out = ortie(&config, &["--json", "token", "inspect"]);
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert!(report.get("drift").is_none(), "{report}");
+}
+
+#[test]
+fn auth_get_flags_do_not_count_as_a_configuration_drift() {
+    let (addr, requests) = start_mock(200, TOKEN_RESPONSE);
+    let dir = TempDir::new().unwrap();
+    let (config, token) = write_config(
+        dir.path(),
+        addr,
+        "grant = \"client-credentials\"\nclient-secret.raw = \"s3cret\"\non-drift = \"reauth\"",
+    );
+
+    let out = ortie(&config, &["auth", "get", "--scope", "offline_access"]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(
+        requests.lock().unwrap()[0]
+            .body
+            .contains("scope=offline_access"),
+        "{}",
+        requests.lock().unwrap()[0].body
+    );
+
+    let stored: Value = serde_json::from_str(&std::fs::read_to_string(&token).unwrap()).unwrap();
+    assert_eq!(
+        stored["provenance"]["scopes"][0],
+        "https://graph.microsoft.com/.default"
+    );
+
+    let out = ortie(&config, &["token", "show"]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(out.stderr.is_empty(), "{out:?}");
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    let out = ortie(&config, &["--json", "token", "inspect"]);
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert!(report.get("drift").is_none(), "{report}");
+}
+
 #[test]
 fn jwt_kind_mints_a_fresh_assertion_per_reacquisition() {
     let (addr, requests) = start_mock(200, TOKEN_RESPONSE);
@@ -361,3 +525,111 @@ fn invalid_client_on_the_jwt_kind_hints_certificate_renewal() {
         "{stdout}"
     );
 }
+
+#[test]
+fn password_grant_warns_then_refreshes_with_its_refresh_token() {
+    let (addr, requests) = start_mock(
+        200,
+        r#"{"access_token":"at-fresh","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-fresh"}"#,
+    );
+    let dir = TempDir::new().unwrap();
+    let (config, token) = write_config(
+        dir.path(),
+        addr,
+        "grant = \"password\"\nusername = \"svc-backup\"\npassword.raw = \"p@ss\"\n\
+         authorization-details = [{ type = \"account_information\" }]",
+    );
+
+    let out = ortie(&config, &["--json", "auth", "get"]);
+    assert!(out.status.success(), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("deprecated"), "{stderr}");
+    serde_json::from_slice::<Value>(&out.stdout).expect("stdout stays JSON");
+
+    let stored: Value = serde_json::from_str(&std::fs::read_to_string(&token).unwrap()).unwrap();
+    assert_eq!(stored["refresh_token"], "rt-fresh");
+
+    let out = ortie(&config, &["token", "refresh"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert!(
+        requests[0].body.contains("grant_type=password"),
+        "{}",
+        requests[0].body
+    );
+    assert!(
+        requests[0].body.contains("username=svc-backup"),
+        "{}",
+        requests[0].body
+    );
+    assert!(
+        requests[0].body.contains("password=p%40ss"),
+        "{}",
+        requests[0].body
+    );
+    assert!(
+        requests[0]
+            .body
+            .contains("authorization_details=%5B%7B%22type"),
+        "{}",
+        requests[0].body
+    );
+    assert!(
+        requests[1].body.contains("grant_type=refresh_token"),
+        "{}",
+        requests[1].body
+    );
+}
+
+#[test]
+fn authorization_details_ride_the_token_request_and_are_stored() {
+    let response = r#"{"access_token":"at-rar","token_type":"Bearer","expires_in":3600,"authorization_details":[{"type":"payment_initiation","actions":["initiate"],"instructedAmount":{"currency":"EUR","amount":"123.50"}}]}"#;
+    let (addr, requests) = start_mock(200, response);
+    let dir = TempDir::new().unwrap();
+    let (config, token) = write_config(
+        dir.path(),
+        addr,
+        "grant = \"client-credentials\"\nclient-secret.raw = \"s3cret\"\n\
+         authorization-details = [{ type = \"payment_initiation\", actions = [\"initiate\"] }]",
+    );
+
+    let out = ortie(&config, &["auth", "get"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let requests = requests.lock().unwrap();
+    let form: Vec<(String, String)> = url::form_urlencoded::parse(requests[0].body.as_bytes())
+        .into_owned()
+        .collect();
+    let details = form
+        .iter()
+        .find(|(key, _)| key == "authorization_details")
+        .map(|(_, value)| serde_json::from_str::<Value>(value).unwrap())
+        .expect("authorization_details in the token request");
+    assert_eq!(details[0]["type"], "payment_initiation");
+    assert!(form.contains(&("grant_type".into(), "client_credentials".into())));
+    assert!(
+        requests[0]
+            .head
+            .to_ascii_lowercase()
+            .contains("authorization: basic"),
+        "{}",
+        requests[0].head
+    );
+
+    let stored: Value = serde_json::from_str(&std::fs::read_to_string(&token).unwrap()).unwrap();
+    assert_eq!(stored["access_token"], "at-rar");
+    assert_eq!(
+        stored["authorization_details"][0]["instructedAmount"]["amount"],
+        "123.50"
+    );
+
+    let out = ortie(&config, &["token", "inspect"]);
+    assert!(out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("With authorization details: payment_initiation"),
+        "{stdout}"
+    );
+}
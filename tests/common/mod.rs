@@ -0,0 +1,198 @@
+//! Shared e2e fixtures: a local mock token endpoint answering from a
+//! script, an account config bound to it, and a runner for the real
+//! binary.
+
+#![allow(dead_code)]
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+/// A local mock token endpoint, counting the requests it served.
+pub struct Mock {
+    /// The address the mock listens on.
+    pub addr: SocketAddr,
+    served: Arc<AtomicUsize>,
+}
+
+impl Mock {
+    /// Starts a mock answering each request with what `respond`
+    /// returns for its number and raw content, `None` dropping the
+    /// connection unanswered.
+    pub fn start(respond: impl Fn(usize, &str) -> Option<String> + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = Arc::new(AtomicUsize::new(0));
+        let served_t = Arc::clone(&served);
+
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buf = [0u8; 8192];
+                let Ok(n) = stream.read(&mut buf) else {
+                    continue;
+                };
+                let req = String::from_utf8_lossy(&buf[..n]);
+
+                let n = served_t.fetch_add(1, Ordering::SeqCst);
+                let Some(resp) = respond(n, &req) else {
+                    continue;
+                };
+                let _ = stream.write_all(resp.as_bytes());
+            }
+        });
+
+        thread::sleep(Duration::from_millis(20));
+        Self { addr, served }
+    }
+
+    /// Starts a mock answering each request with the next scripted
+    /// response, then the last one forever.
+    pub fn scripted(script: Vec<Option<String>>) -> Self {
+        Self::start(move |n, _| script[n.min(script.len() - 1)].clone())
+    }
+
+    /// Starts a mock answering every request with `body` and the
+    /// given status.
+    pub fn always(status: &str, body: &str) -> Self {
+        Self::scripted(vec![response(status, "", body)])
+    }
+
+    /// The number of requests served so far.
+    pub fn served(&self) -> usize {
+        self.served.load(Ordering::SeqCst)
+    }
+}
+
+/// A raw JSON HTTP response, `headers` being CRLF-terminated lines.
+pub fn response(status: &str, headers: &str, body: &str) -> Option<String> {
+    Some(format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    ))
+}
+
+/// Writes the config of a default `test` account bound to `addr`,
+/// storing its token in `token.json`, with `token` written there when
+/// given and `lines` appended to the account. Returns the config
+/// path.
+pub fn write_config(dir: &Path, addr: SocketAddr, token: Option<&str>, lines: &str) -> PathBuf {
+    let path = dir.join("token.json");
+
+    if let Some(token) = token {
+        std::fs::write(&path, token).unwrap();
+    }
+
+    let config = dir.join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+[accounts.test]
+default = true
+client-id = "app-id"
+endpoints.authorization = "http://{addr}/authorize"
+endpoints.token = "http://{addr}/token"
+storage.read.command = ["cat", "{t}"]
+storage.write.command = ["tee", "{t}"]
+{lines}
+"#,
+            t = path.display(),
+        ),
+    )
+    .unwrap();
+
+    config
+}
+
+/// Issues a token through `grant`, one of the grants answering
+/// from the token endpoint without a browser (`device`, `ciba`,
+/// `password`), against a mock whose token endpoint answers `body`.
+/// Returns the stored token.
+pub fn issue(grant: &str, body: &str) -> Value {
+    let token = body.to_owned();
+    let mock = Mock::start(move |_, req| {
+        if req.starts_with("POST /devicecode") {
+            response(
+                "200 OK",
+                "",
+                r#"{"device_code":"dc-test","user_code":"USER","verification_uri":"http://localhost/d","expires_in":60,"interval":1}"#,
+            )
+        } else if req.starts_with("POST /bc-authorize") {
+            response(
+                "200 OK",
+                "",
+                r#"{"auth_req_id":"req-test","expires_in":60,"interval":1}"#,
+            )
+        } else {
+            response("200 OK", "", &token)
+        }
+    });
+
+    let addr = mock.addr;
+    let lines = match grant {
+        "device" => {
+            format!(
+                "grant = \"device\"\nendpoints.device-authorization = \"http://{addr}/devicecode\""
+            )
+        }
+        "ciba" => format!(
+            "grant = \"ciba\"\nciba.login-hint = \"alice@example.com\"\n\
+             endpoints.backchannel-authentication = \"http://{addr}/bc-authorize\""
+        ),
+        "password" => "grant = \"password\"\nusername = \"alice\"\npassword.raw = \"p\"".into(),
+        grant => panic!("no headless fixture for the {grant} grant"),
+    };
+
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), addr, None, &lines);
+
+    let out = ortie(dir.path(), &config, &["auth", "get", "--wait"]);
+    assert!(out.status.success(), "{out:?}");
+
+    serde_json::from_str(&stored(dir.path())).unwrap()
+}
+
+/// The stored token file written by [`write_config`].
+pub fn stored(dir: &Path) -> String {
+    std::fs::read_to_string(dir.join("token.json")).unwrap()
+}
+
+/// The hook output file, for hook commands writing to it.
+pub fn hook_path(dir: &Path) -> PathBuf {
+    dir.join("hook.txt")
+}
+
+/// The real binary run against `config`, with its runtime directory
+/// (where refresh locks live) in `dir`.
+pub fn command(dir: &Path, config: &Path, args: &[&str]) -> Command {
+    let mut cmd = Command::new(PathBuf::from(env!("CARGO_BIN_EXE_ortie")));
+    cmd.arg("-c")
+        .arg(config)
+        .args(args)
+        .env("XDG_RUNTIME_DIR", dir);
+    cmd
+}
+
+/// Runs the real binary to completion, see [`command`].
+pub fn ortie(dir: &Path, config: &Path, args: &[&str]) -> Output {
+    command(dir, config, args).output().unwrap()
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
@@ -162,3 +162,95 @@ storage.write.command = ["tee", "{t}"]
         "token show should print the issued token; stdout: {stdout}"
     );
 }
+
+#[test]
+fn auth_get_wait_json_streams_events() {
+    let (addr, polls, _h) = start_mock();
+    let dir = TempDir::new().unwrap();
+    let token = dir.path().join("token.json");
+    std::fs::write(&token, b"").unwrap();
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+[accounts.device]
+default = true
+client-id = "c"
+grant = "device"
+endpoints.device-authorization = "http://{addr}/devicecode"
+endpoints.token = "http://{addr}/token"
+storage.read.command = ["cat", "{t}"]
+storage.write.command = ["tee", "{t}"]
+"#,
+            t = token.display()
+        ),
+    )
+    .unwrap();
+    let bin = PathBuf::from(env!("CARGO_BIN_EXE_ortie"));
+
+    let get = Command::new(&bin)
+        .args([
+            "-c",
+            config.to_str().unwrap(),
+            "--json",
+            "auth",
+            "get",
+            "--wait",
+        ])
+        .output()
+        .unwrap();
+    assert!(get.status.success(), "{get:?}");
+    assert!(polls.load(Ordering::SeqCst) >= 2);
+
+    let events: Vec<Value> = String::from_utf8_lossy(&get.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let names: Vec<&str> = events.iter().filter_map(|e| e["event"].as_str()).collect();
+    assert_eq!(names, ["device_code", "polling", "polling", "issued"]);
+    assert_eq!(events[0]["user_code"], "USER");
+    assert_eq!(events[3]["token_type"], "Bearer");
+
+    let stored: Value = serde_json::from_str(&std::fs::read_to_string(&token).unwrap()).unwrap();
+    assert_eq!(stored["access_token"], "at-test");
+}
+
+#[test]
+fn auth_get_fires_the_authorize_hook_with_the_user_code() {
+    let (addr, _polls, _h) = start_mock();
+    let dir = TempDir::new().unwrap();
+    let token = dir.path().join("token.json");
+    let hook = dir.path().join("hook.txt");
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+[accounts.device]
+default = true
+client-id = "c"
+grant = "device"
+endpoints.device-authorization = "http://{addr}/devicecode"
+endpoints.token = "http://{addr}/token"
+storage.read.command = ["cat", "{t}"]
+storage.write.command = ["tee", "{t}"]
+hooks.on-authorize.command = "printf '%s %s %s' \"$USER_CODE\" \"$VERIFICATION_URI\" \"$EXPIRES_IN\" > {h}"
+"#,
+            t = token.display(),
+            h = hook.display(),
+        ),
+    )
+    .unwrap();
+    let bin = PathBuf::from(env!("CARGO_BIN_EXE_ortie"));
+
+    let get = Command::new(&bin)
+        .args(["-c", config.to_str().unwrap(), "--json", "auth", "get"])
+        .output()
+        .unwrap();
+    assert!(get.status.success(), "{get:?}");
+    assert_eq!(
+        std::fs::read_to_string(&hook).unwrap(),
+        format!("USER http://{addr}/d 60"),
+    );
+}
@@ -0,0 +1,111 @@
+//! `on-invalid-grant` e2e via the real binary and a local mock token
+//! endpoint rejecting the refresh token: the account is marked as
+//! needing a new authorization, and the exit code tells.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+use common::{Mock, hook_path, ortie};
+
+/// Starts a mock token endpoint answering every refresh with
+/// `invalid_grant`.
+fn start_mock() -> Mock {
+    Mock::always(
+        "400 Bad Request",
+        r#"{"error":"invalid_grant","error_description":"Token has been revoked"}"#,
+    )
+}
+
+/// Writes a config bound to the mock with an expired token in storage
+/// and an on-invalid-grant hook writing `$ERROR` to `hook.txt`.
+/// Returns the config path.
+fn write_config(dir: &Path, mock: &Mock, policy: &str) -> PathBuf {
+    common::write_config(
+        dir,
+        mock.addr,
+        Some(
+            r#"{"access_token":"at-0","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-0","issued_at":1000}"#,
+        ),
+        &format!(
+            r#"
+{policy}
+hooks.on-invalid-grant.command = "printf '%s' \"$ERROR\" > {h}"
+"#,
+            h = hook_path(dir).display()
+        ),
+    )
+}
+
+#[test]
+fn a_rejected_refresh_token_marks_the_account_for_reauthorization() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, r#"on-invalid-grant = "notify""#);
+
+    let out = ortie(dir.path(), &config, &["token", "show", "-r"]);
+    assert_eq!(out.status.code(), Some(30), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("auth get --account test"), "{stdout}");
+    assert_eq!(mock.served(), 1);
+
+    let hook = std::fs::read_to_string(hook_path(dir.path())).unwrap();
+    assert_eq!(hook, "InvalidGrant");
+
+    let out = ortie(dir.path(), &config, &["--json", "token", "inspect"]);
+    assert!(out.status.success(), "{out:?}");
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["reauth_required"], true);
+    assert_eq!(report["refresh_token"], "rt-0");
+
+    // NOTE: a rejected refresh token is not sent again.
+    let out = ortie(dir.path(), &config, &["token", "show", "-r"]);
+    assert_eq!(out.status.code(), Some(30), "{out:?}");
+    assert_eq!(mock.served(), 1);
+}
+
+#[test]
+fn reauth_outside_a_terminal_notifies_instead() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, r#"on-invalid-grant = "reauth""#);
+
+    let out = ortie(dir.path(), &config, &["token", "refresh"]);
+    assert_eq!(out.status.code(), Some(30), "{out:?}");
+    assert!(hook_path(dir.path()).exists());
+}
+
+#[test]
+fn the_default_policy_fails_without_marking() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, "");
+
+    let out = ortie(dir.path(), &config, &["token", "show", "-r"]);
+    assert_eq!(out.status.code(), Some(1), "{out:?}");
+    assert!(!hook_path(dir.path()).exists());
+
+    let stored = common::stored(dir.path());
+    assert!(!stored.contains("reauth_required"), "{stored}");
+}
+
+#[test]
+fn token_check_reports_a_rejected_refresh_under_reauth() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, r#"on-invalid-grant = "reauth""#);
+
+    let out = ortie(
+        dir.path(),
+        &config,
+        &["--json", "token", "check", "--refresh"],
+    );
+    assert_eq!(out.status.code(), Some(30), "{out:?}");
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["state"], "reauth-required");
+    assert_eq!(mock.served(), 1);
+    assert!(hook_path(dir.path()).exists());
+}
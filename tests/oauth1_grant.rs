@@ -0,0 +1,179 @@
+//! OAuth 1.0a e2e via the real binary and a local mock provider: the
+//! three legs of `auth get`, then `token sign` with the stored token
+//! credentials.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use serde_json::Value;
+use tempfile::TempDir;
+use url::Url;
+
+/// Starts the mock provider, recording the `Authorization` header of
+/// every request it answers.
+fn start_mock() -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let headers = Arc::new(Mutex::new(Vec::new()));
+    let headers_t = Arc::clone(&headers);
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut buf = [0u8; 8192];
+            let Ok(n) = stream.read(&mut buf) else {
+                continue;
+            };
+            let req = String::from_utf8_lossy(&buf[..n]);
+            let path = req
+                .lines()
+                .next()
+                .and_then(|l| l.split_whitespace().nth(1))
+                .unwrap_or("/");
+
+            if let Some(header) = req.lines().find_map(|l| l.strip_prefix("Authorization: ")) {
+                headers_t.lock().unwrap().push(header.to_owned());
+            }
+
+            let body = if path.starts_with("/request_token") {
+                "oauth_token=temp&oauth_token_secret=temp-secret&oauth_callback_confirmed=true"
+            } else if path.starts_with("/access_token") {
+                "oauth_token=tok&oauth_token_secret=tok-secret&user_id=42"
+            } else {
+                "oauth_problem=not_found"
+            };
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = stream.write_all(resp.as_bytes());
+        }
+    });
+
+    thread::sleep(Duration::from_millis(20));
+    (addr, headers)
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn auth_get_runs_the_three_legs_then_token_sign_signs_with_them() {
+    let (addr, headers) = start_mock();
+    let port = free_port();
+    let dir = TempDir::new().unwrap();
+    let token = dir.path().join("token.json");
+    std::fs::write(&token, b"").unwrap();
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+[accounts.jira]
+default = true
+client-id = "consumer"
+client-secret.raw = "consumer-secret"
+grant = "oauth1"
+endpoints.request-token = "http://{addr}/request_token"
+endpoints.authorization = "http://{addr}/authorize"
+endpoints.access-token = "http://{addr}/access_token"
+endpoints.redirection = "http://127.0.0.1:{port}/callback"
+storage.read.command = ["cat", "{t}"]
+storage.write.command = ["tee", "{t}"]
+"#,
+            t = token.display()
+        ),
+    )
+    .unwrap();
+    let bin = PathBuf::from(env!("CARGO_BIN_EXE_ortie"));
+
+    let mut get = Command::new(&bin)
+        .args([
+            "-c",
+            config.to_str().unwrap(),
+            "--json",
+            "auth",
+            "get",
+            "--wait",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(get.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    let v: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(v["event"], "oauth1_authorization_request");
+    let uri = Url::parse(v["authorization_uri"].as_str().unwrap()).unwrap();
+    assert_eq!(uri.path(), "/authorize");
+    assert_eq!(uri.query(), Some("oauth_token=temp"));
+
+    // NOTE: the browser, landing on the callback with the verifier;
+    // the listener binds right after the event, so retry a little.
+    let mut browser = (0..50)
+        .find_map(|_| {
+            TcpStream::connect(("127.0.0.1", port))
+                .inspect_err(|_| thread::sleep(Duration::from_millis(20)))
+                .ok()
+        })
+        .unwrap();
+    browser
+        .write_all(b"GET /callback?oauth_token=temp&oauth_verifier=ver HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n")
+        .unwrap();
+    let mut page = String::new();
+    browser.read_to_string(&mut page).unwrap();
+    assert!(page.starts_with("HTTP/1.1 200"), "{page}");
+
+    let status = get.wait().unwrap();
+    assert!(status.success());
+
+    let headers = headers.lock().unwrap().clone();
+    assert!(
+        headers[0].contains("oauth_callback=\"http%3A%2F%2F127.0.0.1%3A"),
+        "{headers:?}"
+    );
+    assert!(headers[0].contains("oauth_signature_method=\"HMAC-SHA1\""));
+    assert!(headers[1].contains("oauth_token=\"temp\""), "{headers:?}");
+    assert!(headers[1].contains("oauth_verifier=\"ver\""));
+
+    let stored: Value = serde_json::from_str(&std::fs::read_to_string(&token).unwrap()).unwrap();
+    assert_eq!(stored["access_token"], "tok");
+    assert_eq!(stored["token_type"], "OAuth");
+    assert_eq!(stored["oauth_token_secret"], "tok-secret");
+
+    let sign = Command::new(&bin)
+        .args([
+            "-c",
+            config.to_str().unwrap(),
+            "token",
+            "sign",
+            "--method",
+            "POST",
+            "--url",
+            "https://jira.example.com/rest/api/2/issue?expand=names",
+        ])
+        .output()
+        .unwrap();
+    assert!(sign.status.success(), "{sign:?}");
+    let header = String::from_utf8(sign.stdout).unwrap();
+    assert!(header.starts_with("Authorization: OAuth "), "{header}");
+    assert!(
+        header.contains("oauth_consumer_key=\"consumer\""),
+        "{header}"
+    );
+    assert!(header.contains("oauth_token=\"tok\""), "{header}");
+    assert!(header.contains("oauth_signature=\""), "{header}");
+}
@@ -0,0 +1,124 @@
+//! Pushed authorization requests e2e via the real binary and a local
+//! mock authorization server: the request parameters reach the pushed
+//! authorization request endpoint, and the authorization URI carries
+//! the `request_uri` it answered.
+
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde_json::Value;
+use tempfile::TempDir;
+use url::Url;
+
+use common::{Mock, ortie};
+
+const REQUEST_URI: &str = "urn:ietf:params:oauth:request_uri:bwc4JK-ESC0w8acc191e-Y1LTC2";
+
+/// Starts a mock answering every request with `status` and `body`,
+/// keeping the raw requests.
+fn start_mock(status: &'static str, body: &'static str) -> (Mock, Arc<Mutex<Vec<String>>>) {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let requests_t = Arc::clone(&requests);
+
+    let mock = Mock::start(move |_, req| {
+        requests_t.lock().unwrap().push(req.to_owned());
+        common::response(status, "", body)
+    });
+
+    (mock, requests)
+}
+
+/// Writes an authorization code account pushing its requests to the
+/// mock. Returns the config path.
+fn write_config(dir: &Path, mock: &Mock) -> PathBuf {
+    common::write_config(
+        dir,
+        mock.addr,
+        None,
+        &format!(
+            r#"
+client-secret.raw = "s3cret"
+scopes = ["openid", "payments"]
+endpoints.pushed-authorization-request = "http://{}/par"
+endpoints.redirection = "http://127.0.0.1:8080/callback"
+"#,
+            mock.addr
+        ),
+    )
+}
+
+/// The form body of a raw request.
+fn form(req: &str) -> Vec<(String, String)> {
+    let (_, body) = req.split_once("\r\n\r\n").unwrap();
+    url::form_urlencoded::parse(body.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+fn param<'a>(pairs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    pairs
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+#[test]
+fn auth_get_pushes_the_request_and_hands_out_its_request_uri() {
+    let body = r#"{"request_uri":"urn:ietf:params:oauth:request_uri:bwc4JK-ESC0w8acc191e-Y1LTC2","expires_in":60}"#;
+    let (mock, requests) = start_mock("201 Created", body);
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock);
+
+    let out = ortie(dir.path(), &config, &["--json", "auth", "get"]);
+    assert!(out.status.success(), "{out:?}");
+    let v: Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    let uri = Url::parse(v["authorization_uri"].as_str().unwrap()).unwrap();
+    assert_eq!(uri.path(), "/authorize");
+    let query: Vec<(String, String)> = uri.query_pairs().into_owned().collect();
+    assert_eq!(
+        query,
+        [
+            ("client_id".to_owned(), "app-id".to_owned()),
+            ("request_uri".to_owned(), REQUEST_URI.to_owned()),
+        ]
+    );
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].starts_with("POST /par "), "{}", requests[0]);
+    assert!(
+        requests[0].contains("Authorization: Basic "),
+        "{}",
+        requests[0]
+    );
+
+    let pushed = form(&requests[0]);
+    assert_eq!(param(&pushed, "response_type"), Some("code"));
+    assert_eq!(param(&pushed, "client_id"), Some("app-id"));
+    assert_eq!(param(&pushed, "scope"), Some("openid payments"));
+    assert_eq!(param(&pushed, "state"), v["state"].as_str());
+    assert_eq!(param(&pushed, "code_challenge_method"), Some("S256"));
+    assert_eq!(
+        param(&pushed, "redirect_uri"),
+        Some("http://127.0.0.1:8080/callback")
+    );
+}
+
+#[test]
+fn a_refused_push_fails_auth_get_with_the_server_error() {
+    let body = r#"{"error":"invalid_request","error_description":"redirect_uri not registered"}"#;
+    let (mock, _) = start_mock("400 Bad Request", body);
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock);
+
+    let out = ortie(dir.path(), &config, &["--json", "auth", "get"]);
+    assert!(!out.status.success(), "{out:?}");
+    let output = String::from_utf8_lossy(&out.stdout) + String::from_utf8_lossy(&out.stderr);
+    assert!(output.contains("InvalidRequest"), "{output}");
+    assert!(output.contains("redirect_uri not registered"), "{output}");
+}
@@ -0,0 +1,70 @@
+//! Concurrent refresh e2e via the real binary and a local mock token
+//! endpoint rotating its refresh tokens: callers racing on an expired
+//! token share one refresh instead of invalidating each other.
+
+mod common;
+
+use std::{process::Stdio, thread, time::Duration};
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+use common::{Mock, command, response};
+
+/// Starts a mock token endpoint answering each refresh slowly with a
+/// rotated refresh token.
+fn start_mock() -> Mock {
+    Mock::start(|n, req| {
+        thread::sleep(Duration::from_millis(300));
+
+        // NOTE: a rotated-out refresh token is refused, as a rotating
+        // server does.
+        if req.contains(&format!("refresh_token=rt-{n}")) {
+            let next = n + 1;
+            response(
+                "200 OK",
+                "",
+                &format!(
+                    r#"{{"access_token":"at-{next}","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-{next}"}}"#
+                ),
+            )
+        } else {
+            response("400 Bad Request", "", r#"{"error":"invalid_grant"}"#)
+        }
+    })
+}
+
+#[test]
+fn concurrent_auto_refreshes_share_one_refresh() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = common::write_config(
+        dir.path(),
+        mock.addr,
+        Some(
+            r#"{"access_token":"at-0","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-0","issued_at":1000}"#,
+        ),
+        "",
+    );
+
+    let children: Vec<_> = (0..3)
+        .map(|_| {
+            command(dir.path(), &config, &["token", "show", "-r"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+
+    for child in children {
+        let out = child.wait_with_output().unwrap();
+        assert!(out.status.success(), "{out:?}");
+        assert_eq!(String::from_utf8_lossy(&out.stdout), "at-1");
+    }
+
+    assert_eq!(mock.served(), 1);
+
+    let stored: Value = serde_json::from_str(&common::stored(dir.path())).unwrap();
+    assert_eq!(stored["refresh_token"], "rt-1");
+}
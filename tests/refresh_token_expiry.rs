@@ -0,0 +1,155 @@
+//! Refresh token expiry e2e via the real binary and a local mock token
+//! endpoint: the expiry is stored from the response or the account
+//! lifetime, whatever the grant, and the expiring hook fires once.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+use common::{Mock, hook_path, now_secs, ortie};
+
+/// Writes a config bound to the mock with an expired token in storage
+/// and an expiring hook appending `$REFRESH_TOKEN_EXPIRES_IN` to
+/// `hook.txt`. Returns the config path.
+fn write_config(dir: &Path, mock: &Mock, lines: &str) -> PathBuf {
+    common::write_config(
+        dir,
+        mock.addr,
+        Some(
+            r#"{"access_token":"at-0","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-0","issued_at":1000}"#,
+        ),
+        &format!(
+            r#"
+{lines}
+hooks.on-refresh-token-expiring.command = "echo \"$REFRESH_TOKEN_EXPIRES_IN\" >> {h}"
+"#,
+            h = hook_path(dir).display()
+        ),
+    )
+}
+
+#[test]
+fn the_expiring_hook_fires_once_per_refresh_token() {
+    let mock = Mock::always(
+        "200 OK",
+        r#"{"access_token":"at-1","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-1","refresh_token_expires_in":3600}"#,
+    );
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, r#"refresh-token-expiring-before = "2h""#);
+
+    let out = ortie(dir.path(), &config, &["token", "show", "-r"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "at-1");
+
+    let out = ortie(dir.path(), &config, &["token", "show"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let hook = std::fs::read_to_string(hook_path(dir.path())).unwrap();
+    let lines: Vec<_> = hook.lines().collect();
+    assert_eq!(lines.len(), 1, "{hook}");
+    let expires_in: u64 = lines[0].parse().unwrap();
+    assert!((3590..=3600).contains(&expires_in), "{expires_in}");
+
+    let out = ortie(dir.path(), &config, &["--json", "token", "inspect"]);
+    assert!(out.status.success(), "{out:?}");
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    let expires_at = report["refresh_token_expires_at"].as_u64().unwrap();
+    assert!(expires_at.abs_diff(now_secs() + 3600) <= 10, "{report}");
+    assert!(report["refresh_token_remaining_lifetime"].as_u64().unwrap() > 3500);
+}
+
+#[test]
+fn the_account_lifetime_stands_in_for_a_silent_server() {
+    let mock = Mock::always(
+        "200 OK",
+        r#"{"access_token":"at-1","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-1"}"#,
+    );
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, r#"refresh-token-lifetime = "7d""#);
+
+    let out = ortie(dir.path(), &config, &["token", "refresh"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let stored: Value = serde_json::from_str(&common::stored(dir.path())).unwrap();
+    let expires_at = stored["refresh_token_expires_at"].as_u64().unwrap();
+    assert!(
+        expires_at.abs_diff(now_secs() + 7 * 86400) <= 10,
+        "{stored}"
+    );
+
+    // NOTE: a week away is outside the default one-day warning.
+    assert!(!hook_path(dir.path()).exists());
+}
+
+#[test]
+fn a_refresh_token_sent_back_as_is_keeps_its_expiry() {
+    let mock = Mock::always(
+        "200 OK",
+        r#"{"access_token":"at-1","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-0"}"#,
+    );
+    let dir = TempDir::new().unwrap();
+    let config = write_config(
+        dir.path(),
+        &mock,
+        "refresh-token-lifetime = \"7d\"\nrefresh-token-expiring-before = \"2h\"",
+    );
+    let expires_at = now_secs() + 3600;
+    std::fs::write(
+        dir.path().join("token.json"),
+        format!(
+            r#"{{"access_token":"at-0","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-0","issued_at":1000,"refresh_token_expires_at":{expires_at},"refresh_token_expiring_notified":true}}"#
+        ),
+    )
+    .unwrap();
+
+    let out = ortie(dir.path(), &config, &["token", "refresh"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let stored: Value = serde_json::from_str(&common::stored(dir.path())).unwrap();
+    assert_eq!(stored["access_token"], "at-1");
+    assert_eq!(stored["refresh_token_expires_at"], expires_at);
+    assert_eq!(stored["refresh_token_expiring_notified"], true);
+    assert!(!hook_path(dir.path()).exists());
+}
+
+#[test]
+fn the_device_grant_stores_the_refresh_token_expiry() {
+    let mock = Mock::start(|_, req| {
+        if req.starts_with("POST /devicecode") {
+            common::response(
+                "200 OK",
+                "",
+                r#"{"device_code":"dc-test","user_code":"USER","verification_uri":"http://localhost/d","expires_in":60,"interval":1}"#,
+            )
+        } else {
+            common::response(
+                "200 OK",
+                "",
+                r#"{"access_token":"at-1","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-1","refresh_token_expires_in":3600}"#,
+            )
+        }
+    });
+    let dir = TempDir::new().unwrap();
+    let config = common::write_config(
+        dir.path(),
+        mock.addr,
+        None,
+        &format!(
+            r#"
+grant = "device"
+endpoints.device-authorization = "http://{}/devicecode"
+"#,
+            mock.addr
+        ),
+    );
+
+    let out = ortie(dir.path(), &config, &["auth", "get", "--wait"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let stored: Value = serde_json::from_str(&common::stored(dir.path())).unwrap();
+    let expires_at = stored["refresh_token_expires_at"].as_u64().unwrap();
+    assert!(expires_at.abs_diff(now_secs() + 3600) <= 10, "{stored}");
+}
@@ -0,0 +1,111 @@
+//! Token request retries e2e via the real binary and a local mock
+//! token endpoint scripted to fail: dropped connections, 5xx and 429
+//! are retried, `invalid_grant` is not, and a single-use refresh token
+//! is never resent once the server may have received it.
+
+mod common;
+
+use tempfile::TempDir;
+
+use common::{Mock, ortie, response, write_config};
+
+/// A fast retry policy.
+const RETRY: &str = "retry.attempts = 4\nretry.delay = \"10ms\"";
+
+#[test]
+fn transient_failures_are_retried_until_the_token_comes() {
+    let mock = Mock::scripted(vec![
+        None,
+        response("503 Service Unavailable", "", "busy"),
+        response("429 Too Many Requests", "Retry-After: 0\r\n", ""),
+        response(
+            "200 OK",
+            "",
+            r#"{"access_token":"at-fresh","token_type":"Bearer","expires_in":3600}"#,
+        ),
+    ]);
+    let dir = TempDir::new().unwrap();
+    let config = write_config(
+        dir.path(),
+        mock.addr,
+        Some(
+            r#"{"access_token":"at-stale","token_type":"Bearer","expires_in":3600,"issued_at":1000}"#,
+        ),
+        &format!("grant = \"client-credentials\"\nclient-secret.raw = \"s3cret\"\n{RETRY}"),
+    );
+
+    let out = ortie(dir.path(), &config, &["token", "show", "--auto-refresh"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "at-fresh");
+    assert_eq!(mock.served(), 4);
+}
+
+#[test]
+fn invalid_grant_is_never_retried() {
+    let mock = Mock::always("503 Service Unavailable", r#"{"error":"invalid_grant"}"#);
+    let dir = TempDir::new().unwrap();
+    let config = write_config(
+        dir.path(),
+        mock.addr,
+        Some(
+            r#"{"access_token":"at-stale","token_type":"Bearer","expires_in":3600,"refresh_token":"rt","issued_at":1000}"#,
+        ),
+        RETRY,
+    );
+
+    let out = ortie(dir.path(), &config, &["token", "refresh"]);
+    assert!(!out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("InvalidGrant"), "{stdout}");
+    assert_eq!(mock.served(), 1);
+}
+
+#[test]
+fn a_refresh_token_the_server_may_have_received_is_never_resent() {
+    let mock = Mock::scripted(vec![
+        None,
+        response(
+            "200 OK",
+            "",
+            r#"{"access_token":"at-fresh","token_type":"Bearer","expires_in":3600}"#,
+        ),
+    ]);
+    let dir = TempDir::new().unwrap();
+    let config = write_config(
+        dir.path(),
+        mock.addr,
+        Some(
+            r#"{"access_token":"at-stale","token_type":"Bearer","expires_in":3600,"refresh_token":"rt","issued_at":1000}"#,
+        ),
+        RETRY,
+    );
+
+    let out = ortie(dir.path(), &config, &["token", "refresh"]);
+    assert!(!out.status.success(), "{out:?}");
+    assert_eq!(mock.served(), 1);
+}
+
+#[test]
+fn a_refresh_answered_a_transient_status_is_retried() {
+    let mock = Mock::scripted(vec![
+        response("503 Service Unavailable", "", "busy"),
+        response(
+            "200 OK",
+            "",
+            r#"{"access_token":"at-fresh","token_type":"Bearer","expires_in":3600}"#,
+        ),
+    ]);
+    let dir = TempDir::new().unwrap();
+    let config = write_config(
+        dir.path(),
+        mock.addr,
+        Some(
+            r#"{"access_token":"at-stale","token_type":"Bearer","expires_in":3600,"refresh_token":"rt","issued_at":1000}"#,
+        ),
+        RETRY,
+    );
+
+    let out = ortie(dir.path(), &config, &["token", "refresh"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(mock.served(), 2);
+}
@@ -0,0 +1,92 @@
+//! Granted scopes e2e via the real binary and a local mock token
+//! endpoint granting fewer scopes than requested: a warning and the
+//! `MISSING_SCOPES` hook variable by default, a failure under
+//! `require-all-scopes`.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use common::{Mock, hook_path, ortie};
+
+/// Starts a mock token endpoint answering every refresh with a token
+/// granted the `mail` scope alone.
+fn start_mock() -> Mock {
+    Mock::always(
+        "200 OK",
+        r#"{"access_token":"at-1","token_type":"Bearer","expires_in":3600,"scope":"mail"}"#,
+    )
+}
+
+/// Writes a config bound to the mock requesting the `mail` and
+/// `calendar` scopes, with an expired token in storage and an
+/// on-refresh success hook writing `$MISSING_SCOPES` to `hook.txt`.
+/// Returns the config path.
+fn write_config(dir: &Path, mock: &Mock, lines: &str) -> PathBuf {
+    common::write_config(
+        dir,
+        mock.addr,
+        Some(
+            r#"{"access_token":"at-0","token_type":"Bearer","expires_in":3600,"refresh_token":"rt-0","issued_at":1000}"#,
+        ),
+        &format!(
+            r#"
+scopes = ["mail", "calendar"]
+{lines}
+hooks.on-refresh.success.command = "printf '%s' \"$MISSING_SCOPES\" > {h}"
+"#,
+            h = hook_path(dir).display()
+        ),
+    )
+}
+
+#[test]
+fn missing_scopes_warn_and_reach_the_hooks() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, "");
+
+    let out = ortie(dir.path(), &config, &["token", "refresh"]);
+    assert!(out.status.success(), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Warning"), "{stderr}");
+    assert!(stderr.contains("calendar"), "{stderr}");
+
+    let hook = std::fs::read_to_string(hook_path(dir.path())).unwrap();
+    assert_eq!(hook, "calendar");
+
+    // NOTE: the stored token is not warned about again.
+    let out = ortie(dir.path(), &config, &["token", "show"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "at-1");
+    assert!(out.stderr.is_empty(), "{out:?}");
+}
+
+#[test]
+fn require_all_scopes_turns_the_mismatch_into_an_error() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, "require-all-scopes = true");
+
+    let out = ortie(dir.path(), &config, &["token", "refresh"]);
+    assert_eq!(out.status.code(), Some(1), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("calendar"), "{stdout}");
+
+    // NOTE: the refused token is neither stored nor reported.
+    assert!(common::stored(dir.path()).contains("at-0"));
+    assert!(!hook_path(dir.path()).exists());
+
+    std::fs::write(
+        dir.path().join("token.json"),
+        r#"{"access_token":"at-1","token_type":"Bearer","expires_in":3600,"scope":"mail"}"#,
+    )
+    .unwrap();
+
+    let out = ortie(dir.path(), &config, &["token", "show"]);
+    assert_eq!(out.status.code(), Some(1), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("auth get --account test"), "{stdout}");
+}
@@ -0,0 +1,109 @@
+//! `stale-on-error` e2e via the real binary and a local mock token
+//! endpoint that keeps failing: `token show` falls back on a stored
+//! token with life left, and a failed refresh is not retried within
+//! the cooldown.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use common::{Mock, hook_path, now_secs, ortie};
+
+/// Starts a mock token endpoint answering every request with a 503.
+fn start_mock() -> Mock {
+    Mock::always("503 Service Unavailable", "down for maintenance")
+}
+
+/// Writes a config bound to the mock, failing fast, with a stored
+/// token expiring at `expires_at` and an on-refresh error hook
+/// writing `$ERROR` to `hook.txt`. Returns the config path.
+fn write_config(dir: &Path, mock: &Mock, expires_at: u64, lines: &str) -> PathBuf {
+    common::write_config(
+        dir,
+        mock.addr,
+        Some(&format!(
+            r#"{{"access_token":"at-stale","token_type":"Bearer","refresh_token":"rt","issued_at":1000,"expires_at":{expires_at}}}"#
+        )),
+        &format!(
+            r#"
+retry.attempts = 1
+{lines}
+hooks.on-refresh.error.command = "printf '%s' \"$ERROR\" > {h}"
+"#,
+            h = hook_path(dir).display()
+        ),
+    )
+}
+
+fn show(dir: &Path, config: &Path) -> std::process::Output {
+    ortie(dir, config, &["token", "show", "--auto-refresh"])
+}
+
+#[test]
+fn a_token_with_life_left_is_served_when_refresh_fails() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(
+        dir.path(),
+        &mock,
+        now_secs() + 30,
+        "stale-on-error = true\nrefresh-cooldown = \"1h\"",
+    );
+
+    let out = show(dir.path(), &config);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "at-stale");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Warning"), "{stderr}");
+    assert_eq!(mock.served(), 1);
+
+    let hook = std::fs::read_to_string(hook_path(dir.path())).unwrap();
+    assert_eq!(hook, "Unknown");
+
+    // NOTE: within the cooldown the failing endpoint is left alone.
+    let out = show(dir.path(), &config);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "at-stale");
+    assert_eq!(mock.served(), 1);
+}
+
+#[test]
+fn an_expired_token_is_never_served() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, now_secs() - 30, "stale-on-error = true");
+
+    let out = show(dir.path(), &config);
+    assert!(!out.status.success(), "{out:?}");
+    assert_eq!(mock.served(), 1);
+}
+
+#[test]
+fn refresh_failures_propagate_without_stale_on_error() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, now_secs() + 30, "");
+
+    let out = show(dir.path(), &config);
+    assert!(!out.status.success(), "{out:?}");
+    assert!(hook_path(dir.path()).exists());
+}
+
+#[test]
+fn local_failures_fire_no_error_hook() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(
+        dir.path(),
+        &mock,
+        now_secs() + 30,
+        "grant = \"client-credentials-jwt\"",
+    );
+
+    let out = ortie(dir.path(), &config, &["token", "refresh"]);
+    assert!(!out.status.success(), "{out:?}");
+    assert_eq!(mock.served(), 0);
+    assert!(!hook_path(dir.path()).exists());
+}
@@ -0,0 +1,156 @@
+//! Step-up authentication e2e via the real binary on the
+//! authorization code grant and a local mock token endpoint: the
+//! requested context reaches the authorization URI, and the one the
+//! ID token tells gates `token show --min-acr`, whatever the grant
+//! issued the token.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use serde_json::Value;
+use tempfile::TempDir;
+use url::Url;
+
+use common::{Mock, ortie};
+
+/// A token response carrying an opaque access token and an ID token
+/// telling the `gold` context.
+fn gold_response() -> String {
+    let header = BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#);
+    let claims = BASE64_URL_SAFE_NO_PAD.encode(r#"{"acr":"gold","auth_time":1700000000}"#);
+
+    format!(
+        r#"{{"access_token":"at-gold","token_type":"Bearer","expires_in":3600,"id_token":"{header}.{claims}.sig"}}"#
+    )
+}
+
+/// Starts a mock token endpoint answering the code exchange with the
+/// [`gold_response`].
+fn start_mock() -> Mock {
+    Mock::always("200 OK", &gold_response())
+}
+
+/// Writes an authorization code account bound to the mock, asking
+/// for the `silver` context. Returns the config path.
+fn write_config(dir: &Path, mock: &Mock, token: Option<&str>) -> PathBuf {
+    common::write_config(
+        dir,
+        mock.addr,
+        token,
+        r#"
+scopes = ["openid"]
+acr-values = ["silver"]
+endpoints.redirection = "http://127.0.0.1:8080/callback"
+"#,
+    )
+}
+
+/// Starts the flow with `args`, returning its JSON output and the
+/// authorization URI query.
+fn auth_get(dir: &Path, config: &Path, args: &[&str]) -> (Value, Vec<(String, String)>) {
+    let out = ortie(dir, config, &[&["--json", "auth", "get"], args].concat());
+    assert!(out.status.success(), "{out:?}");
+
+    let v: Value = serde_json::from_slice(&out.stdout).unwrap();
+    let uri = Url::parse(v["authorization_uri"].as_str().unwrap()).unwrap();
+    let query = uri.query_pairs().into_owned().collect();
+
+    (v, query)
+}
+
+fn param<'a>(query: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    query
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+#[test]
+fn auth_get_step_up_flags_override_the_account_acr_values() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, None);
+
+    let (_, query) = auth_get(dir.path(), &config, &[]);
+    assert_eq!(param(&query, "acr_values"), Some("silver"));
+    assert_eq!(param(&query, "max_age"), None);
+
+    let args = ["--acr-values", "gold phr", "--max-age", "0"];
+    let (_, query) = auth_get(dir.path(), &config, &args);
+    assert_eq!(param(&query, "acr_values"), Some("gold phr"));
+    assert_eq!(param(&query, "max_age"), Some("0"));
+    assert_eq!(mock.served(), 0);
+}
+
+#[test]
+fn a_stepped_up_token_passes_min_acr() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let config = write_config(dir.path(), &mock, None);
+
+    let (v, query) = auth_get(dir.path(), &config, &["--acr-values", "gold"]);
+    assert_eq!(param(&query, "acr_values"), Some("gold"));
+
+    let state = v["state"].as_str().unwrap();
+    let pkce = v["pkce_code_verifier"].as_str().unwrap();
+    let mut redirect = Url::parse("http://127.0.0.1:8080/callback").unwrap();
+    redirect
+        .query_pairs_mut()
+        .append_pair("code", "code-gold")
+        .append_pair("state", state);
+
+    let args = ["auth", "resume", redirect.as_str(), "--state", state];
+    let out = ortie(
+        dir.path(),
+        &config,
+        &[&args[..], &["--pkce", pkce]].concat(),
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(mock.served(), 1);
+
+    let out = ortie(dir.path(), &config, &["token", "show", "--min-acr", "gold"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "at-gold");
+
+    let out = ortie(dir.path(), &config, &["--json", "token", "inspect"]);
+    let report: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["acr"], "gold");
+    assert_eq!(report["auth_time"], 1700000000);
+}
+
+#[test]
+fn token_show_min_acr_refuses_a_weaker_token() {
+    let mock = start_mock();
+    let dir = TempDir::new().unwrap();
+    let token = r#"{"access_token":"at-gold","token_type":"Bearer","acr":"gold"}"#;
+    let config = write_config(dir.path(), &mock, Some(token));
+
+    let out = ortie(dir.path(), &config, &["token", "show", "--min-acr", "phr"]);
+    assert!(!out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!stdout.contains("at-gold"), "{stdout}");
+    assert!(stdout.contains("--acr-values phr"), "{stdout}");
+    assert_eq!(mock.served(), 0);
+}
+
+fn assert_gold(stored: &Value) {
+    assert_eq!(stored["acr"], "gold", "{stored}");
+    assert_eq!(stored["auth_time"], 1700000000, "{stored}");
+}
+
+#[test]
+fn the_device_grant_reads_the_context_from_the_id_token() {
+    assert_gold(&common::issue("device", &gold_response()));
+}
+
+#[test]
+fn the_ciba_grant_reads_the_context_from_the_id_token() {
+    assert_gold(&common::issue("ciba", &gold_response()));
+}
+
+#[test]
+fn the_password_grant_reads_the_context_from_the_id_token() {
+    assert_gold(&common::issue("password", &gold_response()));
+}